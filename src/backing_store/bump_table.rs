@@ -82,6 +82,21 @@ fn propagate<'a, T: Clone>(
     }
 }
 
+/// Occupancy and probe-length statistics of a `BackedRobinhoodTable`; long
+/// probe sequences indicate that the table is overloaded or that many keys
+/// hash to nearby slots.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UniqueTableStats {
+    /// the number of stored elements
+    pub num_nodes: usize,
+    /// the number of slots in the table
+    pub capacity: usize,
+    /// the longest distance of a stored element from the slot it hashes to
+    pub max_probe: usize,
+    /// the mean distance of stored elements from the slots they hash to
+    pub avg_probe: f64,
+}
+
 /// Implements a mutable vector-backed robin-hood linear probing hash table,
 /// whose keys are given by BDD pointers.
 pub struct BackedRobinhoodTable<'a, T>
@@ -97,6 +112,8 @@ where
     len: usize,
     /// # cache hits
     hits: usize,
+    /// # times the table has been resized
+    grows: usize,
 }
 
 impl<'a, T: Clone> BackedRobinhoodTable<'a, T>
@@ -105,14 +122,21 @@ where
 {
     /// reserve a robin-hood table capable of holding at least `sz` elements
     pub fn new() -> BackedRobinhoodTable<'a, T> {
-        let v: Vec<HashTableElement<T>> = vec![HashTableElement::default(); DEFAULT_SIZE];
+        Self::with_capacity(DEFAULT_SIZE)
+    }
+
+    /// reserve a robin-hood table with `cap` slots, rounded up to a power of two
+    pub fn with_capacity(cap: usize) -> BackedRobinhoodTable<'a, T> {
+        let cap = cap.max(1).next_power_of_two();
+        let v: Vec<HashTableElement<T>> = vec![HashTableElement::default(); cap];
 
         BackedRobinhoodTable {
             tbl: v,
             alloc: Bump::new(),
-            cap: DEFAULT_SIZE,
+            cap,
             len: 0,
             hits: 0,
+            grows: 0,
         }
     }
 
@@ -135,6 +159,7 @@ where
     pub fn grow(&mut self) {
         let new_sz = (self.cap + 1).next_power_of_two();
         self.cap = new_sz;
+        self.grows += 1;
         let old = mem::replace(&mut self.tbl, vec![HashTableElement::default(); new_sz]);
         let c = self.cap;
         for i in old.iter() {
@@ -156,6 +181,32 @@ where
     pub fn hits(&self) -> usize {
         self.hits
     }
+
+    /// the number of times the table has been resized since creation
+    pub fn num_grows(&self) -> usize {
+        self.grows
+    }
+
+    /// Scans the table for its current load and probe-sequence lengths
+    pub fn table_stats(&self) -> UniqueTableStats {
+        let (total, max_probe) = self
+            .tbl
+            .iter()
+            .filter(|x| x.is_occupied())
+            .fold((0, 0), |(total, max), x| {
+                (total + x.psl as usize, max.max(x.psl as usize))
+            });
+        UniqueTableStats {
+            num_nodes: self.len,
+            capacity: self.cap,
+            max_probe,
+            avg_probe: if self.len == 0 {
+                0.0
+            } else {
+                total as f64 / self.len as f64
+            },
+        }
+    }
 }
 
 impl<'a, T: Eq + Hash + Clone> UniqueTable<'a, T> for BackedRobinhoodTable<'a, T> {
@@ -253,3 +304,32 @@ impl<'a, T: Hash + Eq + Clone> Default for BackedRobinhoodTable<'a, T> {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colliding_keys_raise_max_probe() {
+        let mut tbl: BackedRobinhoodTable<u64> = BackedRobinhoodTable::with_capacity(1024);
+        let tbl_ptr = &mut tbl as *mut BackedRobinhoodTable<u64>;
+        // distinct hashes land in distinct slots
+        for i in 0..100 {
+            unsafe { (*tbl_ptr).get_or_insert_by_hash(i, i, false) };
+        }
+        let stats = tbl.table_stats();
+        assert_eq!(stats.num_nodes, 100);
+        assert_eq!(stats.max_probe, 0);
+        assert_eq!(stats.avg_probe, 0.0);
+
+        // distinct keys sharing one hash must probe past each other
+        for i in 0..50 {
+            unsafe { (*tbl_ptr).get_or_insert_by_hash(500, 1000 + i, false) };
+        }
+        let stats = tbl.table_stats();
+        assert_eq!(stats.num_nodes, 150);
+        assert_eq!(stats.capacity, 1024);
+        assert_eq!(stats.max_probe, 49);
+        assert!(stats.avg_probe > 0.0);
+    }
+}
@@ -1,9 +1,22 @@
 use crate::{
     builder::{bdd::CompiledCNF, BottomUpBuilder},
-    repr::{BddNode, BddPtr, Cnf, DDNNFPtr, PartialModel, VarLabel},
+    repr::{BddNode, BddPtr, Cnf, DDNNFPtr, Literal, PartialModel, VarLabel},
 };
 use std::{cmp::Ordering, collections::BinaryHeap};
 
+/// A binary Boolean operator that can be applied with [`BddBuilder::apply_binop`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinOp {
+    And,
+    Or,
+    Xor,
+    Nand,
+    Nor,
+    /// logical implication `f => g`
+    Imp,
+    Iff,
+}
+
 pub trait BddBuilder<'a>: BottomUpBuilder<'a, BddPtr<'a>> {
     fn less_than(&self, a: VarLabel, b: VarLabel) -> bool;
     fn has_variable(&self, bdd: BddPtr<'a>, var: VarLabel) -> bool;
@@ -14,6 +27,109 @@ pub trait BddBuilder<'a>: BottomUpBuilder<'a, BddPtr<'a>> {
 
     fn ite_helper(&'a self, f: BddPtr<'a>, g: BddPtr<'a>, h: BddPtr<'a>) -> BddPtr<'a>;
     fn cond_helper(&'a self, bdd: BddPtr<'a>, lbl: VarLabel, value: bool) -> BddPtr<'a>;
+    fn neg_helper(&'a self, f: BddPtr<'a>) -> BddPtr<'a>;
+
+    /// Apply the binary operator `op` to `f` and `g`. Every operator is
+    /// expressed as a single `ite`, so no intermediate BDDs are constructed.
+    /// ```
+    /// # use rsdd::builder::bdd::{BddBuilder, BinOp, RobddBuilder};
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, DDNNFPtr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let nand = builder.apply_binop(BinOp::Nand, a, b);
+    /// assert_eq!(nand, builder.and(a, b).neg());
+    /// ```
+    fn apply_binop(&'a self, op: BinOp, f: BddPtr<'a>, g: BddPtr<'a>) -> BddPtr<'a> {
+        let (t, e) = match op {
+            BinOp::And => (g, BddPtr::false_ptr()),
+            BinOp::Or => (BddPtr::true_ptr(), g),
+            BinOp::Xor => (g.neg(), g),
+            BinOp::Nand => (g.neg(), BddPtr::true_ptr()),
+            BinOp::Nor => (BddPtr::false_ptr(), g.neg()),
+            BinOp::Imp => (g, BddPtr::true_ptr()),
+            BinOp::Iff => (g, g.neg()),
+        };
+        self.ite(f, t, e)
+    }
+
+    /// Compute the Boolean function `f => g`
+    fn imp(&'a self, f: BddPtr<'a>, g: BddPtr<'a>) -> BddPtr<'a> {
+        self.apply_binop(BinOp::Imp, f, g)
+    }
+
+    /// Existentially quantifies out every variable in `vars` from `bdd`
+    fn exists_many(&'a self, bdd: BddPtr<'a>, vars: &[VarLabel]) -> BddPtr<'a> {
+        vars.iter().fold(bdd, |acc, v| self.exists(acc, *v))
+    }
+
+    /// Universally quantifies out the variable `lbl` from `bdd`
+    fn forall(&'a self, bdd: BddPtr<'a>, lbl: VarLabel) -> BddPtr<'a> {
+        let v1 = self.condition(bdd, lbl, true);
+        let v2 = self.condition(bdd, lbl, false);
+        self.and(v1, v2)
+    }
+
+    /// Universally quantifies out every variable in `vars` from `bdd`
+    fn forall_many(&'a self, bdd: BddPtr<'a>, vars: &[VarLabel]) -> BddPtr<'a> {
+        vars.iter().fold(bdd, |acc, v| self.forall(acc, *v))
+    }
+
+    /// Compiles the disjunction of `lits`. The empty clause is false, and a
+    /// clause containing both polarities of a variable is true.
+    /// ```
+    /// # use rsdd::builder::bdd::{BddBuilder, RobddBuilder};
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, Literal, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = Literal::new(VarLabel::new(0), true);
+    /// let b = Literal::new(VarLabel::new(1), false);
+    /// let expected = builder.or(
+    ///     builder.var(VarLabel::new(0), true),
+    ///     builder.var(VarLabel::new(1), false),
+    /// );
+    /// assert_eq!(builder.clause(&[a, b]), expected);
+    /// ```
+    fn clause(&'a self, lits: &[Literal]) -> BddPtr<'a> {
+        let mut r = BddPtr::false_ptr();
+        for lit in lits {
+            r = self.or(r, self.var(lit.label(), lit.polarity()));
+            if r.is_true() {
+                break;
+            }
+        }
+        r
+    }
+
+    /// Compiles the conjunction of `lits`. The empty cube is true, and a cube
+    /// containing both polarities of a variable is false.
+    /// ```
+    /// # use rsdd::builder::bdd::{BddBuilder, RobddBuilder};
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, Literal, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = Literal::new(VarLabel::new(0), true);
+    /// let b = Literal::new(VarLabel::new(1), false);
+    /// let expected = builder.and(
+    ///     builder.var(VarLabel::new(0), true),
+    ///     builder.var(VarLabel::new(1), false),
+    /// );
+    /// assert_eq!(builder.cube(&[a, b]), expected);
+    /// ```
+    fn cube(&'a self, lits: &[Literal]) -> BddPtr<'a> {
+        let mut r = BddPtr::true_ptr();
+        for lit in lits {
+            r = self.and(r, self.var(lit.label(), lit.polarity()));
+            if r.is_false() {
+                break;
+            }
+        }
+        r
+    }
 
     // convenience utilities
     /// disjoins a list of BDDs
@@ -111,7 +227,7 @@ where
         if polarity {
             r
         } else {
-            r.neg()
+            self.negate(r)
         }
     }
 
@@ -138,7 +254,7 @@ where
     }
 
     fn negate(&'a self, f: BddPtr<'a>) -> BddPtr<'a> {
-        f.neg()
+        self.neg_helper(f)
     }
 
     /// if f then g else h
@@ -152,7 +268,7 @@ where
     }
 
     fn xor(&'a self, f: BddPtr<'a>, g: BddPtr<'a>) -> BddPtr<'a> {
-        self.ite(f, g.neg(), g)
+        self.apply_binop(BinOp::Xor, f, g)
     }
 
     /// Existentially quantifies out the variable `lbl` from `f`
@@ -170,6 +286,21 @@ where
         r
     }
 
+    /// Compose `g` into `f` for `lbl`. Substituting a constant is just
+    /// conditioning, so that case skips the general construction.
+    fn compose(&'a self, f: BddPtr<'a>, lbl: VarLabel, g: BddPtr<'a>) -> BddPtr<'a> {
+        match g {
+            BddPtr::PtrTrue => self.condition(f, lbl, true),
+            BddPtr::PtrFalse => self.condition(f, lbl, false),
+            BddPtr::Reg(_) | BddPtr::Compl(_) => {
+                let var = self.var(lbl, true);
+                let iff = self.iff(var, g);
+                let a = self.and(iff, f);
+                self.exists(a, lbl)
+            }
+        }
+    }
+
     /// Compile a BDD from a CNF
     fn compile_cnf(&'a self, cnf: &Cnf) -> BddPtr<'a> {
         let mut cvec: Vec<BddPtr> = Vec::with_capacity(cnf.clauses().len());
@@ -213,14 +344,7 @@ where
         });
 
         for lit_vec in cnf_sorted.iter() {
-            let (vlabel, val) = (lit_vec[0].label(), lit_vec[0].polarity());
-            let mut bdd = self.var(vlabel, val);
-            for lit in lit_vec {
-                let (vlabel, val) = (lit.label(), lit.polarity());
-                let var = self.var(vlabel, val);
-                bdd = self.or(bdd, var);
-            }
-            cvec.push(bdd);
+            cvec.push(self.clause(lit_vec));
         }
         // now cvec has a list of all the clauses; collapse it down
         let r = self.collapse_clauses(&cvec);
@@ -4,10 +4,12 @@ use std::cmp::Ordering;
 mod builder;
 mod robdd;
 mod stats;
+mod wmc_session;
 
 pub use self::builder::*;
 pub use self::robdd::*;
 pub use self::stats::*;
+pub use self::wmc_session::*;
 
 // TODO: move this to a compile module
 
@@ -1,17 +1,109 @@
+use num_bigint::BigUint;
 use ordered_float::OrderedFloat;
-use rand::{rngs::ThreadRng, Rng};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 use crate::{
-    backing_store::{BackedRobinhoodTable, UniqueTable},
+    backing_store::{BackedRobinhoodTable, UniqueTable, UniqueTableStats},
     builder::{
         bdd::{BddBuilder, BddBuilderStats},
-        cache::{Ite, IteTable},
+        cache::{AllIteTable, Ite, IteTable},
         BottomUpBuilder,
     },
-    repr::{BddNode, BddPtr, DDNNFPtr, PartialModel, VarLabel, VarOrder, WmcParams},
-    util::semirings::RealSemiring,
+    repr::{
+        Aig, BddNode, BddPtr, Cnf, DDNNFPtr, Expr, Literal, PartialModel, VarLabel, VarOrder,
+        VarSet, WmcParams,
+    },
+    serialize::{BDDSerializer, SerBDDPtr},
+    util::semirings::{
+        DynFiniteField, ExpectedUtility, RationalSemiring, RealSemiring, Semiring, Tropical,
+        ViterbiSemiring,
+    },
 };
-use std::{cell::RefCell, time::{Duration, Instant}};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    io::{self, BufRead},
+    time::{Duration, Instant},
+};
+
+/// Returned when an operation ran past the builder's time limit, so its
+/// result may be truncated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutError;
+
+impl Display for TimeoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BDD builder time limit exceeded")
+    }
+}
+
+impl std::error::Error for TimeoutError {}
+
+/// Returned when conditioning on evidence whose weighted model count is zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZeroEvidenceError;
+
+impl Display for ZeroEvidenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "evidence has zero weighted model count")
+    }
+}
+
+impl std::error::Error for ZeroEvidenceError {}
+
+/// The order in which `RobddBuilder::compile_cnf_ordered` conjoins the
+/// clauses of a CNF, which can greatly affect the size of the intermediate
+/// BDDs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClauseOrder {
+    /// the order in which the clauses appear in the CNF
+    AsGiven,
+    /// shortest clauses first, keeping the CNF's order among clauses of
+    /// equal length
+    BySize,
+    /// greedily, the clause sharing the most variables with the clauses
+    /// conjoined so far; ties go to the clause introducing the fewest new
+    /// variables, then to the earliest
+    ByShared,
+}
+
+/// reorders `clauses` according to `strategy`
+fn order_clauses(clauses: &[Vec<Literal>], strategy: ClauseOrder) -> Vec<&[Literal]> {
+    let mut ordered: Vec<&[Literal]> = clauses.iter().map(|c| c.as_slice()).collect();
+    match strategy {
+        ClauseOrder::AsGiven => (),
+        ClauseOrder::BySize => ordered.sort_by_key(|c| c.len()),
+        ClauseOrder::ByShared => {
+            let mut support = VarSet::new();
+            let mut remaining = ordered;
+            ordered = Vec::with_capacity(remaining.len());
+            while !remaining.is_empty() {
+                let (best, _) = remaining
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| {
+                        let shared = c.iter().filter(|l| support.contains(l.label())).count();
+                        (i, (shared, c.len() - shared))
+                    })
+                    .min_by_key(|&(i, (shared, new))| (std::cmp::Reverse(shared), new, i))
+                    .unwrap();
+                let clause = remaining.remove(best);
+                for lit in clause {
+                    support.insert(lit.label());
+                }
+                ordered.push(clause);
+            }
+        }
+    }
+    ordered
+}
+
+/// Decides, when normalizing an `Ite` for the apply cache, whether `a` should
+/// come before `b`, given the builder's variable order; see
+/// `RobddBuilder::with_ite_normalization`
+pub type IteNormalization = for<'b> fn(BddPtr<'b>, BddPtr<'b>, &VarOrder) -> bool;
 
 pub struct RobddBuilder<'a, T: IteTable<'a, BddPtr<'a>> + Default> {
     compute_table: RefCell<BackedRobinhoodTable<'a, BddNode<'a>>>,
@@ -19,10 +111,261 @@ pub struct RobddBuilder<'a, T: IteTable<'a, BddPtr<'a>> + Default> {
     stats: RefCell<BddBuilderStats>,
     order: RefCell<VarOrder>,
     time_limit: Option<(Instant, Duration)>,
+    /// whether nodes may be referenced through complemented edges
+    complement_edges: bool,
+    /// memoized negations of nodes when complemented edges are disabled
+    neg_table: RefCell<HashMap<*const BddNode<'a>, BddPtr<'a>>>,
+    /// the source of randomness for sampling, if seeded; see `seed_rng`
+    rng: RefCell<Option<StdRng>>,
+    /// the peak node count of the builders this one was garbage-collected
+    /// from by `gc`, since the last `reset_peak`
+    peak_num_nodes: RefCell<usize>,
+    /// replaces the variable order when normalizing `Ite`s, if set
+    ite_normalization: Option<IteNormalization>,
 }
 
 type SampleCache = (Option<f64>, Option<f64>);
 
+const PROB_EQUIV_SEED: u64 = 0x5eed;
+
+type PathBoundsCache = (Option<(usize, usize)>, Option<(usize, usize)>);
+
+/// identifies a (possibly complemented) node during `all_marginals`
+type MarginalKey<'a> = (*const BddNode<'a>, bool);
+
+fn marginal_key(ptr: BddPtr) -> Option<MarginalKey> {
+    match ptr {
+        BddPtr::PtrTrue | BddPtr::PtrFalse => None,
+        BddPtr::Compl(node) | BddPtr::Reg(node) => Some((node as *const _, ptr.is_neg())),
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct Path {
+    weight: OrderedFloat<f64>,
+    decisions: Vec<(VarLabel, bool)>,
+}
+
+type TopKCache = (Option<Vec<Path>>, Option<Vec<Path>>);
+
+/// Bottom-up pass to compute the top K paths from `ptr` to the true sink; if
+/// `lowest` is set, instead computes the K lowest-weight paths with non-zero
+/// weight. If `log_space` is set, the weights in `wmc` are log-weights, which
+/// are summed along a path rather than multiplied. Paths of equal weight are
+/// ordered lexicographically by their decisions.
+fn bottom_up_top_k(
+    ptr: BddPtr,
+    k: usize,
+    wmc: &WmcParams<RealSemiring>,
+    lowest: bool,
+    log_space: bool,
+) -> Vec<Path> {
+    match ptr {
+        BddPtr::PtrTrue => vec![Path {
+            weight: OrderedFloat(if log_space { 0.0 } else { 1.0 }),
+            decisions: vec![],
+        }],
+        BddPtr::PtrFalse => vec![],
+        BddPtr::Compl(node) | BddPtr::Reg(node) => {
+            let bottomup_helper = |cached: Option<Vec<Path>>| {
+                let (l, h) = if ptr.is_neg() {
+                    (ptr.low_raw().neg(), ptr.high_raw().neg())
+                } else {
+                    (ptr.low_raw(), ptr.high_raw())
+                };
+
+                let low_paths = bottom_up_top_k(l, k, wmc, lowest, log_space);
+                let high_paths = bottom_up_top_k(h, k, wmc, lowest, log_space);
+
+                let low_weight = wmc.var_weight(node.var).0 .0;
+                let high_weight = wmc.var_weight(node.var).1 .0;
+
+                let mut true_paths = Vec::new();
+
+                let extend = |p: &mut Path, w: f64| {
+                    if log_space {
+                        p.weight += OrderedFloat(w);
+                    } else {
+                        p.weight *= OrderedFloat(w);
+                    }
+                };
+
+                true_paths.extend(low_paths.into_iter().map(|mut p| {
+                    extend(&mut p, low_weight);
+                    p.decisions.insert(0, (node.var, false));
+                    p
+                }));
+
+                true_paths.extend(high_paths.into_iter().map(|mut p| {
+                    extend(&mut p, high_weight);
+                    p.decisions.insert(0, (node.var, true));
+                    p
+                }));
+
+                if lowest {
+                    let zero = if log_space { f64::NEG_INFINITY } else { 0.0 };
+                    true_paths.retain(|p| p.weight.0 != zero);
+                    true_paths.sort_by(|a, b| {
+                        a.weight
+                            .cmp(&b.weight)
+                            .then_with(|| a.decisions.cmp(&b.decisions))
+                    });
+                } else {
+                    // break ties by the lexicographic order of the decisions
+                    // (variable label, then false before true), so the
+                    // result does not depend on traversal order
+                    true_paths.sort_by(|a, b| {
+                        b.weight
+                            .cmp(&a.weight)
+                            .then_with(|| a.decisions.cmp(&b.decisions))
+                    });
+                }
+                true_paths.truncate(k);
+
+                // println!("Top-k paths for {:?}: {:?}", node.var, true_paths);
+
+                if ptr.is_neg() {
+                    ptr.set_scratch::<TopKCache>((Some(true_paths.clone()), cached));
+                } else {
+                    ptr.set_scratch::<TopKCache>((cached, Some(true_paths.clone())));
+                }
+                true_paths
+            };
+
+            match ptr.scratch::<TopKCache>() {
+                Some((Some(l), Some(h))) => {
+                    if ptr.is_neg() {
+                        l
+                    } else {
+                        h
+                    }
+                }
+                Some((Some(v), None)) if ptr.is_neg() => v,
+                Some((None, Some(v))) if !ptr.is_neg() => v,
+                Some((None, cached)) | Some((cached, None)) => bottomup_helper(cached),
+                None => bottomup_helper(None),
+            }
+        }
+    }
+}
+
+/// Top-down pass to construct a new BDD containing exactly `paths`
+fn construct_top_k_bdd<'b, T: IteTable<'b, BddPtr<'b>> + Default>(
+    builder: &'b RobddBuilder<'b, T>,
+    paths: &[Path],
+    order: &VarOrder,
+) -> BddPtr<'b> {
+    if paths.is_empty() {
+        return BddPtr::PtrFalse;
+    }
+
+    if paths.iter().all(|p| p.decisions.is_empty()) {
+        return BddPtr::PtrTrue;
+    }
+
+    // Find the next variable to consider
+    let next_var = paths
+        .iter()
+        .flat_map(|path| path.decisions.first())
+        .min_by_key(|&&(var, _)| order.get(var))
+        .map(|&(var, _)| var)
+        .unwrap();
+
+    let (low_paths, high_paths): (Vec<_>, Vec<_>) = paths.iter().partition(|path| {
+        path.decisions
+            .first()
+            .is_none_or(|&(v, d)| v != next_var || !d)
+    });
+
+    let low_paths: Vec<_> = low_paths
+        .into_iter()
+        .map(|p| {
+            let mut new_p = p.clone();
+            if !new_p.decisions.is_empty() && new_p.decisions[0].0 == next_var {
+                new_p.decisions.remove(0);
+            }
+            new_p
+        })
+        .collect();
+
+    let high_paths: Vec<_> = high_paths
+        .into_iter()
+        .map(|p| {
+            let mut new_p = p.clone();
+            new_p.decisions.remove(0);
+            new_p
+        })
+        .collect();
+
+    let low = construct_top_k_bdd(builder, &low_paths, order);
+    let high = construct_top_k_bdd(builder, &high_paths, order);
+
+    if low == high {
+        low
+    } else {
+        let new_node = BddNode::new(next_var, low, high);
+        builder.get_or_insert(new_node)
+    }
+}
+
+/// `ln(1 - exp(x))` for `x <= 0`, accurate near both ends
+fn log1mexp(x: f64) -> f64 {
+    if x > -std::f64::consts::LN_2 {
+        (-x.exp_m1()).ln()
+    } else {
+        (-x.exp()).ln_1p()
+    }
+}
+
+/// A Gumbel-distributed sample with location `phi`
+fn sample_gumbel<R: Rng>(rng: &mut R, phi: f64) -> f64 {
+    let u: f64 = rng.gen_range(f64::MIN_POSITIVE..1.0);
+    phi - (-u.ln()).ln()
+}
+
+/// Shifts the Gumbel sample `g`, drawn alongside siblings whose maximum is
+/// `z`, so that the siblings' maximum becomes `t` (Kool et al., 2019)
+fn truncate_gumbel(g: f64, z: f64, t: f64) -> f64 {
+    let v = t - g + log1mexp(g - z);
+    t - v.max(0.0) - (-v.abs()).exp().ln_1p()
+}
+
+/// the inverse of `a` modulo `m`, if it exists
+fn mod_inverse(a: u64, m: u64) -> Option<u64> {
+    // extended Euclid
+    let (mut old_r, mut r) = (a as i128, m as i128);
+    let (mut old_s, mut s) = (1i128, 0i128);
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_s, s) = (s, old_s - q * s);
+    }
+    if old_r != 1 {
+        return None;
+    }
+    Some(old_s.rem_euclid(m as i128) as u64)
+}
+
+/// number of conjuncts below which `and_many_parallel` stops splitting and
+/// conjoins sequentially
+const PARALLEL_AND_GRAIN: usize = 4;
+
+/// Conjoins serialized BDDs as a balanced reduction tree. Each subtree is
+/// compiled in its own builder, with independent halves run in parallel.
+fn and_serialized(order: &VarOrder, bdds: &[BDDSerializer]) -> BDDSerializer {
+    if bdds.len() <= PARALLEL_AND_GRAIN {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new(order.clone(), None);
+        let r = bdds
+            .iter()
+            .flat_map(|b| builder.import_serialized(b))
+            .fold(BddPtr::PtrTrue, |acc, f| builder.and(acc, f));
+        return BDDSerializer::from_bdd(r);
+    }
+    let (l, r) = bdds.split_at(bdds.len() / 2);
+    let (l, r) = rayon::join(|| and_serialized(order, l), || and_serialized(order, r));
+    and_serialized(order, &[l, r])
+}
+
 impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> BddBuilder<'a> for RobddBuilder<'a, T> {
     fn less_than(&self, a: VarLabel, b: VarLabel) -> bool {
         self.order.borrow().lt(a, b)
@@ -45,6 +388,15 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> BddBuilder<'a> for RobddBuilder<
 
     /// Normalizes and fetches a node from the store
     fn get_or_insert(&'a self, bdd: BddNode<'a>) -> BddPtr<'a> {
+        if !self.complement_edges {
+            let bdd = BddNode::new(
+                bdd.var,
+                self.uncomplement(bdd.low),
+                self.uncomplement(bdd.high),
+            );
+            let tbl = unsafe { &mut *self.compute_table.as_ptr() };
+            return BddPtr::Reg(tbl.get_or_insert(bdd));
+        }
         unsafe {
             // TODO: Make this safe if possible
             let tbl = &mut *self.compute_table.as_ptr();
@@ -74,15 +426,18 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> BddBuilder<'a> for RobddBuilder<
             ) => self.order.borrow().lt(node_a.var, node_b.var),
         };
 
-        let ite = Ite::new(o, f, g, h);
+        let ite = match &self.ite_normalization {
+            Some(policy) => Ite::new(|a, b| policy(a, b, &self.order.borrow()), f, g, h),
+            None => Ite::new(o, f, g, h),
+        };
 
         if let Ite::IteConst(f) = ite {
-            return f;
+            return self.uncomplement(f);
         }
 
         let hash = self.apply_table.borrow().hash(&ite);
         if let Some(v) = self.apply_table.borrow().get(ite, hash) {
-            return v;
+            return self.uncomplement(v);
         }
 
         // ok the work!
@@ -97,16 +452,16 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> BddBuilder<'a> for RobddBuilder<
         let t = self.ite(fx, gx, hx);
         let f = self.ite(fxn, gxn, hxn);
 
-        if t == f {
-            self.apply_table.borrow_mut().insert(ite, t, hash);
-            return t;
-        };
-
         if self.check_time_limit() {
             // to avoid us caching this in apply_table
             return BddPtr::PtrFalse;
         }
 
+        if t == f {
+            self.apply_table.borrow_mut().insert(ite, t, hash);
+            return t;
+        };
+
         // now we have a new BDD
         let node = BddNode::new(lbl, f, t);
         let r = self.get_or_insert(node);
@@ -115,19 +470,171 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> BddBuilder<'a> for RobddBuilder<
     }
 
     fn cond_helper(&'a self, bdd: BddPtr<'a>, lbl: VarLabel, value: bool) -> BddPtr<'a> {
-        self.cond_with_alloc(bdd, lbl, value, &mut Vec::new())
+        let r = self.cond_with_alloc(bdd, lbl, value, &mut Vec::new());
+        self.uncomplement(r)
+    }
+
+    fn neg_helper(&'a self, f: BddPtr<'a>) -> BddPtr<'a> {
+        self.uncomplement(f.neg())
     }
 }
 
 impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> RobddBuilder<'a, T> {
     /// Creates a new variable manager with the specified order
     pub fn new(order: VarOrder, time_limit: Option<(Instant, Duration)>) -> RobddBuilder<'a, T> {
+        RobddBuilder::from_parts(order, None, T::default(), time_limit)
+    }
+
+    /// Creates a new variable manager whose node table is pre-sized to
+    /// `2^log2_capacity` slots, avoiding repeated rehashing when the final
+    /// size of a large compilation is roughly known in advance.
+    pub fn with_capacity(order: VarOrder, log2_capacity: usize) -> RobddBuilder<'a, T> {
+        RobddBuilder::from_parts(order, Some(1 << log2_capacity), T::default(), None)
+    }
+
+    /// Creates a new variable manager that uses `apply_table` as its apply
+    /// cache, e.g. a `BoundedIteTable` with a non-default capacity
+    pub fn with_apply_table(order: VarOrder, apply_table: T) -> RobddBuilder<'a, T> {
+        RobddBuilder::from_parts(order, None, apply_table, None)
+    }
+
+    /// the shared constructor; every other setting starts at its default
+    fn from_parts(
+        order: VarOrder,
+        table_capacity: Option<usize>,
+        apply_table: T,
+        time_limit: Option<(Instant, Duration)>,
+    ) -> RobddBuilder<'a, T> {
+        let compute_table = match table_capacity {
+            Some(cap) => BackedRobinhoodTable::with_capacity(cap),
+            None => BackedRobinhoodTable::new(),
+        };
         RobddBuilder {
-            compute_table: RefCell::new(BackedRobinhoodTable::new()),
+            compute_table: RefCell::new(compute_table),
             order: RefCell::new(order),
-            apply_table: RefCell::new(T::default()),
+            apply_table: RefCell::new(apply_table),
             stats: RefCell::new(BddBuilderStats::new()),
             time_limit,
+            complement_edges: true,
+            neg_table: RefCell::new(HashMap::new()),
+            rng: RefCell::new(None),
+            peak_num_nodes: RefCell::new(0),
+            ite_normalization: None,
+        }
+    }
+
+    /// Creates a new variable manager that never references nodes through
+    /// complemented edges: every node it returns is a `BddPtr::Reg` whose
+    /// descendants are also regular, which is simpler to export to packages
+    /// that expect plain BDDs. Negation then builds a separate copy of the
+    /// negated diagram, so diagrams may be up to twice as large.
+    ///
+    /// Builder operations keep their results in this form as long as their
+    /// inputs are; use `negate` rather than `BddPtr::neg`, which always
+    /// produces a complemented pointer.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel, VarOrder};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::without_complement_edges(
+    ///     VarOrder::linear_order(2),
+    /// );
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), false);
+    /// let nand = builder.negate(builder.and(a, b));
+    /// assert!(matches!(nand, BddPtr::Reg(_)));
+    /// assert_eq!(nand, builder.or(builder.negate(a), builder.negate(b)));
+    /// ```
+    pub fn without_complement_edges(order: VarOrder) -> RobddBuilder<'a, T> {
+        let mut builder = RobddBuilder::new(order, None);
+        builder.complement_edges = false;
+        builder
+    }
+
+    /// Creates a new variable manager whose apply cache normalizes each `Ite`
+    /// with `policy` instead of the variable order: `policy(a, b, order)`
+    /// decides whether `a` is placed before `b`. Every policy gives the same
+    /// results, since each rewrite it selects is a logical identity, but it
+    /// changes which calls share a cache entry, and so the cache hit rate.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, Cnf, VarOrder};
+    /// // place pointers with later top variables first
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::with_ite_normalization(
+    ///     VarOrder::linear_order(4),
+    ///     |a, b, order| match (a.var_safe(), b.var_safe()) {
+    ///         (Some(va), Some(vb)) => order.lt(vb, va),
+    ///         (None, _) => true,
+    ///         (_, None) => false,
+    ///     },
+    /// );
+    /// let cnf = Cnf::from_string("(1 || 2) && (-1 || 3)");
+    /// assert_eq!(builder.model_count(builder.compile_cnf(&cnf)), 8);
+    /// ```
+    pub fn with_ite_normalization(
+        order: VarOrder,
+        policy: IteNormalization,
+    ) -> RobddBuilder<'a, T> {
+        let mut builder = RobddBuilder::new(order, None);
+        builder.ite_normalization = Some(policy);
+        builder
+    }
+
+    /// Whether this builder references nodes through complemented edges; see
+    /// `without_complement_edges`
+    pub fn complement_edges(&self) -> bool {
+        self.complement_edges
+    }
+
+    /// Rewrites a complemented pointer into an explicit copy of the negated
+    /// diagram when complemented edges are disabled; otherwise returns `ptr`
+    fn uncomplement(&'a self, ptr: BddPtr<'a>) -> BddPtr<'a> {
+        if self.complement_edges {
+            return ptr;
+        }
+        match ptr {
+            BddPtr::Compl(node) => {
+                let key = node as *const BddNode<'a>;
+                if let Some(r) = self.neg_table.borrow().get(&key) {
+                    return *r;
+                }
+                let r = self.get_or_insert(BddNode::new(node.var, node.low.neg(), node.high.neg()));
+                let mut neg_table = self.neg_table.borrow_mut();
+                neg_table.insert(key, r);
+                if let BddPtr::Reg(neg_node) = r {
+                    neg_table.insert(neg_node, BddPtr::Reg(node));
+                }
+                r
+            }
+            BddPtr::Reg(_) | BddPtr::PtrTrue | BddPtr::PtrFalse => ptr,
+        }
+    }
+
+    /// Seeds the random number generator used by this builder's sampling
+    /// methods (`weighted_sample` and `sample_distinct`), making their
+    /// results reproducible. Until this is called, they draw from the
+    /// thread-local generator. Methods that take an explicit generator, such
+    /// as `gumbel_top_k`, are unaffected.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::BddPtr;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+    /// builder.seed_rng(42);
+    /// ```
+    pub fn seed_rng(&self, seed: u64) {
+        *self.rng.borrow_mut() = Some(StdRng::seed_from_u64(seed));
+    }
+
+    /// Runs `f` with the seeded generator if there is one, and the
+    /// thread-local generator otherwise
+    fn with_rng<R>(&self, f: impl FnOnce(&mut dyn RngCore) -> R) -> R {
+        match self.rng.borrow_mut().as_mut() {
+            Some(rng) => f(rng),
+            None => f(&mut rand::thread_rng()),
         }
     }
 
@@ -144,6 +651,58 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> RobddBuilder<'a, T> {
         self.time_limit = None;
     }
 
+    /// Compiles `cnf` like `compile_cnf`, but returns an error if the time
+    /// limit was exceeded. Operations that run out of time return a
+    /// placeholder false, so without this check a timed-out compilation is
+    /// indistinguishable from an unsatisfiable CNF.
+    /// ```
+    /// # use std::time::Duration;
+    /// # use rsdd::builder::bdd::{RobddBuilder, TimeoutError};
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, Cnf};
+    /// let cnf = Cnf::from_string("(1 || 2) && (-1 || 3)");
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+    /// assert!(builder.try_compile_cnf(&cnf).is_ok());
+    ///
+    /// let mut builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+    /// builder.start_time_limit(Duration::ZERO);
+    /// std::thread::sleep(Duration::from_millis(1));
+    /// assert_eq!(builder.try_compile_cnf(&cnf), Err(TimeoutError));
+    /// ```
+    pub fn try_compile_cnf(&'a self, cnf: &Cnf) -> Result<BddPtr<'a>, TimeoutError> {
+        let r = self.compile_cnf(cnf);
+        if self.check_time_limit() {
+            Err(TimeoutError)
+        } else {
+            Ok(r)
+        }
+    }
+
+    /// Compiles `cnf` by conjoining its clauses one at a time, in the order
+    /// given by `strategy`. Unlike `compile_cnf`, which combines the clauses
+    /// in a balanced tree, this keeps a single growing BDD, so the clause
+    /// order directly controls the size of the intermediate results.
+    /// ```
+    /// # use rsdd::builder::bdd::{ClauseOrder, RobddBuilder};
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, Cnf};
+    /// let cnf = Cnf::from_string("(1 || 2 || 3) && (-1) && (2 || -3)");
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+    /// let bdd = builder.compile_cnf_ordered(&cnf, ClauseOrder::BySize);
+    /// assert_eq!(bdd, builder.compile_cnf(&cnf));
+    /// ```
+    pub fn compile_cnf_ordered(&'a self, cnf: &Cnf, strategy: ClauseOrder) -> BddPtr<'a> {
+        let mut r = BddPtr::true_ptr();
+        for clause in order_clauses(cnf.clauses(), strategy) {
+            r = self.and(r, self.clause(clause));
+            if r.is_false() {
+                break;
+            }
+        }
+        r
+    }
+
     #[inline(always)]
     pub fn check_time_limit(&self) -> bool {
         if let Some((start_time, time_limit)) = self.time_limit {
@@ -158,6 +717,75 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> RobddBuilder<'a, T> {
         self.order.borrow().num_vars()
     }
 
+    /// Returns every variable in the manager, from the top of the order to
+    /// the bottom
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel, VarOrder};
+    /// let order = VarOrder::new(&[VarLabel::new(1), VarLabel::new(0)]);
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new(order, None);
+    /// assert_eq!(builder.variables(), vec![VarLabel::new(1), VarLabel::new(0)]);
+    /// ```
+    pub fn variables(&self) -> Vec<VarLabel> {
+        self.order.borrow().in_order_iter().collect()
+    }
+
+    /// Returns each variable in the manager paired with its level, from the
+    /// top of the order (level 0) to the bottom
+    pub fn levels(&self) -> Vec<(usize, VarLabel)> {
+        self.order.borrow().in_order_iter().enumerate().collect()
+    }
+
+    /// replaces the variable order and rebuilds `roots` under it, returning
+    /// them in the same order. Every other diagram in the builder is left in
+    /// the old order and must not be used afterwards. Panics if `order` does
+    /// not have as many variables as the current one.
+    #[cfg(feature = "ffi")]
+    pub(crate) fn set_order(&'a self, order: VarOrder, roots: &[BddPtr<'a>]) -> Vec<BddPtr<'a>> {
+        assert_eq!(
+            order.num_vars(),
+            self.num_vars(),
+            "set_order requires an order over the builder's variables"
+        );
+        self.order.replace(order);
+        // cached results may be out of the new order
+        self.apply_table.replace(T::default());
+        let mut remap = HashMap::new();
+        roots
+            .iter()
+            .map(|&root| self.rebuild_in_order(root, &mut remap))
+            .collect()
+    }
+
+    /// rebuilds `bdd`, which may be out of order, with `ite`s on the current
+    /// order
+    #[cfg(feature = "ffi")]
+    fn rebuild_in_order(
+        &'a self,
+        bdd: BddPtr<'a>,
+        remap: &mut HashMap<*const BddNode<'a>, BddPtr<'a>>,
+    ) -> BddPtr<'a> {
+        match bdd {
+            BddPtr::PtrTrue | BddPtr::PtrFalse => bdd,
+            BddPtr::Compl(node) => {
+                let r = self.rebuild_in_order(BddPtr::Reg(node), remap);
+                self.negate(r)
+            }
+            BddPtr::Reg(node) => {
+                let key = node as *const BddNode<'a>;
+                if let Some(r) = remap.get(&key) {
+                    return *r;
+                }
+                let low = self.rebuild_in_order(node.low, remap);
+                let high = self.rebuild_in_order(node.high, remap);
+                let r = self.ite(self.var(node.var, true), high, low);
+                remap.insert(key, r);
+                r
+            }
+        }
+    }
+
     /// Generate a new variable label which was not in the original order. Places the
     /// new variable label at the end of the current order. Returns the newly
     /// generated label.
@@ -213,6 +841,22 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> RobddBuilder<'a, T> {
         (label, ptr)
     }
 
+    /// Generate `count` new variables at the end of the current order, as with
+    /// `count` calls to `new_var`. Their labels are contiguous and they are
+    /// returned in order, each with its pointer.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let vars = builder.new_vars(3, true);
+    /// assert_eq!(vars[0].0, VarLabel::new(2));
+    /// assert_eq!(builder.num_vars(), 5);
+    /// ```
+    pub fn new_vars(&'a self, count: usize, polarity: bool) -> Vec<(VarLabel, BddPtr<'a>)> {
+        (0..count).map(|_| self.new_var(polarity)).collect()
+    }
+
     /// Use `new_var` to create a new positive pointer.
     #[inline]
     pub fn new_pos(&'a self) -> (VarLabel, BddPtr<'a>) {
@@ -230,7 +874,8 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> RobddBuilder<'a, T> {
         ptr: BddPtr<'a>,
         wmc: &WmcParams<RealSemiring>,
     ) -> (BddPtr<'a>, f64) {
-        let mut rng = rand::thread_rng();
+        #[cfg(debug_assertions)]
+        ptr.assert_scratch_cleared_recursive();
 
         fn bottomup_pass_h(ptr: BddPtr, wmc: &WmcParams<RealSemiring>) -> f64 {
             match ptr {
@@ -289,7 +934,7 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> RobddBuilder<'a, T> {
             builder: &'b RobddBuilder<'b, T>,
             ptr: BddPtr<'b>,
             wmc: &WmcParams<RealSemiring>,
-            rng: &mut ThreadRng,
+            rng: &mut dyn RngCore,
         ) -> (BddPtr<'b>, f64) {
             match ptr {
                 BddPtr::PtrTrue => (ptr, 1.0),
@@ -333,164 +978,725 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> RobddBuilder<'a, T> {
             }
         }
 
-        // let r = bottomup_pass_h(ptr, wmc);
-        let (sample, sample_probability) = sample_path(self, ptr, wmc, &mut rng);
+        // cache the root as well: `sample_path` only caches the children of
+        // each node it visits, and `clear_scratch` stops at a clean root
+        bottomup_pass_h(ptr, wmc);
+        let (sample, sample_probability) = self.with_rng(|rng| sample_path(self, ptr, wmc, rng));
         ptr.clear_scratch();
         (sample, sample_probability)
     }
 
-    /// Compute the top K accepting paths through the BDD and return a new BDD containing only those paths
-    pub fn top_k_paths(
+    /// Draws up to `n` distinct satisfying total assignments of `ptr`, each
+    /// with probability proportional to its weight among the assignments not
+    /// yet drawn: after every draw the sampled assignment is removed from the
+    /// function and the remaining weight renormalized. Each variable is drawn
+    /// top-down in proportion to the weighted mass below either value, as in
+    /// `gumbel_top_k`. Each assignment is returned with its (unnormalized)
+    /// weight, the product of its literal weights. Sampling stops early once
+    /// the remaining assignments have no weight, so if `ptr` has fewer than
+    /// `n` models of non-zero weight, all of them are returned.
+    ///
+    /// A free variable whose two weights sum to zero is left unassigned and
+    /// out of the weight, so the sample stands for both of its completions,
+    /// which are removed together.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel, WmcParams};
+    /// # use rsdd::util::semirings::RealSemiring;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let mut wmc = WmcParams::default();
+    /// wmc.set_default(RealSemiring(0.5), RealSemiring(0.5));
+    /// // `a` has two models over two variables
+    /// let samples = builder.sample_distinct(a, 5, &wmc);
+    /// assert_eq!(samples.len(), 2);
+    /// assert!(samples[0].0 != samples[1].0);
+    /// ```
+    pub fn sample_distinct(
         &'a self,
         ptr: BddPtr<'a>,
-        k: usize,
+        n: usize,
         wmc: &WmcParams<RealSemiring>,
-    ) -> BddPtr<'a> {
-        #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
-        struct Path {
-            weight: OrderedFloat<f64>,
-            decisions: Vec<(VarLabel, bool)>,
-        }
-
-        type TopKCache = (Option<Vec<Path>>, Option<Vec<Path>>);
-
-        // Bottom-up pass to compute top K paths
-        fn bottom_up_top_k<'b, T: IteTable<'b, BddPtr<'b>> + Default>(
-            builder: &'b RobddBuilder<'b, T>,
-            ptr: BddPtr<'b>,
-            k: usize,
-            wmc: &WmcParams<RealSemiring>,
-        ) -> Vec<Path> {
-            match ptr {
-                BddPtr::PtrTrue => vec![Path {
-                    weight: OrderedFloat(1.0),
-                    decisions: vec![],
-                }],
-                BddPtr::PtrFalse => vec![],
-                BddPtr::Compl(node) | BddPtr::Reg(node) => {
-                    let bottomup_helper = |cached: Option<Vec<Path>>| {
-                        let (l, h) = if ptr.is_neg() {
-                            (ptr.low_raw().neg(), ptr.high_raw().neg())
-                        } else {
-                            (ptr.low_raw(), ptr.high_raw())
-                        };
-
-                        let low_paths = bottom_up_top_k(builder, l, k, wmc);
-                        let high_paths = bottom_up_top_k(builder, h, k, wmc);
-
-                        let low_weight = wmc.var_weight(node.var).0 .0;
-                        let high_weight = wmc.var_weight(node.var).1 .0;
-
-                        let mut true_paths = Vec::new();
+    ) -> Vec<(PartialModel, f64)> {
+        let num_vars = self.num_vars();
+        // a weightless variable that the function leaves free is set aside,
+        // so its level does not scale the mass
+        let level_mass: Vec<f64> = (0..num_vars)
+            .map(|l| {
+                let (lo, hi) = wmc.var_weight(self.order.borrow().var_at_level(l));
+                if lo.0 + hi.0 <= 0.0 {
+                    1.0
+                } else {
+                    lo.0 + hi.0
+                }
+            })
+            .collect();
+        #[allow(clippy::mutable_key_type)]
+        let mut cache = HashMap::new();
+        let mut mass = |residual: BddPtr<'a>, from_level: usize| -> f64 {
+            let gap: f64 = level_mass[from_level..self.level(residual)]
+                .iter()
+                .product();
+            gap * self.level_mass_h(residual, &level_mass, wmc, &mut cache)
+        };
 
-                        true_paths.extend(low_paths.into_iter().map(|mut p| {
-                            p.weight *= OrderedFloat(low_weight);
-                            p.decisions.insert(0, (node.var, false));
-                            p
-                        }));
+        let mut remaining = ptr;
+        let mut samples = Vec::new();
+        while samples.len() < n && mass(remaining, 0) > 0.0 {
+            let mut residual = remaining;
+            let mut model = PartialModel::new(num_vars);
+            let mut weight = 1.0;
+            let mut blocking = Vec::new();
+            for level in 0..num_vars {
+                let var = self.order.borrow().var_at_level(level);
+                let (lo, hi) = wmc.var_weight(var);
+                let tested = residual.var_safe() == Some(var);
+                if !tested && lo.0 + hi.0 <= 0.0 {
+                    continue;
+                }
+                let (low, high) = if tested {
+                    (residual.low(), residual.high())
+                } else {
+                    (residual, residual)
+                };
+                let low_mass = lo.0 * mass(low, level + 1);
+                let high_mass = hi.0 * mass(high, level + 1);
+                let r: f64 = self.with_rng(|rng| rng.gen());
+                let value = r * (low_mass + high_mass) >= low_mass;
+                residual = if value { high } else { low };
+                model.set(var, value);
+                weight *= if value { hi.0 } else { lo.0 };
+                blocking.push(Literal::new(var, !value));
+            }
+            remaining = self.and(remaining, self.clause(&blocking));
+            samples.push((model, weight));
+        }
+        samples
+    }
 
-                        true_paths.extend(high_paths.into_iter().map(|mut p| {
-                            p.weight *= OrderedFloat(high_weight);
-                            p.decisions.insert(0, (node.var, true));
-                            p
-                        }));
+    /// the weighted model count of `ptr` over the variables at or below its
+    /// level, where `level_mass[l]` is the total weight of the variable at
+    /// level `l`
+    #[allow(clippy::mutable_key_type)]
+    fn level_mass_h(
+        &'a self,
+        ptr: BddPtr<'a>,
+        level_mass: &[f64],
+        wmc: &WmcParams<RealSemiring>,
+        cache: &mut HashMap<BddPtr<'a>, f64>,
+    ) -> f64 {
+        if ptr.is_const() {
+            return if ptr.is_true() { 1.0 } else { 0.0 };
+        }
+        if let Some(m) = cache.get(&ptr) {
+            return *m;
+        }
+        let level = self.level(ptr);
+        let (lo, hi) = wmc.var_weight(ptr.var_safe().unwrap());
+        let mut m = 0.0;
+        for (child, w) in [(ptr.low(), lo.0), (ptr.high(), hi.0)] {
+            let gap: f64 = level_mass[level + 1..self.level(child)].iter().product();
+            m += w * gap * self.level_mass_h(child, level_mass, wmc, cache);
+        }
+        cache.insert(ptr, m);
+        m
+    }
 
-                        true_paths.sort_by(|a, b| b.weight.cmp(&a.weight));
-                        true_paths.truncate(k);
+    /// Draws `k` distinct satisfying total assignments of `ptr` without
+    /// replacement, with probabilities proportional to their weights, via
+    /// Gumbel-top-k: conceptually every assignment's log-weight is perturbed
+    /// with independent Gumbel noise and the `k` largest are kept. Rather
+    /// than perturbing every assignment, the perturbations are sampled
+    /// top-down one variable at a time, keeping a beam of the `k` best
+    /// partial assignments (stochastic beam search, Kool et al., 2019), so
+    /// the cost is linear in `k` and the number of variables.
+    ///
+    /// Returns the assignments with their (unnormalized) weights, in
+    /// decreasing order of perturbed weight; fewer than `k` are returned if
+    /// `ptr` has fewer models of non-zero weight. With `k = 1` this is an
+    /// exact weighted sample.
+    pub fn gumbel_top_k<R: Rng>(
+        &'a self,
+        ptr: BddPtr<'a>,
+        k: usize,
+        wmc: &WmcParams<RealSemiring>,
+        rng: &mut R,
+    ) -> Vec<(PartialModel, f64)> {
+        struct Candidate<'b> {
+            residual: BddPtr<'b>,
+            model: PartialModel,
+            log_weight: f64,
+            perturbed: f64,
+        }
 
-                        // println!("Top-k paths for {:?}: {:?}", node.var, true_paths);
+        let num_vars = self.num_vars();
+        let level_mass: Vec<f64> = (0..num_vars)
+            .map(|l| {
+                let (lo, hi) = wmc.var_weight(self.order.borrow().var_at_level(l));
+                lo.0 + hi.0
+            })
+            .collect();
+        #[allow(clippy::mutable_key_type)]
+        let mut cache = HashMap::new();
+        let mut mass = |residual: BddPtr<'a>, from_level: usize| -> f64 {
+            let gap: f64 = level_mass[from_level..self.level(residual)]
+                .iter()
+                .product();
+            gap * self.level_mass_h(residual, &level_mass, wmc, &mut cache)
+        };
 
-                        if ptr.is_neg() {
-                            ptr.set_scratch::<TopKCache>((Some(true_paths.clone()), cached));
-                        } else {
-                            ptr.set_scratch::<TopKCache>((cached, Some(true_paths.clone())));
-                        }
-                        true_paths
+        let total = mass(ptr, 0);
+        if k == 0 || total == 0.0 {
+            return vec![];
+        }
+        let mut beam = vec![Candidate {
+            residual: ptr,
+            model: PartialModel::new(num_vars),
+            log_weight: 0.0,
+            perturbed: total.ln(),
+        }];
+        for level in 0..num_vars {
+            let var = self.order.borrow().var_at_level(level);
+            let (lo, hi) = wmc.var_weight(var);
+            let mut next = Vec::with_capacity(2 * beam.len());
+            for c in beam {
+                let mut children = Vec::with_capacity(2);
+                for (value, w) in [(false, lo.0), (true, hi.0)] {
+                    let residual = match c.residual.var_safe() {
+                        Some(v) if v == var && value => c.residual.high(),
+                        Some(v) if v == var => c.residual.low(),
+                        _ => c.residual,
                     };
-
-                    match ptr.scratch::<TopKCache>() {
-                        Some((Some(l), Some(h))) => {
-                            if ptr.is_neg() {
-                                l
-                            } else {
-                                h
-                            }
-                        }
-                        Some((Some(v), None)) if ptr.is_neg() => v,
-                        Some((None, Some(v))) if !ptr.is_neg() => v,
-                        Some((None, cached)) | Some((cached, None)) => bottomup_helper(cached),
-                        None => bottomup_helper(None),
+                    let m = w * mass(residual, level + 1);
+                    if m > 0.0 {
+                        let log_weight = c.log_weight + w.ln();
+                        let g = sample_gumbel(rng, c.log_weight + m.ln());
+                        children.push((value, residual, log_weight, g));
                     }
                 }
+                // condition the children's perturbations on their maximum
+                // being the parent's
+                let z = children
+                    .iter()
+                    .map(|ch| ch.3)
+                    .fold(f64::NEG_INFINITY, f64::max);
+                for (value, residual, log_weight, g) in children {
+                    let mut model = c.model.clone();
+                    model.set(var, value);
+                    next.push(Candidate {
+                        residual,
+                        model,
+                        log_weight,
+                        perturbed: truncate_gumbel(g, z, c.perturbed),
+                    });
+                }
             }
+            next.sort_by(|a, b| b.perturbed.total_cmp(&a.perturbed));
+            next.truncate(k);
+            beam = next;
         }
+        beam.into_iter()
+            .map(|c| (c.model, c.log_weight.exp()))
+            .collect()
+    }
 
-        // Top-down pass to construct new BDD with top K paths
-        fn construct_top_k_bdd<'b, T: IteTable<'b, BddPtr<'b>> + Default>(
-            builder: &'b RobddBuilder<'b, T>,
-            paths: &[Path],
-            order: &VarOrder,
-        ) -> BddPtr<'b> {
-            if paths.is_empty() {
-                return BddPtr::PtrFalse;
-            }
+    /// Conjoins `bdd` with the disjunction of the literals in `clause`; an
+    /// empty clause is false. Useful for adding constraints to an already
+    /// compiled BDD without recompiling it.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, Literal, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let clause = [Literal::new(VarLabel::new(0), false), Literal::new(VarLabel::new(1), true)];
+    /// // a /\ (!a \/ b) = a /\ b
+    /// assert_eq!(builder.conjoin_clause(a, &clause), builder.and(a, b));
+    /// ```
+    pub fn conjoin_clause(&'a self, bdd: BddPtr<'a>, clause: &[Literal]) -> BddPtr<'a> {
+        self.and(bdd, self.clause(clause))
+    }
 
-            if paths.iter().all(|p| p.decisions.is_empty()) {
-                return BddPtr::PtrTrue;
+    fn compile_expr_h(
+        &'a self,
+        e: &Expr,
+        cache: &mut HashMap<*const Expr, BddPtr<'a>>,
+    ) -> BddPtr<'a> {
+        let key = e as *const Expr;
+        if let Some(r) = cache.get(&key) {
+            return *r;
+        }
+        let r = match e {
+            Expr::True => BddPtr::PtrTrue,
+            Expr::False => BddPtr::PtrFalse,
+            Expr::Var(v) => self.var(*v, true),
+            Expr::Not(e) => {
+                let e = self.compile_expr_h(e, cache);
+                self.negate(e)
             }
+            Expr::And(l, r) => {
+                let l = self.compile_expr_h(l, cache);
+                let r = self.compile_expr_h(r, cache);
+                self.and(l, r)
+            }
+            Expr::Or(l, r) => {
+                let l = self.compile_expr_h(l, cache);
+                let r = self.compile_expr_h(r, cache);
+                self.or(l, r)
+            }
+            Expr::Xor(l, r) => {
+                let l = self.compile_expr_h(l, cache);
+                let r = self.compile_expr_h(r, cache);
+                self.xor(l, r)
+            }
+        };
+        cache.insert(key, r);
+        r
+    }
 
-            // Find the next variable to consider
-            let next_var = paths
-                .iter()
-                .flat_map(|path| path.decisions.first())
-                .min_by_key(|&&(var, _)| order.get(var))
-                .map(|&(var, _)| var)
-                .unwrap();
-
-            let (low_paths, high_paths): (Vec<_>, Vec<_>) = paths.iter().partition(|path| {
-                path.decisions
-                    .first()
-                    .map_or(true, |&(v, d)| v != next_var || !d)
-            });
-
-            let low_paths: Vec<_> = low_paths
-                .into_iter()
-                .map(|p| {
-                    let mut new_p = p.clone();
-                    if !new_p.decisions.is_empty() && new_p.decisions[0].0 == next_var {
-                        new_p.decisions.remove(0);
-                    }
-                    new_p
-                })
-                .collect();
-
-            let high_paths: Vec<_> = high_paths
-                .into_iter()
-                .map(|p| {
-                    let mut new_p = p.clone();
-                    new_p.decisions.remove(0);
-                    new_p
-                })
-                .collect();
-
-            let low = construct_top_k_bdd(builder, &low_paths, order);
-            let high = construct_top_k_bdd(builder, &high_paths, order);
+    /// Compiles a nested Boolean expression. Subexpressions shared through an
+    /// `Rc` are compiled once, memoized by pointer identity.
+    /// ```
+    /// # use std::rc::Rc;
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, Expr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = Rc::new(Expr::Var(VarLabel::new(0)));
+    /// let b = Rc::new(Expr::Var(VarLabel::new(1)));
+    /// let e = Expr::And(a, Rc::new(Expr::Not(b)));
+    /// let expected = builder.and(
+    ///     builder.var(VarLabel::new(0), true),
+    ///     builder.var(VarLabel::new(1), false),
+    /// );
+    /// assert!(builder.compile_expr(&e) == expected);
+    /// ```
+    pub fn compile_expr(&'a self, e: &Expr) -> BddPtr<'a> {
+        self.compile_expr_h(e, &mut HashMap::new())
+    }
 
-            if low == high {
-                low
+    /// Compiles each output of an and-inverter graph, returning them by
+    /// name. The `k`th input becomes `VarLabel(k)`, and variables are added
+    /// to the end of the order if the builder has fewer than there are
+    /// inputs. Gates are compiled in topological order, each into one `and`
+    /// of its (possibly negated) operands.
+    pub fn compile_aig(&'a self, aig: &Aig) -> HashMap<String, BddPtr<'a>> {
+        while self.num_vars() < aig.inputs().len() {
+            self.new_label();
+        }
+        let mut vars: HashMap<u64, BddPtr<'a>> = HashMap::new();
+        for (k, lit) in aig.inputs().iter().enumerate() {
+            vars.insert(lit / 2, self.var(VarLabel::new_usize(k), true));
+        }
+        let lit_bdd = |vars: &HashMap<u64, BddPtr<'a>>, lit: u64| {
+            let v = if lit / 2 == 0 {
+                BddPtr::false_ptr()
+            } else {
+                vars[&(lit / 2)]
+            };
+            if lit % 2 == 1 {
+                self.negate(v)
             } else {
-                let new_node = BddNode::new(next_var, low, high);
-                builder.get_or_insert(new_node)
+                v
             }
+        };
+        for &(lhs, r0, r1) in aig.ands() {
+            let r = self.and(lit_bdd(&vars, r0), lit_bdd(&vars, r1));
+            vars.insert(lhs / 2, r);
         }
+        aig.outputs()
+            .iter()
+            .map(|(name, lit)| (name.clone(), lit_bdd(&vars, *lit)))
+            .collect()
+    }
 
-        let top_k_paths = bottom_up_top_k(self, ptr, k, wmc);
-        let result: BddPtr<'a> = construct_top_k_bdd(self, &top_k_paths, self.order());
-        ptr.clear_scratch();
-        result
+    /// Reads an ASCII AIGER (`aag`) circuit from `reader` (see
+    /// `Aig::from_aag_reader`) and compiles its outputs (see `compile_aig`).
+    /// ```
+    /// # use std::io::Cursor;
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(0);
+    /// let aag = "aag 3 2 0 1 1\n2\n4\n7\n6 2 4\no0 nand\n";
+    /// let outputs = builder.from_aag(Cursor::new(aag)).unwrap();
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// assert_eq!(outputs["nand"], builder.negate(builder.and(a, b)));
+    /// ```
+    pub fn from_aag<R: BufRead>(&'a self, reader: R) -> io::Result<HashMap<String, BddPtr<'a>>> {
+        let aig = Aig::from_aag_reader(reader)?;
+        Ok(self.compile_aig(&aig))
     }
 
-    /// Get the current variable order
+    /// Returns the set of variables labeling some node reachable from `ptr`.
+    ///
+    /// A variable can appear structurally without the function depending on
+    /// it (e.g., in a node whose branches are equal); see `essential_support`.
+    ///
+    /// Pre-condition: scratch cleared
+    pub fn support(&'a self, ptr: BddPtr<'a>) -> VarSet {
+        debug_assert!(ptr.is_scratch_cleared());
+        fn support_h(ptr: BddPtr, vars: &mut VarSet) {
+            if ptr.is_const() || ptr.scratch::<usize>().is_some() {
+                return;
+            }
+            vars.insert(ptr.var_safe().unwrap());
+            ptr.set_scratch::<usize>(0);
+            support_h(ptr.low_raw(), vars);
+            support_h(ptr.high_raw(), vars);
+        }
+        let mut vars = VarSet::new_with_num_vars(self.num_vars());
+        support_h(ptr, &mut vars);
+        ptr.clear_scratch();
+        vars
+    }
+
+    /// Returns the variables in `support(ptr)` that `ptr` actually depends
+    /// on, i.e. those `v` for which `exists(ptr, v) != forall(ptr, v)`.
+    ///
+    /// Pre-condition: scratch cleared
+    pub fn essential_support(&'a self, ptr: BddPtr<'a>) -> VarSet {
+        let mut vars = VarSet::new_with_num_vars(self.num_vars());
+        for v in self.support(ptr).iter() {
+            if self.exists(ptr, v) != self.forall(ptr, v) {
+                vars.insert(v);
+            }
+        }
+        vars
+    }
+
+    #[allow(clippy::mutable_key_type)]
+    fn prime_implicants_h(
+        &'a self,
+        ptr: BddPtr<'a>,
+        cache: &mut HashMap<BddPtr<'a>, Vec<Vec<Literal>>>,
+    ) -> Vec<Vec<Literal>> {
+        if ptr.is_true() {
+            return vec![vec![]];
+        }
+        if ptr.is_false() {
+            return vec![];
+        }
+        if let Some(r) = cache.get(&ptr) {
+            return r.clone();
+        }
+        let var = ptr.var_safe().unwrap();
+        let (f0, f1) = (ptr.low(), ptr.high());
+        let mut r = self.prime_implicants_h(self.and(f0, f1), cache);
+        let common = r.len();
+        // primes of f0 /\ f1 need no literal on `var`; the remaining primes of
+        // each cofactor are prime for `f` once extended with that literal
+        for (cofactor, polarity) in [(f0, false), (f1, true)] {
+            for p in self.prime_implicants_h(cofactor, cache) {
+                if !r[..common].contains(&p) {
+                    let mut cube = vec![Literal::new(var, polarity)];
+                    cube.extend(p);
+                    r.push(cube);
+                }
+            }
+        }
+        cache.insert(ptr, r.clone());
+        r
+    }
+
+    /// Computes the prime implicants of `ptr`: the minimal cubes (conjunctions
+    /// of literals) that imply it. Each cube lists its literals in variable
+    /// order. Uses the recursion
+    /// `PI(f) = PI(f0 /\ f1) + !x PI(f0) + x PI(f1)`, dropping from each
+    /// cofactor's primes those already prime for `f0 /\ f1`. The number of
+    /// primes can be exponential, so this is intended for small functions.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, Literal, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let primes = builder.prime_implicants(builder.or(a, b));
+    /// assert_eq!(
+    ///     primes,
+    ///     vec![
+    ///         vec![Literal::new(VarLabel::new(0), true)],
+    ///         vec![Literal::new(VarLabel::new(1), true)],
+    ///     ]
+    /// );
+    /// ```
+    pub fn prime_implicants(&'a self, ptr: BddPtr<'a>) -> Vec<Vec<Literal>> {
+        let mut r = self.prime_implicants_h(ptr, &mut HashMap::new());
+        r.sort();
+        r
+    }
+
+    /// Finds a satisfying assignment of `ptr` by descending from the root to
+    /// the true terminal, or returns `None` if `ptr` is unsatisfiable.
+    /// Variables skipped along the chosen path are don't-cares and are left
+    /// unset in the returned model.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), false);
+    /// let m = builder.find_model(a).unwrap();
+    /// assert_eq!(m.get(VarLabel::new(0)), Some(false));
+    /// assert_eq!(m.get(VarLabel::new(1)), None);
+    /// assert!(builder.find_model(BddPtr::PtrFalse).is_none());
+    /// ```
+    pub fn find_model(&'a self, ptr: BddPtr<'a>) -> Option<PartialModel> {
+        if ptr.is_false() {
+            return None;
+        }
+        let mut model = PartialModel::new(self.num_vars());
+        let mut cur = ptr;
+        // in a reduced BDD every node other than false is satisfiable, so
+        // either branch that isn't false leads to the true terminal
+        while let Some(var) = cur.var_safe() {
+            let high = cur.high();
+            if high.is_false() {
+                model.set(var, false);
+                cur = cur.low();
+            } else {
+                model.set(var, true);
+                cur = high;
+            }
+        }
+        Some(model)
+    }
+
+    /// Whether `a` entails `b`, i.e. every model of `a` is a model of `b`.
+    /// This holds exactly when `and(a, b.neg())` is false, but rather than
+    /// building that BDD, both diagrams are cofactored together and the
+    /// search stops at the first pair of cofactors that disagrees.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// assert!(builder.implies(builder.and(a, b), a));
+    /// assert!(!builder.implies(a, builder.and(a, b)));
+    /// ```
+    #[allow(clippy::mutable_key_type)]
+    pub fn implies(&'a self, a: BddPtr<'a>, b: BddPtr<'a>) -> bool {
+        fn implies_h<'a, T: IteTable<'a, BddPtr<'a>> + Default>(
+            builder: &RobddBuilder<'a, T>,
+            a: BddPtr<'a>,
+            b: BddPtr<'a>,
+            known: &mut HashSet<(BddPtr<'a>, BddPtr<'a>)>,
+        ) -> bool {
+            if a.is_false() || b.is_true() || a == b {
+                return true;
+            }
+            if a.is_true() || b.is_false() || a == b.neg() {
+                // `a` is satisfiable, and here it contradicts `b`
+                return false;
+            }
+            if known.contains(&(a, b)) {
+                return true;
+            }
+            // split on whichever top variable comes first in the order
+            let var = match (a.var_safe(), b.var_safe()) {
+                (Some(va), Some(vb)) if builder.less_than(vb, va) => vb,
+                (Some(va), _) => va,
+                (None, vb) => vb.unwrap(),
+            };
+            let cofactor = |f: BddPtr<'a>, value| match f.var_safe() {
+                Some(v) if v == var && value => f.cofactor_true(),
+                Some(v) if v == var => f.cofactor_false(),
+                _ => f,
+            };
+            let r = implies_h(builder, cofactor(a, true), cofactor(b, true), known)
+                && implies_h(builder, cofactor(a, false), cofactor(b, false), known);
+            if r {
+                known.insert((a, b));
+            }
+            r
+        }
+        implies_h(self, a, b, &mut HashSet::new())
+    }
+
+    /// Whether `a` and `b` represent the same Boolean function, checked by
+    /// testing that `xor(a, b)` is false rather than by comparing pointers.
+    /// Diagrams built by this builder's operations are canonical, so for them
+    /// this agrees with `==`; it differs for pointers whose representation
+    /// was changed outside the builder, e.g. a `BddPtr::neg` taken in a
+    /// builder created with `without_complement_edges`.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, DDNNFPtr, VarLabel, VarOrder};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::without_complement_edges(
+    ///     VarOrder::linear_order(2),
+    /// );
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let f = builder.and(a, b);
+    /// // a complemented edge, versus the builder's explicit negated copy
+    /// assert!(f.neg() != builder.negate(f));
+    /// assert!(builder.semantically_eq(f.neg(), builder.negate(f)));
+    /// ```
+    pub fn semantically_eq(&'a self, a: BddPtr<'a>, b: BddPtr<'a>) -> bool {
+        a == b || self.xor(a, b).is_false()
+    }
+
+    /// A fast, necessary condition for `a` and `b` being equal: evaluates both
+    /// on `trials` random total assignments and returns false if they ever
+    /// disagree, and true otherwise. The assignments are drawn from a fixed
+    /// seed, so results are reproducible.
+    pub fn prob_equiv(&'a self, a: BddPtr<'a>, b: BddPtr<'a>, trials: usize) -> bool {
+        let mut rng = ChaCha8Rng::seed_from_u64(PROB_EQUIV_SEED);
+        let mut assgn = vec![false; self.num_vars()];
+        (0..trials).all(|_| {
+            assgn.iter_mut().for_each(|v| *v = rng.gen());
+            a.evaluate(&assgn) == b.evaluate(&assgn)
+        })
+    }
+
+    /// Rebuilds a serialized BDD in this builder, returning its roots. The
+    /// serialized nodes must use the same variable order as this builder.
+    fn import_serialized(&'a self, ser: &BDDSerializer) -> Vec<BddPtr<'a>> {
+        fn resolve<'b>(nodes: &[BddPtr<'b>], ptr: &SerBDDPtr) -> BddPtr<'b> {
+            match ptr {
+                SerBDDPtr::True => BddPtr::PtrTrue,
+                SerBDDPtr::False => BddPtr::PtrFalse,
+                SerBDDPtr::Ptr { index, compl } => {
+                    if *compl {
+                        nodes[*index].neg()
+                    } else {
+                        nodes[*index]
+                    }
+                }
+            }
+        }
+        let mut nodes: Vec<BddPtr<'a>> = Vec::with_capacity(ser.nodes.len());
+        for node in ser.nodes.iter() {
+            let low = resolve(&nodes, &node.low);
+            let high = resolve(&nodes, &node.high);
+            let var = VarLabel::new_usize(node.topvar);
+            nodes.push(self.get_or_insert(BddNode::new(var, low, high)));
+        }
+        ser.roots
+            .iter()
+            .map(|r| self.uncomplement(resolve(&nodes, r)))
+            .collect()
+    }
+
+    /// Conjoins all of `ptrs` using a balanced reduction tree whose
+    /// independent subtrees are compiled in parallel.
+    ///
+    /// The shared tables are not thread-safe, so each subtree is serialized,
+    /// compiled in a fresh thread-local builder with the same variable order,
+    /// and only the final result is replayed into this builder. This pays off
+    /// when the conjuncts are large and the intermediate results are expensive
+    /// to compute; for small inputs, prefer folding with `and`.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(8);
+    /// let vars: Vec<BddPtr> = (0..8)
+    ///     .map(|v| builder.var(VarLabel::new(v), true))
+    ///     .collect();
+    /// let seq = vars.iter().fold(BddPtr::PtrTrue, |acc, v| builder.and(acc, *v));
+    /// assert!(builder.and_many_parallel(vars) == seq);
+    /// ```
+    pub fn and_many_parallel(&'a self, ptrs: Vec<BddPtr<'a>>) -> BddPtr<'a> {
+        let order = self.order.borrow().clone();
+        let ser: Vec<BDDSerializer> = ptrs.into_iter().map(BDDSerializer::from_bdd).collect();
+        let r = and_serialized(&order, &ser);
+        self.import_serialized(&r)[0]
+    }
+
+    /// Compute the top K accepting paths through the BDD and return a new BDD containing only those paths
+    pub fn top_k_paths(
+        &'a self,
+        ptr: BddPtr<'a>,
+        k: usize,
+        wmc: &WmcParams<RealSemiring>,
+    ) -> BddPtr<'a> {
+        #[cfg(debug_assertions)]
+        ptr.assert_scratch_cleared_recursive();
+        let top_k_paths = bottom_up_top_k(ptr, k, wmc, false, false);
+        let result: BddPtr<'a> = construct_top_k_bdd(self, &top_k_paths, self.order());
+        ptr.clear_scratch();
+        result
+    }
+
+    /// Like `top_k_paths`, but the weights in `wmc` are natural-log weights:
+    /// a path's score is the sum of its literals' log-weights, so weights
+    /// may be negative (and `-inf` stands for a zero weight).
+    pub fn top_k_paths_log(
+        &'a self,
+        ptr: BddPtr<'a>,
+        k: usize,
+        log_wmc: &WmcParams<RealSemiring>,
+    ) -> BddPtr<'a> {
+        #[cfg(debug_assertions)]
+        ptr.assert_scratch_cleared_recursive();
+        let top_k_paths = bottom_up_top_k(ptr, k, log_wmc, false, true);
+        let result: BddPtr<'a> = construct_top_k_bdd(self, &top_k_paths, self.order());
+        ptr.clear_scratch();
+        result
+    }
+
+    /// Compute the K lowest-weight accepting paths through the BDD and return a
+    /// new BDD containing only those paths. Paths with zero weight are skipped,
+    /// so the result contains only satisfiable-but-unlikely paths.
+    pub fn bottom_k_paths(
+        &'a self,
+        ptr: BddPtr<'a>,
+        k: usize,
+        wmc: &WmcParams<RealSemiring>,
+    ) -> BddPtr<'a> {
+        #[cfg(debug_assertions)]
+        ptr.assert_scratch_cleared_recursive();
+        let bottom_k_paths = bottom_up_top_k(ptr, k, wmc, true, false);
+        let result: BddPtr<'a> = construct_top_k_bdd(self, &bottom_k_paths, self.order());
+        ptr.clear_scratch();
+        result
+    }
+
+    /// Compute the top K accepting paths through the BDD, returning each path
+    /// as a partial model (the decisions along the path) with its weight, in
+    /// non-increasing order of weight. Paths of equal weight are ordered
+    /// lexicographically by their decisions, compared as (variable label,
+    /// value) pairs from the root down with false before true; this also
+    /// decides which tied paths are kept at the cutoff. Unlike `top_k_paths`,
+    /// this does not construct a new BDD.
+    pub fn top_k_models(
+        &'a self,
+        ptr: BddPtr<'a>,
+        k: usize,
+        wmc: &WmcParams<RealSemiring>,
+    ) -> Vec<(PartialModel, f64)> {
+        let paths = bottom_up_top_k(ptr, k, wmc, false, false);
+        ptr.clear_scratch();
+        paths
+            .into_iter()
+            .map(|p| {
+                let mut model = PartialModel::new(self.num_vars());
+                for (var, value) in p.decisions {
+                    model.set(var, value);
+                }
+                (model, p.weight.0)
+            })
+            .collect()
+    }
+
+    /// Get the current variable order
     #[inline]
     pub fn order(&self) -> &VarOrder {
         // TODO fix this, it doesn't need to be unsafe
@@ -507,7 +1713,7 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> RobddBuilder<'a, T> {
                 }
                 let r = if v { f.high_raw() } else { f.low_raw() };
                 if f.is_neg() {
-                    r.neg()
+                    self.negate(r)
                 } else {
                     r
                 }
@@ -533,7 +1739,7 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> RobddBuilder<'a, T> {
 
                 if node.var == lbl {
                     let r = if value { bdd.high_raw() } else { bdd.low_raw() };
-                    return if bdd.is_neg() { r.neg() } else { r };
+                    return if bdd.is_neg() { self.negate(r) } else { r };
                 }
 
                 // check cache
@@ -541,7 +1747,7 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> RobddBuilder<'a, T> {
                     None => (),
                     Some(v) => {
                         return if bdd.is_neg() {
-                            alloc[v].neg()
+                            self.negate(alloc[v])
                         } else {
                             alloc[v]
                         }
@@ -552,20 +1758,19 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> RobddBuilder<'a, T> {
                 let l = self.cond_with_alloc(bdd.low_raw(), lbl, value, alloc);
                 let h = self.cond_with_alloc(bdd.high_raw(), lbl, value, alloc);
 
-                if l == h {
+                let res = if l == h {
                     // reduce the BDD -- two children identical
                     if bdd.is_neg() {
-                        return l.neg();
+                        self.negate(l)
                     } else {
-                        return l;
-                    };
-                };
-                let res = if l != bdd.low_raw() || h != bdd.high_raw() {
+                        l
+                    }
+                } else if l != bdd.low_raw() || h != bdd.high_raw() {
                     // cache and return the new BDD
                     let new_bdd = BddNode::new(node.var, l, h);
                     let r = self.get_or_insert(new_bdd);
                     if bdd.is_neg() {
-                        r.neg()
+                        self.negate(r)
                     } else {
                         r
                     }
@@ -575,7 +1780,7 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> RobddBuilder<'a, T> {
                 };
 
                 let idx = if bdd.is_neg() {
-                    alloc.push(res.neg());
+                    alloc.push(self.negate(res));
                     alloc.len() - 1
                 } else {
                     alloc.push(res);
@@ -601,377 +1806,4543 @@ impl<'a, T: IteTable<'a, BddPtr<'a>> + Default> RobddBuilder<'a, T> {
     ///
     /// Pre-condition: scratch cleared
     pub fn condition_model(&'a self, bdd: BddPtr<'a>, m: &PartialModel) -> BddPtr<'a> {
-        debug_assert!(bdd.is_scratch_cleared());
+        #[cfg(debug_assertions)]
+        bdd.assert_scratch_cleared_recursive();
         let r = self.cond_model_h(bdd, m);
         bdd.clear_scratch();
         r
     }
 
-    /// Prints the total number of recursive calls executed so far by the RobddBuilder
-    /// This is a stable way to track performance
-    pub fn num_recursive_calls(&self) -> usize {
-        self.stats.borrow().num_recursive_calls
-    }
-
-    fn smooth_helper(&'a self, bdd: BddPtr<'a>, current: usize, total: usize) -> BddPtr<'a> {
-        debug_assert!(current <= total);
-        if current >= total {
-            return bdd;
+    /// Enumerates every total assignment that extends `partial` and satisfies
+    /// `ptr`, i.e. the models of `ptr | partial` over the builder's remaining
+    /// variables, each returned together with the assignments of `partial`.
+    /// Models are listed in lexicographic order of the variable order, with
+    /// false before true. Returns an empty vector if no completion exists.
+    ///
+    /// The number of completions is exponential in the number of free
+    /// variables, so this is only practical when few variables remain.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, PartialModel, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let a_or_b = builder.or(a, b);
+    /// // with a = false, b must be true and c is free
+    /// let partial = PartialModel::from_assignments(&[Some(false), None, None]);
+    /// let completions = builder.completions(a_or_b, &partial);
+    /// assert_eq!(completions.len(), 2);
+    /// assert!(completions.iter().all(|m| m.get(VarLabel::new(1)) == Some(true)));
+    /// ```
+    pub fn completions(&'a self, ptr: BddPtr<'a>, partial: &PartialModel) -> Vec<PartialModel> {
+        fn completions_h<'a>(
+            bdd: BddPtr<'a>,
+            free: &[VarLabel],
+            model: &mut PartialModel,
+            out: &mut Vec<PartialModel>,
+        ) {
+            if bdd.is_false() {
+                return;
+            }
+            let Some((&var, rest)) = free.split_first() else {
+                out.push(model.clone());
+                return;
+            };
+            for value in [false, true] {
+                let next = match bdd.var_safe() {
+                    Some(v) if v == var && value => bdd.cofactor_true(),
+                    Some(v) if v == var => bdd.cofactor_false(),
+                    _ => bdd,
+                };
+                model.set(var, value);
+                completions_h(next, rest, model, out);
+            }
+            model.unset(var);
         }
 
+        let conditioned = self.condition_model(ptr, partial);
+        let free: Vec<VarLabel> = self
+            .order
+            .borrow()
+            .in_order_iter()
+            .filter(|v| !partial.is_set(*v))
+            .collect();
+        let mut model = partial.clone();
+        let mut out = Vec::new();
+        completions_h(conditioned, &free, &mut model, &mut out);
+        out
+    }
+
+    fn restrict_with_alloc(
+        &'a self,
+        bdd: BddPtr<'a>,
+        cube: &PartialModel,
+        last_level: usize,
+        alloc: &mut Vec<BddPtr<'a>>,
+    ) -> BddPtr<'a> {
+        self.stats.borrow_mut().num_recursive_calls += 1;
         match bdd {
-            BddPtr::Reg(node) => {
-                let smoothed_node = BddNode::new(
-                    node.var,
-                    self.smooth_helper(node.low, current + 1, total),
-                    self.smooth_helper(node.high, current + 1, total),
-                );
-                self.get_or_insert(smoothed_node)
-            }
-            BddPtr::Compl(node) => self.smooth_helper(BddPtr::Reg(node), current, total).neg(),
-            BddPtr::PtrTrue | BddPtr::PtrFalse => {
-                let var = self.order.borrow().var_at_level(current);
-                let smoothed_node = BddNode::new(
-                    var,
-                    self.smooth_helper(bdd, current + 1, total),
-                    self.smooth_helper(bdd, current + 1, total),
-                );
-                self.get_or_insert(smoothed_node)
+            BddPtr::PtrTrue | BddPtr::PtrFalse => bdd,
+            BddPtr::Reg(node) | BddPtr::Compl(node) => {
+                if self.order.borrow().get(node.var) > last_level {
+                    // we passed every variable in the cube, nothing left to do
+                    return bdd;
+                }
+
+                // check cache
+                if let Some(v) = bdd.scratch::<usize>() {
+                    return if bdd.is_neg() {
+                        self.negate(alloc[v])
+                    } else {
+                        alloc[v]
+                    };
+                }
+
+                // compute the result for the regular (non-complemented) node
+                let res = match cube.get(node.var) {
+                    Some(value) => {
+                        let r = if value { node.high } else { node.low };
+                        self.restrict_with_alloc(r, cube, last_level, alloc)
+                    }
+                    None => {
+                        let l = self.restrict_with_alloc(node.low, cube, last_level, alloc);
+                        let h = self.restrict_with_alloc(node.high, cube, last_level, alloc);
+                        if l == h {
+                            l
+                        } else if l != node.low || h != node.high {
+                            self.get_or_insert(BddNode::new(node.var, l, h))
+                        } else {
+                            BddPtr::Reg(node)
+                        }
+                    }
+                };
+
+                alloc.push(res);
+                bdd.set_scratch(alloc.len() - 1);
+                if bdd.is_neg() {
+                    self.negate(res)
+                } else {
+                    res
+                }
             }
         }
     }
 
-    /// Return a smoothed version of the input BDD. Requires:
-    /// - BDD is an ROBDD, i.e. each variable only appears once per path
-    /// - variable ordering respects the builder's order
-    pub fn smooth(&'a self, bdd: BddPtr<'a>, num_vars: usize) -> BddPtr<'a> {
-        // TODO: this num_vars should be tied to the specific BDD, not the manager
-        self.smooth_helper(bdd, 0, num_vars)
+    /// Compute the Boolean function `f | cube`, where `cube` is a (partial)
+    /// assignment to variables. Unlike `condition_model`, this applies every
+    /// assignment in a single traversal of `bdd`.
+    ///
+    /// Pre-condition: scratch cleared
+    pub fn restrict(&'a self, bdd: BddPtr<'a>, cube: &PartialModel) -> BddPtr<'a> {
+        debug_assert!(bdd.is_scratch_cleared());
+        let last_level = {
+            let order = self.order.borrow();
+            cube.assignment_iter()
+                .filter(|lit| lit.label().value_usize() < order.num_vars())
+                .map(|lit| order.get(lit.label()))
+                .max()
+        };
+        let last_level = match last_level {
+            Some(l) => l,
+            None => return bdd,
+        };
+        let r = self.restrict_with_alloc(bdd, cube, last_level, &mut Vec::new());
+        bdd.clear_scratch();
+        r
     }
 
-    pub fn stats(&'a self) -> BddBuilderStats {
-        BddBuilderStats {
-            num_recursive_calls: self.stats.borrow().num_recursive_calls,
+    fn rename_with_alloc(
+        &'a self,
+        bdd: BddPtr<'a>,
+        map: &HashMap<VarLabel, VarLabel>,
+        alloc: &mut Vec<BddPtr<'a>>,
+    ) -> BddPtr<'a> {
+        self.stats.borrow_mut().num_recursive_calls += 1;
+        match bdd {
+            BddPtr::PtrTrue | BddPtr::PtrFalse => bdd,
+            BddPtr::Reg(node) | BddPtr::Compl(node) => {
+                // check cache
+                if let Some(v) = bdd.scratch::<usize>() {
+                    return if bdd.is_neg() {
+                        self.negate(alloc[v])
+                    } else {
+                        alloc[v]
+                    };
+                }
+
+                // compute the result for the regular (non-complemented) node
+                let l = self.rename_with_alloc(node.low, map, alloc);
+                let h = self.rename_with_alloc(node.high, map, alloc);
+                let var = *map.get(&node.var).unwrap_or(&node.var);
+                let above = |child: BddPtr<'a>| match child.var_safe() {
+                    None => true,
+                    Some(v) => self.less_than(var, v),
+                };
+                let res = if l == h {
+                    l
+                } else if above(l) && above(h) {
+                    // the renaming preserved the order here, so we can build
+                    // the node directly
+                    self.get_or_insert(BddNode::new(var, l, h))
+                } else {
+                    let v = self.var(var, true);
+                    self.ite(v, h, l)
+                };
+
+                alloc.push(res);
+                bdd.set_scratch(alloc.len() - 1);
+                if bdd.is_neg() {
+                    self.negate(res)
+                } else {
+                    res
+                }
+            }
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
+    /// Simultaneously substitutes every variable `v` in `bdd` with `map[v]`;
+    /// variables not in `map` are left unchanged. Mappings that preserve the
+    /// variable order are applied by relabeling nodes directly; otherwise, the
+    /// affected nodes are rebuilt with `ite`.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// # use std::collections::HashMap;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let map = HashMap::from([(VarLabel::new(0), VarLabel::new(1))]);
+    /// assert_eq!(builder.rename_vars(a, &map), b);
+    /// ```
+    pub fn rename_vars(&'a self, bdd: BddPtr<'a>, map: &HashMap<VarLabel, VarLabel>) -> BddPtr<'a> {
+        debug_assert!(bdd.is_scratch_cleared());
+        let r = self.rename_with_alloc(bdd, map, &mut Vec::new());
+        bdd.clear_scratch();
+        r
+    }
 
-    use crate::builder::BottomUpBuilder;
-    use crate::repr::WmcParams;
-    use crate::util::semirings::{FiniteField, RealSemiring};
-    use crate::{builder::cache::AllIteTable, repr::DDNNFPtr};
+    fn compose_with_alloc(
+        &'a self,
+        bdd: BddPtr<'a>,
+        subs: &HashMap<VarLabel, BddPtr<'a>>,
+        alloc: &mut Vec<BddPtr<'a>>,
+    ) -> BddPtr<'a> {
+        self.stats.borrow_mut().num_recursive_calls += 1;
+        match bdd {
+            BddPtr::PtrTrue | BddPtr::PtrFalse => bdd,
+            BddPtr::Reg(node) | BddPtr::Compl(node) => {
+                // check cache
+                if let Some(v) = bdd.scratch::<usize>() {
+                    return if bdd.is_neg() {
+                        self.negate(alloc[v])
+                    } else {
+                        alloc[v]
+                    };
+                }
 
-    use crate::{
-        builder::bdd::robdd::RobddBuilder,
-        repr::{BddPtr, Cnf, VarLabel},
-    };
+                // compute the result for the regular (non-complemented) node;
+                // the children are substituted before `node.var`, so no
+                // substituted function is itself substituted into
+                let l = self.compose_with_alloc(node.low, subs, alloc);
+                let h = self.compose_with_alloc(node.high, subs, alloc);
+                let g = match subs.get(&node.var) {
+                    Some(g) => *g,
+                    None => self.var(node.var, true),
+                };
+                let res = self.ite(g, h, l);
 
-    // check that (a \/ b) /\ a === a
-    #[test]
-    fn simple_equality() {
-        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
-        let v1 = builder.var(VarLabel::new(0), true);
-        let v2 = builder.var(VarLabel::new(1), true);
-        let r1 = builder.or(v1, v2);
-        let r2 = builder.and(r1, v1);
-        assert!(
-            builder.eq(v1, r2),
-            "Not eq:\n {}\n{}",
-            v1.to_string_debug(),
-            r2.to_string_debug()
-        );
+                alloc.push(res);
+                bdd.set_scratch(alloc.len() - 1);
+                if bdd.is_neg() {
+                    self.negate(res)
+                } else {
+                    res
+                }
+            }
+        }
     }
 
-    #[test]
-    fn simple_ite1() {
-        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
-        let v1 = builder.var(VarLabel::new(0), true);
-        let v2 = builder.var(VarLabel::new(1), true);
-        let r1 = builder.or(v1, v2);
-        let r2 = builder.ite(r1, v1, BddPtr::false_ptr());
-        assert!(
-            builder.eq(v1, r2),
-            "Not eq:\n {}\n{}",
-            v1.to_string_debug(),
-            r2.to_string_debug()
-        );
+    /// Simultaneously substitutes every variable `v` in `bdd` with the
+    /// function `subs[v]`; variables not in `subs` are left unchanged.
+    /// Unlike composing one variable at a time, a substituted function that
+    /// mentions another substituted variable is not itself rewritten.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, DDNNFPtr, VarLabel};
+    /// # use std::collections::HashMap;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let f = builder.and(a, b.neg());
+    /// // swap a and b
+    /// let subs = HashMap::from([(VarLabel::new(0), b), (VarLabel::new(1), a)]);
+    /// assert_eq!(builder.compose_multiple(f, &subs), builder.and(b, a.neg()));
+    /// ```
+    pub fn compose_multiple(
+        &'a self,
+        bdd: BddPtr<'a>,
+        subs: &HashMap<VarLabel, BddPtr<'a>>,
+    ) -> BddPtr<'a> {
+        debug_assert!(bdd.is_scratch_cleared());
+        let r = self.compose_with_alloc(bdd, subs, &mut Vec::new());
+        bdd.clear_scratch();
+        r
     }
 
-    #[test]
-    fn test_newvar() {
-        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(0);
-        let l1 = builder.new_label();
-        let l2 = builder.new_label();
-        let v1 = builder.var(l1, true);
-        let v2 = builder.var(l2, true);
-        let r1 = builder.or(v1, v2);
-        let r2 = builder.and(r1, v1);
+    #[allow(clippy::mutable_key_type)]
+    fn project_h(
+        &'a self,
+        bdd: BddPtr<'a>,
+        keep: &VarSet,
+        cache: &mut HashMap<BddPtr<'a>, BddPtr<'a>>,
+    ) -> BddPtr<'a> {
+        let var = match bdd.var_safe() {
+            None => return bdd,
+            Some(v) => v,
+        };
+        // quantification does not commute with negation, so results are
+        // cached per (possibly complemented) pointer
+        if let Some(r) = cache.get(&bdd) {
+            return *r;
+        }
+        let l = self.project_h(bdd.low(), keep, cache);
+        let r = if keep.contains(var) {
+            let h = self.project_h(bdd.high(), keep, cache);
+            // the children only mention variables below `var`
+            if l == h {
+                l
+            } else {
+                self.get_or_insert(BddNode::new(var, l, h))
+            }
+        } else if l.is_true() {
+            l
+        } else {
+            let h = self.project_h(bdd.high(), keep, cache);
+            self.or(l, h)
+        };
+        cache.insert(bdd, r);
+        r
+    }
+
+    /// Existentially quantifies every variable of `bdd` that is not in
+    /// `keep`, in a single top-down pass: each eliminated variable is
+    /// replaced by the disjunction of its (already projected) cofactors.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel, VarSet};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let mut keep = VarSet::new();
+    /// keep.insert(VarLabel::new(1));
+    /// assert_eq!(builder.project(builder.and(a, b), &keep), b);
+    /// ```
+    pub fn project(&'a self, bdd: BddPtr<'a>, keep: &VarSet) -> BddPtr<'a> {
+        self.project_h(bdd, keep, &mut HashMap::new())
+    }
+
+    /// Computes the image of `states` under the transition relation `trans`:
+    /// the set of next states reachable in one step, expressed over the
+    /// current-state variables. `cur_to_next` maps each current-state
+    /// variable to its next-state copy; `trans` relates the two copies, and
+    /// `states` should mention only current-state variables.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// # use std::collections::HashMap;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let (x, x_next) = (VarLabel::new(0), VarLabel::new(1));
+    /// // the bit flips on every step
+    /// let trans = builder.xor(builder.var(x, true), builder.var(x_next, true));
+    /// let cur_to_next = HashMap::from([(x, x_next)]);
+    /// let img = builder.image(trans, builder.var(x, false), &cur_to_next);
+    /// assert_eq!(img, builder.var(x, true));
+    /// ```
+    pub fn image(
+        &'a self,
+        trans: BddPtr<'a>,
+        states: BddPtr<'a>,
+        cur_to_next: &HashMap<VarLabel, VarLabel>,
+    ) -> BddPtr<'a> {
+        let mut keep = VarSet::new_with_num_vars(self.num_vars());
+        for v in 0..self.num_vars() {
+            let v = VarLabel::new_usize(v);
+            if !cur_to_next.contains_key(&v) {
+                keep.insert(v);
+            }
+        }
+        let next = self.project(self.and(trans, states), &keep);
+        let next_to_cur: HashMap<VarLabel, VarLabel> =
+            cur_to_next.iter().map(|(c, n)| (*n, *c)).collect();
+        self.rename_vars(next, &next_to_cur)
+    }
+
+    /// Computes the set of states reachable from `init` under `trans` by
+    /// repeatedly adding the `image` of the states found so far, stopping at
+    /// a fixpoint. If `max_iters` is given, at most that many image steps are
+    /// taken, and the states reached so far are returned even if the
+    /// fixpoint has not been reached.
+    /// ```
+    /// # use rsdd::builder::bdd::{BddBuilder, RobddBuilder};
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// # use std::collections::HashMap;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let (x, x_next) = (VarLabel::new(0), VarLabel::new(1));
+    /// // the bit can only be set, never cleared
+    /// let trans = builder.imp(builder.var(x, true), builder.var(x_next, true));
+    /// let cur_to_next = HashMap::from([(x, x_next)]);
+    /// let init = builder.var(x, true);
+    /// assert_eq!(builder.reachable(trans, init, &cur_to_next, None), init);
+    /// ```
+    pub fn reachable(
+        &'a self,
+        trans: BddPtr<'a>,
+        init: BddPtr<'a>,
+        cur_to_next: &HashMap<VarLabel, VarLabel>,
+        max_iters: Option<usize>,
+    ) -> BddPtr<'a> {
+        let mut reached = init;
+        let mut iters = 0;
+        while max_iters.is_none_or(|m| iters < m) {
+            let next = self.or(reached, self.image(trans, reached, cur_to_next));
+            if self.eq(next, reached) {
+                break;
+            }
+            reached = next;
+            iters += 1;
+        }
+        reached
+    }
+
+    /// the level of `bdd` in the current order; constants are placed below every
+    /// variable (i.e., at level `num_vars`)
+    fn level(&self, bdd: BddPtr<'a>) -> usize {
+        match bdd.var_safe() {
+            Some(v) => self.order.borrow().get(v),
+            None => self.num_vars(),
+        }
+    }
+
+    /// `2^n` as a model count, panicking if it overflows
+    fn model_count_pow2(n: usize) -> u128 {
+        u32::try_from(n)
+            .ok()
+            .and_then(|n| 1u128.checked_shl(n))
+            .expect("model count overflowed u128")
+    }
+
+    /// counts the models of `bdd` over the variables at or below its level,
+    /// caching the count for each regular node in its scratch
+    fn model_count_h(&self, bdd: BddPtr<'a>) -> u128 {
+        match bdd {
+            BddPtr::PtrTrue => 1,
+            BddPtr::PtrFalse => 0,
+            BddPtr::Compl(node) => {
+                let free = self.num_vars() - self.level(bdd);
+                let c = self.model_count_h(BddPtr::Reg(node));
+                // `2^free - c`, where `c >= 1`, without forming `2^128`
+                match free {
+                    128 => u128::MAX - (c - 1),
+                    _ => Self::model_count_pow2(free) - c,
+                }
+            }
+            BddPtr::Reg(node) => {
+                if let Some(c) = bdd.scratch::<u128>() {
+                    return c;
+                }
+                // each child is smoothed over the variables skipped between
+                // this node and the child
+                let level = self.level(bdd);
+                let count_child = |child: BddPtr<'a>| {
+                    let gap = self.level(child) - level - 1;
+                    self.model_count_h(child)
+                        .checked_mul(Self::model_count_pow2(gap))
+                        .expect("model count overflowed u128")
+                };
+                let c = count_child(node.low)
+                    .checked_add(count_child(node.high))
+                    .expect("model count overflowed u128");
+                bdd.set_scratch::<u128>(c);
+                c
+            }
+        }
+    }
+
+    /// Count the satisfying assignments of `bdd` over all variables in the
+    /// builder. The count is computed directly with integer arithmetic, so it
+    /// is exact as long as it fits in a `u128`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with "model count overflowed u128" if the count, or the count of
+    /// some sub-diagram over the variables below it, does not fit in a `u128`;
+    /// use `exact_model_count_crt` for larger counts.
+    ///
+    /// Pre-condition: scratch cleared
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let c = builder.var(VarLabel::new(2), true);
+    /// assert_eq!(builder.model_count(builder.and(a, c)), 2);
+    /// assert_eq!(builder.model_count(builder.or(a, c)), 6);
+    /// ```
+    pub fn model_count(&'a self, bdd: BddPtr<'a>) -> u128 {
+        debug_assert!(bdd.is_scratch_cleared());
+        let count = self.model_count_h(bdd);
+        bdd.clear_scratch();
+        count
+            .checked_mul(Self::model_count_pow2(self.level(bdd)))
+            .expect("model count overflowed u128")
+    }
+
+    /// Computes the exact number of models of `bdd` over all of the builder's
+    /// variables by counting modulo each of `moduli` and reconstructing the
+    /// count with the Chinese remainder theorem. Arithmetic in the traversal
+    /// stays in machine words, so this works for counts beyond `u128`.
+    ///
+    /// The moduli must be pairwise coprime, and their product must exceed
+    /// `2^num_vars` (an upper bound on the count); this panics otherwise.
+    ///
+    /// Pre-condition: scratch cleared
+    /// ```
+    /// # use num_bigint::BigUint;
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let c = builder.var(VarLabel::new(2), true);
+    /// let count = builder.exact_model_count_crt(builder.or(a, c), &[3, 5]);
+    /// assert_eq!(count, BigUint::from(6u32));
+    /// ```
+    pub fn exact_model_count_crt(&'a self, bdd: BddPtr<'a>, moduli: &[u64]) -> BigUint {
+        let bound = BigUint::from(1u32) << self.num_vars();
+        let product = moduli
+            .iter()
+            .fold(BigUint::from(1u32), |acc, m| acc * BigUint::from(*m));
         assert!(
-            builder.eq(v1, r2),
-            "Not eq:\n {}\n{}",
-            v1.to_string_debug(),
-            r2.to_string_debug()
+            product > bound,
+            "product of moduli must exceed 2^{}",
+            self.num_vars()
+        );
+
+        let mut all_vars = VarSet::new_with_num_vars(self.num_vars());
+        for v in 0..self.num_vars() {
+            all_vars.insert(VarLabel::new_usize(v));
+        }
+        let smoothed = self.smooth_over(bdd, &all_vars);
+
+        // incrementally maintain x = count mod `modulus`
+        let mut x = BigUint::from(0u32);
+        let mut modulus = BigUint::from(1u32);
+        for &m in moduli {
+            let mut params = WmcParams::default();
+            params.set_default(DynFiniteField::new(1, m), DynFiniteField::new(1, m));
+            let a = smoothed.unsmoothed_wmc(&params).val;
+
+            // find k with x + modulus * k = a (mod m)
+            let x_mod = u64::try_from(&x % m).unwrap();
+            let modulus_mod = u64::try_from(&modulus % m).unwrap();
+            let inv = mod_inverse(modulus_mod, m).expect("moduli must be pairwise coprime");
+            let diff = (a as u128 + m as u128 - x_mod as u128) % m as u128;
+            let k = (diff * inv as u128 % m as u128) as u64;
+            x += &modulus * k;
+            modulus *= m;
+        }
+        x
+    }
+
+    /// Prints the total number of recursive calls executed so far by the RobddBuilder
+    /// This is a stable way to track performance
+    pub fn num_recursive_calls(&self) -> usize {
+        self.stats.borrow().num_recursive_calls
+    }
+
+    fn smooth_helper(&'a self, bdd: BddPtr<'a>, current: usize, total: usize) -> BddPtr<'a> {
+        debug_assert!(current <= total);
+        if current >= total {
+            return bdd;
+        }
+
+        let level = match bdd {
+            BddPtr::Reg(node) | BddPtr::Compl(node) => self.order.borrow().get(node.var),
+            BddPtr::PtrTrue | BddPtr::PtrFalse => total,
+        };
+        if level > current {
+            // `bdd` skips the variable at `current`; add a node that makes
+            // the don't-care decision explicit
+            let var = self.order.borrow().var_at_level(current);
+            let smoothed = self.smooth_helper(bdd, current + 1, total);
+            return self.get_or_insert(BddNode::new(var, smoothed, smoothed));
+        }
+
+        match bdd {
+            BddPtr::Reg(node) => {
+                let smoothed_node = BddNode::new(
+                    node.var,
+                    self.smooth_helper(node.low, current + 1, total),
+                    self.smooth_helper(node.high, current + 1, total),
+                );
+                self.get_or_insert(smoothed_node)
+            }
+            BddPtr::Compl(node) => {
+                let r = self.smooth_helper(BddPtr::Reg(node), current, total);
+                self.negate(r)
+            }
+            BddPtr::PtrTrue | BddPtr::PtrFalse => unreachable!("terminals sit below every level"),
+        }
+    }
+
+    /// Return a smoothed version of the input BDD. Requires:
+    /// - BDD is an ROBDD, i.e. each variable only appears once per path
+    /// - variable ordering respects the builder's order
+    pub fn smooth(&'a self, bdd: BddPtr<'a>, num_vars: usize) -> BddPtr<'a> {
+        // TODO: this num_vars should be tied to the specific BDD, not the manager
+        self.smooth_helper(bdd, 0, num_vars)
+    }
+
+    /// The exact weighted model count of `ptr` over all of the builder's
+    /// variables, computed with rational arithmetic: `ptr` is smoothed first,
+    /// so variables it does not mention contribute the sum of their weights.
+    /// Returns `None` if a numerator or denominator overflows `i128`.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel, WmcParams};
+    /// # use rsdd::util::semirings::RationalSemiring;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let mut wmc = WmcParams::default();
+    /// wmc.set_weight(
+    ///     VarLabel::new(0),
+    ///     RationalSemiring::new(2, 3),
+    ///     RationalSemiring::new(1, 3),
+    /// );
+    /// wmc.set_weight(
+    ///     VarLabel::new(1),
+    ///     RationalSemiring::new(1, 2),
+    ///     RationalSemiring::new(1, 2),
+    /// );
+    /// assert_eq!(builder.exact_wmc_rational(a, &wmc), Some(RationalSemiring::new(1, 3)));
+    /// ```
+    pub fn exact_wmc_rational(
+        &'a self,
+        ptr: BddPtr<'a>,
+        wmc: &WmcParams<RationalSemiring>,
+    ) -> Option<RationalSemiring> {
+        self.smooth(ptr, self.num_vars()).checked_rational_wmc(wmc)
+    }
+
+    /// The total weight of the assignments on which `a` and `b` disagree: the
+    /// weighted model count of `xor(a, b)` over all of the builder's
+    /// variables. This is 0 exactly when the functions are equal (given
+    /// positive weights), and the total weight of all assignments when they
+    /// are complements.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel, WmcParams};
+    /// # use rsdd::util::semirings::RealSemiring;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let mut wmc = WmcParams::default();
+    /// wmc.set_default(RealSemiring(0.5), RealSemiring(0.5));
+    /// // `a` and `a \/ b` disagree only when `a` is false and `b` true
+    /// let d = builder.hamming_weight_of_diff(a, builder.or(a, b), &wmc);
+    /// assert_eq!(d, 0.25);
+    /// ```
+    pub fn hamming_weight_of_diff(
+        &'a self,
+        a: BddPtr<'a>,
+        b: BddPtr<'a>,
+        wmc: &WmcParams<RealSemiring>,
+    ) -> f64 {
+        let diff = self.xor(a, b);
+        self.smooth(diff, self.num_vars()).unsmoothed_wmc(wmc).0
+    }
+
+    /// Returns the number of nodes currently allocated in the compute table
+    pub fn num_nodes(&self) -> usize {
+        self.compute_table.borrow().num_nodes()
+    }
+
+    /// Collects every node in the compute table, whether or not it is still
+    /// reachable from a BDD in use, in no particular order; e.g. to dump the
+    /// manager's entire state. This allocates a vector of all the nodes, as
+    /// the table cannot stay borrowed while the caller builds new nodes.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// builder.and(a, b);
+    /// assert_eq!(builder.collect_nodes().len(), builder.table_stats().num_nodes);
+    /// ```
+    pub fn collect_nodes(&self) -> Vec<&'a BddNode<'a>> {
+        self.compute_table.borrow().iter().collect()
+    }
+
+    /// Returns the largest number of nodes the compute table has held since
+    /// the builder was created or `reset_peak` was last called, including
+    /// the peak of any builder it was filled from by `gc`. A table never
+    /// shrinks, so this exceeds `num_nodes` only when a `gc` discarded
+    /// intermediate results; it is the figure that bounds memory use.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let _ab = builder.and(a, b);
+    /// let peak = builder.num_nodes();
+    /// let fresh = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// builder.gc(&[a], &fresh);
+    /// assert_eq!(fresh.peak_node_count(), peak);
+    /// assert!(fresh.num_nodes() < peak);
+    /// ```
+    pub fn peak_node_count(&self) -> usize {
+        self.peak_num_nodes.borrow().max(self.num_nodes())
+    }
+
+    /// Restarts the tracking of `peak_node_count` from the current size of
+    /// the compute table
+    pub fn reset_peak(&self) {
+        self.peak_num_nodes.replace(0);
+    }
+
+    /// Returns the load and probe-sequence lengths of the compute table, e.g.
+    /// to detect when it is overloaded and should be given a larger capacity
+    pub fn table_stats(&self) -> UniqueTableStats {
+        self.compute_table.borrow().table_stats()
+    }
+
+    /// Garbage-collects by copying the nodes reachable from `roots` into
+    /// `into`, typically a fresh builder with the same variable order, and
+    /// returns the copied roots in the same order as they were given. Once
+    /// every pointer into this builder is dropped, dropping the builder frees
+    /// the nodes that were not copied; the borrow checker ensures no pointer
+    /// outlives it. `into` starts with its own (empty) apply cache, so no
+    /// cached result can refer to a discarded node.
+    ///
+    /// Apart from `peak_node_count`, statistics are not carried over: `stats`
+    /// on `into` (e.g. `num_table_resizes`) only counts its own work, starting
+    /// from whatever capacity `into` was created with.
+    ///
+    /// Panics if `into` does not have the same variable order.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let a_and_b = builder.and(a, b);
+    /// let fresh = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+    /// let roots = builder.gc(&[a_and_b], &fresh);
+    /// assert_eq!(fresh.num_nodes(), 2);
+    /// ```
+    pub fn gc<'b, U: IteTable<'b, BddPtr<'b>> + Default>(
+        &self,
+        roots: &[BddPtr<'a>],
+        into: &'b RobddBuilder<'b, U>,
+    ) -> Vec<BddPtr<'b>> {
+        assert!(
+            self.order
+                .borrow()
+                .in_order_iter()
+                .eq(into.order().in_order_iter()),
+            "gc requires a builder with the same variable order"
+        );
+        // `into` takes over from this builder, so it inherits its peak
+        let peak = into.peak_num_nodes.borrow().max(self.peak_node_count());
+        into.peak_num_nodes.replace(peak);
+        let mut remap = HashMap::new();
+        roots
+            .iter()
+            .map(|&root| Self::gc_copy(root, into, &mut remap))
+            .collect()
+    }
+
+    /// copies `bdd` into `into`, memoizing on the address of the (regular)
+    /// node being copied
+    fn gc_copy<'b, U: IteTable<'b, BddPtr<'b>> + Default>(
+        bdd: BddPtr<'a>,
+        into: &'b RobddBuilder<'b, U>,
+        remap: &mut HashMap<*const BddNode<'a>, BddPtr<'b>>,
+    ) -> BddPtr<'b> {
+        match bdd {
+            BddPtr::PtrTrue => BddPtr::PtrTrue,
+            BddPtr::PtrFalse => BddPtr::PtrFalse,
+            BddPtr::Compl(node) => {
+                let r = Self::gc_copy(BddPtr::Reg(node), into, remap);
+                into.negate(r)
+            }
+            BddPtr::Reg(node) => {
+                let key = node as *const BddNode<'a>;
+                if let Some(r) = remap.get(&key) {
+                    return *r;
+                }
+                let low = Self::gc_copy(node.low, into, remap);
+                let high = Self::gc_copy(node.high, into, remap);
+                let r = into.get_or_insert(BddNode::new(node.var, low, high));
+                remap.insert(key, r);
+                r
+            }
+        }
+    }
+
+    fn smooth_over_h(
+        &'a self,
+        bdd: BddPtr<'a>,
+        from_level: usize,
+        levels: &[(usize, VarLabel)],
+        alloc: &mut Vec<BddPtr<'a>>,
+    ) -> BddPtr<'a> {
+        let level = self.level(bdd);
+        let mut r = match bdd {
+            BddPtr::PtrTrue | BddPtr::PtrFalse => bdd,
+            BddPtr::Reg(node) | BddPtr::Compl(node) => {
+                // the smoothed node (below its own level) is cached on the
+                // regular pointer
+                let core = match bdd.scratch::<usize>() {
+                    Some(idx) => alloc[idx],
+                    None => {
+                        let l = self.smooth_over_h(node.low, level + 1, levels, alloc);
+                        let h = self.smooth_over_h(node.high, level + 1, levels, alloc);
+                        let core = self.get_or_insert(BddNode::new(node.var, l, h));
+                        alloc.push(core);
+                        bdd.set_scratch(alloc.len() - 1);
+                        core
+                    }
+                };
+                if bdd.is_neg() {
+                    self.negate(core)
+                } else {
+                    core
+                }
+            }
+        };
+        // insert a don't-care node for every smoothing variable that is
+        // skipped between `from_level` and `bdd`, bottom-up
+        for &(_, var) in levels
+            .iter()
+            .rev()
+            .filter(|(l, _)| *l >= from_level && *l < level)
+        {
+            r = self.get_or_insert(BddNode::new(var, r, r));
+        }
+        r
+    }
+
+    /// Return a version of `bdd` that is smoothed over exactly the variables
+    /// in `vars`: every variable of `vars` appears on every path, and no other
+    /// variables are introduced. Unlike `smooth`, this does not assume that
+    /// every variable in the builder belongs to the function.
+    ///
+    /// Pre-condition: scratch cleared
+    pub fn smooth_over(&'a self, bdd: BddPtr<'a>, vars: &VarSet) -> BddPtr<'a> {
+        debug_assert!(bdd.is_scratch_cleared());
+        let mut levels: Vec<(usize, VarLabel)> = {
+            let order = self.order.borrow();
+            vars.iter()
+                .filter(|v| v.value_usize() < order.num_vars())
+                .map(|v| (order.get(v), v))
+                .collect()
+        };
+        levels.sort();
+        let r = self.smooth_over_h(bdd, 0, &levels, &mut Vec::new());
+        bdd.clear_scratch();
+        r
+    }
+
+    /// the smoothed weighted model count of `ptr` over the variables at or
+    /// below its level, where `sumw[l]` is the total weight of the variable
+    /// at level `l`
+    fn smoothed_wmc_h(
+        &'a self,
+        ptr: BddPtr<'a>,
+        wmc: &WmcParams<RealSemiring>,
+        sumw: &[f64],
+        up: &mut HashMap<MarginalKey<'a>, (BddPtr<'a>, f64)>,
+    ) -> f64 {
+        match ptr {
+            BddPtr::PtrTrue => 1.0,
+            BddPtr::PtrFalse => 0.0,
+            BddPtr::Compl(node) | BddPtr::Reg(node) => {
+                if let Some((_, v)) = up.get(&(node as *const _, ptr.is_neg())) {
+                    return *v;
+                }
+                let level = self.level(ptr);
+                let (low_w, high_w) = wmc.var_weight(ptr.var_safe().unwrap());
+                let mut v = 0.0;
+                for (child, w) in [(ptr.low(), low_w.0), (ptr.high(), high_w.0)] {
+                    let gap = sumw[level + 1..self.level(child)].iter().product::<f64>();
+                    v += w * gap * self.smoothed_wmc_h(child, wmc, sumw, up);
+                }
+                up.insert((node as *const _, ptr.is_neg()), (ptr, v));
+                v
+            }
+        }
+    }
+
+    /// Computes the smoothed weighted model count `Z` of `ptr` over every
+    /// variable in the builder, along with the partial derivatives
+    /// `(dZ/dw_low, dZ/dw_high)` with respect to the two weights of the
+    /// variable at each level. One bottom-up pass computes the weighted model
+    /// count below each node, and one top-down pass pushes the weight of the
+    /// paths reaching each node back down.
+    fn wmc_partials(
+        &'a self,
+        ptr: BddPtr<'a>,
+        wmc: &WmcParams<RealSemiring>,
+    ) -> (f64, Vec<(f64, f64)>) {
+        let n = self.num_vars();
+        let sumw: Vec<f64> = (0..n)
+            .map(|l| {
+                let (low_w, high_w) = wmc.var_weight(self.order.borrow().var_at_level(l));
+                low_w.0 + high_w.0
+            })
+            .collect();
+        // for each level in `levels`, the product of the total weights of the
+        // other levels in `levels`
+        let others = |levels: std::ops::Range<usize>| -> Vec<f64> {
+            let ws = &sumw[levels];
+            let mut prefix = vec![1.0; ws.len() + 1];
+            for (i, w) in ws.iter().enumerate() {
+                prefix[i + 1] = prefix[i] * w;
+            }
+            let mut suffix = 1.0;
+            let mut r = vec![0.0; ws.len()];
+            for i in (0..ws.len()).rev() {
+                r[i] = prefix[i] * suffix;
+                suffix *= ws[i];
+            }
+            r
+        };
+
+        // bottom-up pass
+        let mut up = HashMap::new();
+        let root_level = self.level(ptr);
+        let root_v = self.smoothed_wmc_h(ptr, wmc, &sumw, &mut up);
+        let above_root: f64 = sumw[..root_level].iter().product();
+        let z = above_root * root_v;
+
+        // top-down pass, visiting nodes in order of level so that all of a
+        // node's parents are visited before it
+        let mut partials = vec![(0.0, 0.0); n];
+        for (l, rest) in others(0..root_level).into_iter().enumerate() {
+            partials[l] = (rest * root_v, rest * root_v);
+        }
+        let mut down: HashMap<MarginalKey<'a>, f64> = HashMap::new();
+        let mut nodes: Vec<(BddPtr<'a>, f64)> = up.values().copied().collect();
+        nodes.sort_by_key(|(p, _)| self.level(*p));
+        if let Some(key) = marginal_key(ptr) {
+            down.insert(key, above_root);
+        }
+        for (cur, _) in nodes {
+            let d = match down.get(&marginal_key(cur).unwrap()) {
+                Some(d) => *d,
+                None => continue,
+            };
+            let level = self.level(cur);
+            let (low_w, high_w) = wmc.var_weight(cur.var_safe().unwrap());
+            for (child, w, polarity) in [(cur.low(), low_w.0, false), (cur.high(), high_w.0, true)]
+            {
+                let child_level = self.level(child);
+                let gap: f64 = sumw[level + 1..child_level].iter().product();
+                let child_v = match marginal_key(child) {
+                    Some(key) => up[&key].1,
+                    None if child.is_true() => 1.0,
+                    None => 0.0,
+                };
+                let through = d * gap * child_v;
+                if polarity {
+                    partials[level].1 += through;
+                } else {
+                    partials[level].0 += through;
+                }
+                let skipped = others(level + 1..child_level);
+                for (s, rest) in (level + 1..child_level).zip(skipped) {
+                    let ds = d * w * rest * child_v;
+                    partials[s].0 += ds;
+                    partials[s].1 += ds;
+                }
+                if let Some(key) = marginal_key(child) {
+                    *down.entry(key).or_insert(0.0) += d * w * gap;
+                }
+            }
+        }
+        (z, partials)
+    }
+
+    /// Computes the marginal probability `P(v = true | ptr)` of every variable
+    /// `v` in the builder, using one bottom-up pass to compute the weighted
+    /// model count below each node and one top-down pass to push the weight of
+    /// the paths reaching each node back down. Variables that do not appear on
+    /// a path are smoothed in with their own weights.
+    ///
+    /// Every variable in the builder must have a weight in `wmc`. If `ptr` has
+    /// a weighted model count of zero, the marginals are NaN.
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel, WmcParams};
+    /// # use rsdd::util::semirings::RealSemiring;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let weights = WmcParams::new(HashMap::from([
+    ///     (VarLabel::new(0), (RealSemiring(0.5), RealSemiring(0.5))),
+    ///     (VarLabel::new(1), (RealSemiring(0.5), RealSemiring(0.5))),
+    /// ]));
+    /// let marginals = builder.all_marginals(builder.or(a, b), &weights);
+    /// assert!((marginals[&VarLabel::new(0)] - 2.0 / 3.0).abs() < 1e-9);
+    /// ```
+    pub fn all_marginals(
+        &'a self,
+        ptr: BddPtr<'a>,
+        wmc: &WmcParams<RealSemiring>,
+    ) -> HashMap<VarLabel, f64> {
+        // Z is linear in each weight, so the weight of the models setting a
+        // variable true is its high weight times dZ/dw_high
+        let (z, partials) = self.wmc_partials(ptr, wmc);
+        partials
+            .into_iter()
+            .enumerate()
+            .map(|(l, (_, d_high))| {
+                let v = self.order.borrow().var_at_level(l);
+                (v, wmc.var_weight(v).1 .0 * d_high / z)
+            })
+            .collect()
+    }
+
+    /// Computes, for every variable `v` in the builder, how strongly the
+    /// smoothed weighted model count `Z` of `ptr` depends on `v`'s weights:
+    /// `dZ/dw_high(v) - dZ/dw_low(v)`, the change in `Z` from shifting weight
+    /// from `v`'s false literal to its true literal. The variables with the
+    /// largest magnitudes are the most influential parameters. All
+    /// derivatives come from the same two passes as `all_marginals`.
+    ///
+    /// Every variable in the builder must have a weight in `wmc`.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel, WmcParams};
+    /// # use rsdd::util::semirings::RealSemiring;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let mut wmc = WmcParams::default();
+    /// wmc.set_weight(VarLabel::new(0), RealSemiring(0.9), RealSemiring(0.1));
+    /// wmc.set_weight(VarLabel::new(1), RealSemiring(0.5), RealSemiring(0.5));
+    /// // Z = w_high(a) * (w_low(b) + w_high(b)) + w_low(a) * w_high(b)
+    /// let s = builder.weight_sensitivities(builder.or(a, b), &wmc);
+    /// assert!((s[&VarLabel::new(0)] - (1.0 - 0.5)).abs() < 1e-9);
+    /// assert!((s[&VarLabel::new(1)] - 0.9).abs() < 1e-9);
+    /// ```
+    pub fn weight_sensitivities(
+        &'a self,
+        ptr: BddPtr<'a>,
+        wmc: &WmcParams<RealSemiring>,
+    ) -> HashMap<VarLabel, f64> {
+        let (_, partials) = self.wmc_partials(ptr, wmc);
+        partials
+            .into_iter()
+            .enumerate()
+            .map(|(l, (d_low, d_high))| (self.order.borrow().var_at_level(l), d_high - d_low))
+            .collect()
+    }
+
+    fn path_length_bounds_h(&'a self, ptr: BddPtr<'a>) -> (usize, usize) {
+        match ptr {
+            BddPtr::PtrTrue => (0, 0),
+            BddPtr::PtrFalse => (usize::MAX, 0),
+            BddPtr::Compl(_) | BddPtr::Reg(_) => {
+                let (compl, reg) = ptr.scratch::<PathBoundsCache>().unwrap_or((None, None));
+                let cached = if ptr.is_neg() { compl } else { reg };
+                if let Some(v) = cached {
+                    return v;
+                }
+                let mut bounds = (usize::MAX, 0);
+                for child in [ptr.low(), ptr.high()] {
+                    let (lo, hi) = self.path_length_bounds_h(child);
+                    if lo != usize::MAX {
+                        bounds = (bounds.0.min(lo + 1), bounds.1.max(hi + 1));
+                    }
+                }
+                if ptr.is_neg() {
+                    ptr.set_scratch::<PathBoundsCache>((Some(bounds), reg));
+                } else {
+                    ptr.set_scratch::<PathBoundsCache>((compl, Some(bounds)));
+                }
+                bounds
+            }
+        }
+    }
+
+    /// Computes `WMC(ptr /\ evidence)`: the total weight of the models of
+    /// `ptr` that agree with `evidence`, e.g. the probability of the evidence
+    /// when the weights are probabilities. Variables set by `evidence`
+    /// contribute only the weight of their observed literal; every other
+    /// variable in the builder is summed over.
+    ///
+    /// Every variable in the builder must have a weight in `wmc`.
+    ///
+    /// Pre-condition: scratch cleared
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, PartialModel, VarLabel, WmcParams};
+    /// # use rsdd::util::semirings::RealSemiring;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let weights = WmcParams::new(HashMap::from([
+    ///     (VarLabel::new(0), (RealSemiring(0.7), RealSemiring(0.3))),
+    ///     (VarLabel::new(1), (RealSemiring(0.4), RealSemiring(0.6))),
+    /// ]));
+    /// let evidence = PartialModel::from_assignments(&[None, Some(false)]);
+    /// // P((a \/ b) /\ !b) = P(a) * P(!b)
+    /// let p = builder.wmc_given(builder.or(a, b), &evidence, &weights);
+    /// assert!((p - 0.3 * 0.4).abs() < 1e-9);
+    /// ```
+    pub fn wmc_given(
+        &'a self,
+        ptr: BddPtr<'a>,
+        evidence: &PartialModel,
+        wmc: &WmcParams<RealSemiring>,
+    ) -> f64 {
+        let conditioned = self.condition_model(ptr, evidence);
+        let mut free = VarSet::new_with_num_vars(self.num_vars());
+        for v in 0..self.num_vars() {
+            let label = VarLabel::new_usize(v);
+            if !evidence.is_set(label) {
+                free.insert(label);
+            }
+        }
+        let evidence_weight: f64 = evidence
+            .assignment_iter()
+            .map(|lit| {
+                let (low_w, high_w) = wmc.var_weight(lit.label());
+                if lit.polarity() {
+                    high_w.0
+                } else {
+                    low_w.0
+                }
+            })
+            .product();
+        let smoothed = self.smooth_over(conditioned, &free);
+        evidence_weight * smoothed.unsmoothed_wmc(wmc).0
+    }
+
+    /// Computes `P(query | evidence)` over the models of `ptr`, i.e.
+    /// `WMC(ptr /\ query /\ evidence) / WMC(ptr /\ evidence)`. Returns an
+    /// error if the evidence has a weighted model count of zero.
+    ///
+    /// Every variable in the builder must have a weight in `wmc`.
+    ///
+    /// Pre-condition: scratch cleared
+    pub fn conditional_prob(
+        &'a self,
+        ptr: BddPtr<'a>,
+        query: &PartialModel,
+        evidence: &PartialModel,
+        wmc: &WmcParams<RealSemiring>,
+    ) -> Result<f64, ZeroEvidenceError> {
+        let z = self.wmc_given(ptr, evidence, wmc);
+        if z == 0.0 {
+            return Err(ZeroEvidenceError);
+        }
+        let mut joint = evidence.clone();
+        for lit in query.assignment_iter() {
+            if evidence.lit_neg_implied(lit) {
+                // the query contradicts the evidence
+                return Ok(0.0);
+            }
+            joint.set(lit.label(), lit.polarity());
+        }
+        Ok(self.wmc_given(ptr, &joint, wmc) / z)
+    }
+
+    /// Computes the expected value of an additive reward over the models of
+    /// `ptr`, conditioned on `ptr` holding: `E[sum_v value[v] * v | ptr]`,
+    /// where each variable `v` is independently true with the weight given by
+    /// `prob`. Variables absent from `value` contribute no reward.
+    ///
+    /// Every variable in the builder must have a weight in `prob`. If `ptr`
+    /// has probability zero, the result is NaN.
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel, WmcParams};
+    /// # use rsdd::util::semirings::RealSemiring;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let prob = WmcParams::new(HashMap::from([
+    ///     (VarLabel::new(0), (RealSemiring(0.5), RealSemiring(0.5))),
+    ///     (VarLabel::new(1), (RealSemiring(0.5), RealSemiring(0.5))),
+    /// ]));
+    /// let value = HashMap::from([(VarLabel::new(0), 3.0)]);
+    /// // given a \/ b, a is true with probability 2/3
+    /// let ev = builder.expected_value(builder.or(a, b), &prob, &value);
+    /// assert!((ev - 2.0).abs() < 1e-9);
+    /// ```
+    pub fn expected_value(
+        &'a self,
+        ptr: BddPtr<'a>,
+        prob: &WmcParams<RealSemiring>,
+        value: &HashMap<VarLabel, f64>,
+    ) -> f64 {
+        let mut eu_params = WmcParams::new(HashMap::new());
+        let mut all_vars = VarSet::new_with_num_vars(self.num_vars());
+        for v in 0..self.num_vars() {
+            let label = VarLabel::new_usize(v);
+            let (low_w, high_w) = prob.var_weight(label);
+            let reward = value.get(&label).copied().unwrap_or(0.0);
+            eu_params.set_weight(
+                label,
+                ExpectedUtility(low_w.0, 0.0),
+                ExpectedUtility(high_w.0, high_w.0 * reward),
+            );
+            all_vars.insert(label);
+        }
+        // unvisited variables still contribute reward, so smooth first
+        let smoothed = self.smooth_over(ptr, &all_vars);
+        let ExpectedUtility(p, eu) = smoothed.unsmoothed_wmc(&eu_params);
+        eu / p
+    }
+
+    /// Computes the weighted model count of `ptr` like `unsmoothed_wmc`, but
+    /// returns the value computed at every pointer reached on the way, keyed
+    /// by that pointer (complemented pointers are keyed separately from their
+    /// regular counterparts, and the terminals are included). The root's entry
+    /// is the result of `ptr.unsmoothed_wmc(wmc)`; this is meant for debugging
+    /// a count by inspecting its intermediate values.
+    #[allow(clippy::mutable_key_type)]
+    pub fn wmc_trace<W: Semiring>(
+        &'a self,
+        ptr: BddPtr<'a>,
+        wmc: &WmcParams<W>,
+    ) -> HashMap<BddPtr<'a>, W> {
+        let mut trace = HashMap::new();
+        ptr.unsmoothed_wmc_cached(wmc, &mut trace);
+        trace
+    }
+
+    /// Folds `ptr` bottom-up like `unsmoothed_wmc`, but with the combining
+    /// operations given as closures rather than a `Semiring`, e.g. to
+    /// prototype a new query. The terminals evaluate to `zero` and `one`, and
+    /// a node on variable `v` with children valued `lo` and `hi` evaluates to
+    /// `or_fn(v, and_fn(low_w, lo), and_fn(high_w, hi))`, where
+    /// `(low_w, high_w) = weight(v)`. Since `or_fn` is passed the variable,
+    /// different variables may be accumulated differently, e.g. summing some
+    /// and maximizing over others.
+    ///
+    /// As with `unsmoothed_wmc`, variables that `ptr` does not mention are
+    /// not accounted for; smooth `ptr` first if they should be.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// // the number of models of `a xor b`, which mentions both variables
+    /// // on every path
+    /// let count = builder.wmc_custom(
+    ///     builder.xor(a, b),
+    ///     |_, lo, hi| lo + hi,
+    ///     |w, v| w * v,
+    ///     |_| (1, 1),
+    ///     0,
+    ///     1,
+    /// );
+    /// assert_eq!(count, 2);
+    /// ```
+    pub fn wmc_custom<V, F, G>(
+        &'a self,
+        ptr: BddPtr<'a>,
+        or_fn: F,
+        and_fn: G,
+        weight: impl Fn(VarLabel) -> (V, V),
+        zero: V,
+        one: V,
+    ) -> V
+    where
+        V: Clone,
+        F: Fn(VarLabel, V, V) -> V,
+        G: Fn(V, V) -> V,
+    {
+        #[allow(clippy::mutable_key_type)]
+        fn wmc_custom_h<'b, V: Clone>(
+            ptr: BddPtr<'b>,
+            fold: &dyn Fn(VarLabel, V, V) -> V,
+            zero: &V,
+            one: &V,
+            cache: &mut HashMap<BddPtr<'b>, V>,
+        ) -> V {
+            if ptr.is_true() {
+                return one.clone();
+            }
+            if ptr.is_false() {
+                return zero.clone();
+            }
+            if let Some(v) = cache.get(&ptr) {
+                return v.clone();
+            }
+            let lo = wmc_custom_h(ptr.low(), fold, zero, one, cache);
+            let hi = wmc_custom_h(ptr.high(), fold, zero, one, cache);
+            let r = fold(ptr.var_safe().unwrap(), lo, hi);
+            cache.insert(ptr, r.clone());
+            r
+        }
+        let fold = |v, lo, hi| {
+            let (low_w, high_w) = weight(v);
+            or_fn(v, and_fn(low_w, lo), and_fn(high_w, hi))
+        };
+        wmc_custom_h(ptr, &fold, &zero, &one, &mut HashMap::new())
+    }
+
+    /// Computes the Shannon entropy (in nats) of the distribution over the
+    /// satisfying assignments of `ptr` in which each assignment has
+    /// probability proportional to its weight under `wmc`. The sum
+    /// `Z = sum_x w(x)` and the term `sum_x w(x) ln w(x)` are accumulated in
+    /// one pass with the `ExpectedUtility` pair semiring, giving
+    /// `H = ln Z - (sum_x w(x) ln w(x)) / Z`.
+    ///
+    /// Every variable in the builder must have a weight in `wmc`. If `ptr`
+    /// has weight zero, the result is NaN.
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, DDNNFPtr, VarLabel, WmcParams};
+    /// # use rsdd::util::semirings::RealSemiring;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(1);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let mut wmc = WmcParams::default();
+    /// wmc.set_default(RealSemiring(0.5), RealSemiring(0.5));
+    /// // a fair coin carries ln 2 nats
+    /// let h = builder.entropy(BddPtr::true_ptr(), &wmc);
+    /// assert!((h - 2f64.ln()).abs() < 1e-12);
+    /// // a single model carries none
+    /// assert!(builder.entropy(a, &wmc).abs() < 1e-12);
+    /// ```
+    pub fn entropy(&'a self, ptr: BddPtr<'a>, wmc: &WmcParams<RealSemiring>) -> f64 {
+        // 0 ln 0 = 0
+        let w_ln_w = |w: f64| if w == 0.0 { 0.0 } else { w * w.ln() };
+        let ExpectedUtility(z, w_ln_w_sum) = self.smoothed_pair_wmc(ptr, |label| {
+            let (low_w, high_w) = wmc.var_weight(label);
+            (
+                ExpectedUtility(low_w.0, w_ln_w(low_w.0)),
+                ExpectedUtility(high_w.0, w_ln_w(high_w.0)),
+            )
+        });
+        z.ln() - w_ln_w_sum / z
+    }
+
+    /// Computes the KL divergence `KL(p || q)` (in nats) between the
+    /// distributions that the weightings `p` and `q` induce on the satisfying
+    /// assignments of `ptr`, each normalized over those assignments.
+    ///
+    /// Writing `wp`, `wq` for the unnormalized weights and `Zp`, `Zq` for
+    /// their sums, this is `(sum_x wp(x) ln(wp(x) / wq(x))) / Zp - ln Zp + ln Zq`,
+    /// where the sum is accumulated in one pass with the `ExpectedUtility`
+    /// pair semiring.
+    /// Returns infinity if some assignment has positive weight under `p` but
+    /// zero weight under `q`. Every variable in the builder must have a
+    /// weight in both `p` and `q`; if `ptr` has weight zero under `p`, the
+    /// result is NaN.
+    pub fn kl_divergence(
+        &'a self,
+        ptr: BddPtr<'a>,
+        p: &WmcParams<RealSemiring>,
+        q: &WmcParams<RealSemiring>,
+    ) -> f64 {
+        let lits = |label: VarLabel| {
+            let (p_lo, p_hi) = p.var_weight(label);
+            let (q_lo, q_hi) = q.var_weight(label);
+            [(p_lo.0, q_lo.0), (p_hi.0, q_hi.0)]
+        };
+
+        // the second component counts, over assignments of positive p-weight,
+        // the literals of zero q-weight; it is nonzero iff some such
+        // assignment has zero q-weight
+        let ExpectedUtility(_, unsupported) = self.smoothed_pair_wmc(ptr, |label| {
+            let [lo, hi] = lits(label).map(|(pw, qw)| {
+                let support = if pw > 0.0 { 1.0 } else { 0.0 };
+                let missing = if pw > 0.0 && qw == 0.0 { 1.0 } else { 0.0 };
+                ExpectedUtility(support, missing)
+            });
+            (lo, hi)
+        });
+        if unsupported > 0.0 {
+            return f64::INFINITY;
+        }
+
+        let ExpectedUtility(z_q, _) = self.smoothed_pair_wmc(ptr, |label| {
+            let [lo, hi] = lits(label).map(|(_, qw)| ExpectedUtility(qw, 0.0));
+            (lo, hi)
+        });
+        let ExpectedUtility(z_p, log_ratio_sum) = self.smoothed_pair_wmc(ptr, |label| {
+            let [lo, hi] = lits(label).map(|(pw, qw)| {
+                // a literal with zero q-weight now only occurs in assignments
+                // of zero p-weight, so its log-ratio term is irrelevant
+                let term = if pw == 0.0 || qw == 0.0 {
+                    0.0
+                } else {
+                    pw * (pw.ln() - qw.ln())
+                };
+                ExpectedUtility(pw, term)
+            });
+            (lo, hi)
+        });
+        log_ratio_sum / z_p - z_p.ln() + z_q.ln()
+    }
+
+    /// weighted model count of `ptr` smoothed over every variable, in the
+    /// `ExpectedUtility` pair semiring with literal weights given by `weight`
+    fn smoothed_pair_wmc(
+        &'a self,
+        ptr: BddPtr<'a>,
+        weight: impl Fn(VarLabel) -> (ExpectedUtility, ExpectedUtility),
+    ) -> ExpectedUtility {
+        let mut params = WmcParams::new(HashMap::new());
+        let mut all_vars = VarSet::new_with_num_vars(self.num_vars());
+        for v in 0..self.num_vars() {
+            let label = VarLabel::new_usize(v);
+            let (low_w, high_w) = weight(label);
+            params.set_weight(label, low_w, high_w);
+            all_vars.insert(label);
+        }
+        // every variable contributes to the weight, so smooth first
+        self.smooth_over(ptr, &all_vars).unsmoothed_wmc(&params)
+    }
+
+    /// Returns the minimum and maximum number of decision nodes on any path
+    /// from `ptr` to the true terminal. Variables that are skipped along a
+    /// path (don't-cares) are not counted. If `ptr` is unsatisfiable, returns
+    /// `(usize::MAX, 0)`.
+    ///
+    /// Pre-condition: scratch cleared
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// assert_eq!(builder.path_length_bounds(builder.or(a, b)), (1, 2));
+    /// assert_eq!(builder.path_length_bounds(BddPtr::PtrFalse), (usize::MAX, 0));
+    /// ```
+    pub fn path_length_bounds(&'a self, ptr: BddPtr<'a>) -> (usize, usize) {
+        debug_assert!(ptr.is_scratch_cleared());
+        let r = self.path_length_bounds_h(ptr);
+        ptr.clear_scratch();
+        r
+    }
+
+    /// the best value of the low and high branches of `ptr` in a semiring
+    /// whose addition is `max`, where `maxw[l]` is the larger weight of the
+    /// variable at level `l`
+    fn max_path_branches<W: Semiring + PartialOrd + 'static>(
+        &'a self,
+        ptr: BddPtr<'a>,
+        weight: &impl Fn(VarLabel) -> (W, W),
+        maxw: &[W],
+    ) -> (W, W) {
+        let level = self.level(ptr);
+        let (l, h) = (ptr.low(), ptr.high());
+        let (low_w, high_w) = weight(ptr.var_safe().unwrap());
+        let gap = |child| {
+            maxw[level + 1..self.level(child)]
+                .iter()
+                .fold(W::one(), |acc, w| acc * *w)
+        };
+        (
+            low_w * gap(l) * self.max_path_h(l, weight, maxw),
+            high_w * gap(h) * self.max_path_h(h, weight, maxw),
+        )
+    }
+
+    /// computes the best value of `ptr` over the variables at or below its
+    /// level, caching a (compl, non_compl) pair in each node's scratch
+    fn max_path_h<W: Semiring + PartialOrd + 'static>(
+        &'a self,
+        ptr: BddPtr<'a>,
+        weight: &impl Fn(VarLabel) -> (W, W),
+        maxw: &[W],
+    ) -> W {
+        match ptr {
+            BddPtr::PtrTrue => W::one(),
+            BddPtr::PtrFalse => W::zero(),
+            BddPtr::Compl(_) | BddPtr::Reg(_) => {
+                let (compl, reg) = ptr
+                    .scratch::<(Option<W>, Option<W>)>()
+                    .unwrap_or((None, None));
+                let cached = if ptr.is_neg() { compl } else { reg };
+                if let Some(v) = cached {
+                    return v;
+                }
+                let (low_v, high_v) = self.max_path_branches(ptr, weight, maxw);
+                let v = low_v + high_v;
+                if ptr.is_neg() {
+                    ptr.set_scratch::<(Option<W>, Option<W>)>((Some(v), reg));
+                } else {
+                    ptr.set_scratch::<(Option<W>, Option<W>)>((compl, Some(v)));
+                }
+                v
+            }
+        }
+    }
+
+    /// Finds the best total model of `ptr` in a semiring whose addition is
+    /// `max` (e.g. Viterbi or tropical), where `weight` gives the
+    /// `(low, high)` weight of each variable. Variables that do not affect
+    /// `ptr` are set to their better polarity; ties are broken toward `true`.
+    fn max_path<W: Semiring + PartialOrd + 'static>(
+        &'a self,
+        ptr: BddPtr<'a>,
+        weight: impl Fn(VarLabel) -> (W, W),
+    ) -> (W, PartialModel) {
+        debug_assert!(ptr.is_scratch_cleared());
+        let n = self.num_vars();
+        let vars: Vec<VarLabel> = (0..n)
+            .map(|l| self.order.borrow().var_at_level(l))
+            .collect();
+        let maxw: Vec<W> = vars
+            .iter()
+            .map(|v| {
+                let (low_w, high_w) = weight(*v);
+                low_w + high_w
+            })
+            .collect();
+
+        let mut model = PartialModel::new(n);
+        let fill = |model: &mut PartialModel, from: usize, to: usize| {
+            for v in &vars[from..to] {
+                let (low_w, high_w) = weight(*v);
+                model.set(*v, high_w >= low_w);
+            }
+        };
+
+        let root_level = self.level(ptr);
+        let value = maxw[..root_level].iter().fold(W::one(), |acc, w| acc * *w)
+            * self.max_path_h(ptr, &weight, &maxw);
+
+        // top-down argmax reconstruction
+        fill(&mut model, 0, root_level);
+        let mut cur = ptr;
+        while !cur.is_const() {
+            let (low_v, high_v) = self.max_path_branches(cur, &weight, &maxw);
+            let choice = high_v >= low_v;
+            model.set(cur.var_safe().unwrap(), choice);
+            let next = if choice { cur.high() } else { cur.low() };
+            fill(&mut model, self.level(cur) + 1, self.level(next));
+            cur = next;
+        }
+        ptr.clear_scratch();
+        (value, model)
+    }
+
+    /// Computes the most probable explanation of `ptr`: the single total
+    /// assignment to the builder's variables with the highest weight, along
+    /// with that weight. Variables that do not affect `ptr` are set to their
+    /// higher-weight polarity; ties are broken toward `true`.
+    ///
+    /// Every variable in the builder must have a weight in `wmc`.
+    ///
+    /// Pre-condition: scratch cleared
+    /// ```
+    /// # use std::collections::HashMap;
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel, WmcParams};
+    /// # use rsdd::util::semirings::RealSemiring;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let f = builder.or(a, b);
+    ///
+    /// let weights = WmcParams::new(HashMap::from([
+    ///     (VarLabel::new(0), (RealSemiring(0.6), RealSemiring(0.4))),
+    ///     (VarLabel::new(1), (RealSemiring(0.3), RealSemiring(0.7))),
+    ///     (VarLabel::new(2), (RealSemiring(0.9), RealSemiring(0.1))),
+    /// ]));
+    /// let (weight, model) = builder.mpe(f, &weights);
+    ///
+    /// assert!((weight - 0.6 * 0.7 * 0.9).abs() < 1e-9);
+    /// assert_eq!(model.get(VarLabel::new(0)), Some(false));
+    /// assert_eq!(model.get(VarLabel::new(1)), Some(true));
+    /// assert_eq!(model.get(VarLabel::new(2)), Some(false));
+    /// ```
+    pub fn mpe(&'a self, ptr: BddPtr<'a>, wmc: &WmcParams<RealSemiring>) -> (f64, PartialModel) {
+        let (value, model) = self.max_path(ptr, |v| {
+            let (low_w, high_w) = wmc.var_weight(v);
+            (ViterbiSemiring(low_w.0), ViterbiSemiring(high_w.0))
+        });
+        (value.0, model)
+    }
+
+    /// Computes the satisfying total assignment of `ptr` with the largest
+    /// sum of literal weights in the max-plus semiring, along with that sum:
+    /// the additive-reward analog of `mpe`. Variables that do not affect
+    /// `ptr` are set to their higher-weight polarity; ties are broken toward
+    /// `true`. If `ptr` is unsatisfiable, returns negative infinity and a
+    /// model with no variables set.
+    ///
+    /// Every variable in the builder must have a weight in `wmc`.
+    ///
+    /// Pre-condition: scratch cleared
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::BottomUpBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, VarLabel, WmcParams};
+    /// # use rsdd::util::semirings::Tropical;
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+    /// let a = builder.var(VarLabel::new(0), true);
+    /// let b = builder.var(VarLabel::new(1), true);
+    /// let f = builder.xor(a, b);
+    ///
+    /// let weights = WmcParams::from_slice(&[
+    ///     (Tropical(1.0), Tropical(3.0)),
+    ///     (Tropical(0.0), Tropical(5.0)),
+    /// ]);
+    /// let (reward, model) = builder.longest_weighted_path(f, &weights);
+    ///
+    /// // a = false, b = true beats a = true, b = false
+    /// assert_eq!(reward, 6.0);
+    /// assert_eq!(model.get(VarLabel::new(0)), Some(false));
+    /// assert_eq!(model.get(VarLabel::new(1)), Some(true));
+    /// ```
+    pub fn longest_weighted_path(
+        &'a self,
+        ptr: BddPtr<'a>,
+        wmc: &WmcParams<Tropical>,
+    ) -> (f64, PartialModel) {
+        if ptr.is_false() {
+            return (f64::NEG_INFINITY, PartialModel::new(self.num_vars()));
+        }
+        let (value, model) = self.max_path(ptr, |v| *wmc.var_weight(v));
+        (value.0, model)
+    }
+
+    /// Solves a weighted MaxSAT problem: finds the total assignment to the
+    /// builder's variables that maximizes the summed weight of the clauses it
+    /// satisfies, returning that sum and the assignment. Weights may be
+    /// negative, in which case satisfying the clause is a penalty.
+    ///
+    /// Each clause is given a fresh indicator variable, constrained to be
+    /// equivalent to the clause and weighted by its reward, and the best path
+    /// through the conjunction of these constraints is found with a max-plus
+    /// fold. This happens in a scratch builder with the same order followed by
+    /// the indicators, so this builder is left unchanged.
+    ///
+    /// Pre-condition: scratch cleared
+    /// ```
+    /// # use rsdd::builder::bdd::RobddBuilder;
+    /// # use rsdd::builder::cache::AllIteTable;
+    /// # use rsdd::repr::{BddPtr, Literal, VarLabel};
+    /// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(1);
+    /// let a = VarLabel::new(0);
+    /// let clauses = [
+    ///     (vec![Literal::new(a, true)], 2.0),
+    ///     (vec![Literal::new(a, false)], 3.0),
+    /// ];
+    /// let (reward, model) = builder.weighted_maxsat(&clauses);
+    /// assert_eq!(reward, 3.0);
+    /// assert_eq!(model.get(a), Some(false));
+    /// ```
+    pub fn weighted_maxsat(&'a self, clauses: &[(Vec<Literal>, f64)]) -> (f64, PartialModel) {
+        let num_vars = self.num_vars();
+        let mut order = self.order.borrow().clone();
+        let mut rewards = HashMap::new();
+        let mut constraints = Vec::new();
+        for (clause, w) in clauses {
+            let indicator = order.new_last();
+            rewards.insert(indicator, *w);
+            constraints.push((indicator, clause));
+        }
+        let scratch = RobddBuilder::<AllIteTable<BddPtr>>::new(order, None);
+        let objective =
+            constraints
+                .into_iter()
+                .fold(BddPtr::true_ptr(), |acc, (indicator, clause)| {
+                    let s = scratch.var(indicator, true);
+                    scratch.and(acc, scratch.iff(s, scratch.clause(clause)))
+                });
+        let (value, model) = scratch.max_path(objective, |v| {
+            (
+                Tropical(0.0),
+                Tropical(rewards.get(&v).copied().unwrap_or(0.0)),
+            )
+        });
+        let mut assignment = PartialModel::new(num_vars);
+        for i in 0..num_vars {
+            let v = VarLabel::new_usize(i);
+            assignment.set(v, model.get(v).unwrap());
+        }
+        (value.0, assignment)
+    }
+
+    /// Apply-cache hits and misses are counted since the builder was created
+    /// or last garbage-collected, which resets the apply cache.
+    pub fn stats(&'a self) -> BddBuilderStats {
+        let apply_stats = self.apply_table.borrow().stats();
+        BddBuilderStats {
+            num_recursive_calls: self.stats.borrow().num_recursive_calls,
+            apply_cache_hits: apply_stats.hits,
+            apply_cache_misses: apply_stats.misses,
+            apply_cache_evictions: apply_stats.evictions,
+            num_table_resizes: self.compute_table.borrow().num_grows(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use crate::builder::BottomUpBuilder;
+    use crate::repr::WmcParams;
+    use crate::util::semirings::{
+        BooleanExprSemiring, ComplexSemiring, DynFiniteField, FiniteField, HyperDual,
+        IntervalSemiring, RationalSemiring, RealSemiring, Semiring, Tropical, ViterbiSemiring,
+    };
+    use crate::{builder::cache::AllIteTable, repr::DDNNFPtr};
+
+    use crate::{
+        builder::bdd::{
+            robdd::{order_clauses, ClauseOrder, RobddBuilder, TimeoutError, ZeroEvidenceError},
+            BddBuilder, BinOp,
+        },
+        repr::{BddNode, BddPtr, Cnf, Expr, Literal, PartialModel, VarLabel, VarOrder, VarSet},
+    };
+
+    // check that (a \/ b) /\ a === a
+    #[test]
+    fn simple_equality() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let v1 = builder.var(VarLabel::new(0), true);
+        let v2 = builder.var(VarLabel::new(1), true);
+        let r1 = builder.or(v1, v2);
+        let r2 = builder.and(r1, v1);
+        assert!(
+            builder.eq(v1, r2),
+            "Not eq:\n {}\n{}",
+            v1.to_string_debug(),
+            r2.to_string_debug()
+        );
+    }
+
+    #[test]
+    fn simple_ite1() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let v1 = builder.var(VarLabel::new(0), true);
+        let v2 = builder.var(VarLabel::new(1), true);
+        let r1 = builder.or(v1, v2);
+        let r2 = builder.ite(r1, v1, BddPtr::false_ptr());
+        assert!(
+            builder.eq(v1, r2),
+            "Not eq:\n {}\n{}",
+            v1.to_string_debug(),
+            r2.to_string_debug()
+        );
+    }
+
+    #[test]
+    fn test_newvar() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(0);
+        let l1 = builder.new_label();
+        let l2 = builder.new_label();
+        let v1 = builder.var(l1, true);
+        let v2 = builder.var(l2, true);
+        let r1 = builder.or(v1, v2);
+        let r2 = builder.and(r1, v1);
+        assert!(
+            builder.eq(v1, r2),
+            "Not eq:\n {}\n{}",
+            v1.to_string_debug(),
+            r2.to_string_debug()
+        );
+    }
+
+    #[test]
+    fn test_wmc() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+        let v1 = builder.var(VarLabel::new(0), true);
+        let v2 = builder.var(VarLabel::new(1), true);
+        let r1 = builder.or(v1, v2);
+        let weights = HashMap::from_iter([
+            (VarLabel::new(0), (RealSemiring(0.2), RealSemiring(0.8))),
+            (VarLabel::new(1), (RealSemiring(0.1), RealSemiring(0.9))),
+        ]);
+        let params = WmcParams::new(weights);
+        let wmc = r1.unsmoothed_wmc(&params);
+        assert!((wmc.0 - (1.0 - 0.2 * 0.1)).abs() < 0.000001);
+    }
+
+    #[test]
+    fn test_condition() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let v1 = builder.var(VarLabel::new(0), true);
+        let v2 = builder.var(VarLabel::new(1), true);
+        let r1 = builder.or(v1, v2);
+        let r3 = builder.condition(r1, VarLabel::new(1), false);
+        assert!(builder.eq(r3, v1));
+    }
+
+    #[test]
+    fn test_condition_compl() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let v1 = builder.var(VarLabel::new(0), false);
+        let v2 = builder.var(VarLabel::new(1), false);
+        let r1 = builder.and(v1, v2);
+        let r3 = builder.condition(r1, VarLabel::new(1), false);
+        assert!(
+            builder.eq(r3, v1),
+            "Not eq:\nOne: {}\nTwo: {}",
+            r3.to_string_debug(),
+            v1.to_string_debug()
+        );
+    }
+
+    #[test]
+    fn test_exist() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        // 1 /\ 2 /\ 3
+        let v1 = builder.var(VarLabel::new(0), true);
+        let v2 = builder.var(VarLabel::new(1), true);
+        let v3 = builder.var(VarLabel::new(2), true);
+        let a1 = builder.and(v1, v2);
+        let r1 = builder.and(a1, v3);
+        let r_expected = builder.and(v1, v3);
+        let res = builder.exists(r1, VarLabel::new(1));
+        assert!(
+            builder.eq(r_expected, res),
+            "Got:\nOne: {}\nExpected: {}",
+            res.to_string_debug(),
+            r_expected.to_string_debug()
+        );
+    }
+
+    #[test]
+    fn test_exist_compl() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        // 1 /\ 2 /\ 3
+        let v1 = builder.var(VarLabel::new(0), false);
+        let v2 = builder.var(VarLabel::new(1), false);
+        let v3 = builder.var(VarLabel::new(2), false);
+        let a1 = builder.and(v1, v2);
+        let r1 = builder.and(a1, v3);
+        let r_expected = builder.and(v1, v3);
+        let res = builder.exists(r1, VarLabel::new(1));
+        // let res = r1;
+        assert!(
+            builder.eq(r_expected, res),
+            "Got:\n: {}\nExpected: {}",
+            res.to_string_debug(),
+            r_expected.to_string_debug()
+        );
+    }
+
+    #[test]
+    fn test_compose() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let v0 = builder.var(VarLabel::new(0), true);
+        let v1 = builder.var(VarLabel::new(1), true);
+        let v2 = builder.var(VarLabel::new(2), true);
+        let v0_and_v1 = builder.and(v0, v1);
+        let v0_and_v2 = builder.and(v0, v2);
+        let res = builder.compose(v0_and_v1, VarLabel::new(1), v2);
+        assert!(
+            builder.eq(res, v0_and_v2),
+            "\nGot: {}\nExpected: {}",
+            res.to_string_debug(),
+            v0_and_v2.to_string_debug()
+        );
+    }
+
+    #[test]
+    fn test_compose_2() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let v0 = builder.var(VarLabel::new(0), true);
+        let v1 = builder.var(VarLabel::new(1), true);
+        let v2 = builder.var(VarLabel::new(2), true);
+        let v3 = builder.var(VarLabel::new(3), true);
+        let v0_and_v1 = builder.and(v0, v1);
+        let v2_and_v3 = builder.and(v2, v3);
+        let v0v2v3 = builder.and(v0, v2_and_v3);
+        let res = builder.compose(v0_and_v1, VarLabel::new(1), v2_and_v3);
+        assert!(
+            builder.eq(res, v0v2v3),
+            "\nGot: {}\nExpected: {}",
+            res.to_string_debug(),
+            v0v2v3.to_string_debug()
+        );
+    }
+
+    #[test]
+    fn test_compose_3() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let v0 = builder.var(VarLabel::new(0), true);
+        let v1 = builder.var(VarLabel::new(1), true);
+        let v2 = builder.var(VarLabel::new(2), true);
+        let f = builder.ite(v0, BddPtr::false_ptr(), v1);
+        let res = builder.compose(f, VarLabel::new(1), v2);
+        let expected = builder.ite(v0, BddPtr::false_ptr(), v2);
+        assert!(
+            builder.eq(res, expected),
+            "\nGot: {}\nExpected: {}",
+            res.to_string_debug(),
+            expected.to_string_debug()
+        );
+    }
+
+    #[test]
+    fn test_compose_4() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(20);
+        let v0 = builder.var(VarLabel::new(4), true);
+        let v1 = builder.var(VarLabel::new(5), true);
+        let v2 = builder.var(VarLabel::new(6), true);
+        let f = builder.ite(v1, BddPtr::false_ptr(), v2);
+        let res = builder.compose(f, VarLabel::new(6), v0);
+        let expected = builder.ite(v1, BddPtr::false_ptr(), v0);
+        assert!(
+            builder.eq(res, expected),
+            "\nGot: {}\nExpected: {}",
+            res.to_string_debug(),
+            expected.to_string_debug()
+        );
+    }
+
+    #[test]
+    fn test_new_label() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(0);
+        let vlbl1 = builder.new_label();
+        let vlbl2 = builder.new_label();
+        let v1 = builder.var(vlbl1, false);
+        let v2 = builder.var(vlbl2, false);
+        let r1 = builder.and(v1, v2);
+        let r3 = builder.condition(r1, VarLabel::new(1), false);
+        assert!(
+            builder.eq(r3, v1),
+            "Not eq:\nOne: {}\nTwo: {}",
+            r3.to_string_debug(),
+            v1.to_string_debug()
+        );
+    }
+
+    #[test]
+    fn circuit1() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let x = builder.var(VarLabel::new(0), false);
+        let y = builder.var(VarLabel::new(1), true);
+        let delta = builder.and(x, y);
+        let yp = builder.var(VarLabel::new(2), true);
+        let inner = builder.iff(yp, y);
+        let conj = builder.and(inner, delta);
+        let res = builder.exists(conj, VarLabel::new(1));
+
+        let expected = builder.and(x, yp);
+        assert!(
+            builder.eq(res, expected),
+            "Not eq:\nGot: {}\nExpected: {}",
+            res.to_string_debug(),
+            expected.to_string_debug()
+        );
+    }
+
+    #[test]
+    fn simple_cond() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let x = builder.var(VarLabel::new(0), true);
+        let y = builder.var(VarLabel::new(1), false);
+        let z = builder.var(VarLabel::new(2), false);
+        let r1 = builder.and(x, y);
+        let r2 = builder.and(r1, z);
+        // now r2 is x /\ !y /\ !z
+
+        let res = builder.condition(r2, VarLabel::new(1), true); // condition on y=T
+        let expected = BddPtr::false_ptr();
+        assert!(
+            builder.eq(res, expected),
+            "\nOriginal BDD: {}\nNot eq:\nGot: {}\nExpected: {}",
+            r2.to_string_debug(),
+            res.to_string_debug(),
+            expected.to_string_debug()
+        );
+    }
+
+    #[test]
+    fn wmc_test_2() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let x = builder.var(VarLabel::new(0), true);
+        let y = builder.var(VarLabel::new(1), true);
+        let f1 = builder.var(VarLabel::new(2), true);
+        let f2 = builder.var(VarLabel::new(3), true);
+
+        let map = HashMap::from_iter([
+            (VarLabel::new(0), (RealSemiring(1.0), RealSemiring(1.0))),
+            (VarLabel::new(1), (RealSemiring(1.0), RealSemiring(1.0))),
+            (VarLabel::new(2), (RealSemiring(0.8), RealSemiring(0.2))),
+            (VarLabel::new(3), (RealSemiring(0.7), RealSemiring(0.3))),
+        ]);
+
+        let wmc = WmcParams::new(map);
+        let iff1 = builder.iff(x, f1);
+        let iff2 = builder.iff(y, f2);
+        let obs = builder.or(x, y);
+        let and1 = builder.and(iff1, iff2);
+        let f = builder.and(and1, obs);
+        assert_eq!(f.unsmoothed_wmc(&wmc).0, 0.2 * 0.3 + 0.2 * 0.7 + 0.8 * 0.3);
+    }
+
+    #[test]
+    fn test_ite_1() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(16);
+        let c1 = Cnf::from_string("(1 || 2) && (0 || -2)");
+        let c2 = Cnf::from_string("(0 || 1) && (-4 || -7)");
+        let cnf1 = builder.compile_cnf(&c1);
+        let cnf2 = builder.compile_cnf(&c2);
+        let iff1 = builder.iff(cnf1, cnf2);
+
+        let clause1 = builder.and(cnf1, cnf2);
+        let clause2 = builder.and(cnf1.neg(), cnf2.neg());
+        let and = builder.or(clause1, clause2);
+
+        if and != iff1 {
+            println!("cnf1: {}", c1);
+            println!("cnf2: {}", c2);
+            println!(
+                "not equal:\nBdd1: {}\nBdd2: {}",
+                and.to_string_debug(),
+                iff1.to_string_debug()
+            );
+        }
+        assert_eq!(and, iff1);
+    }
+
+    #[test]
+    fn test_apply_binop_truth_tables() {
+        type TruthTable = fn(bool, bool) -> bool;
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+        let a = builder.var(VarLabel::new(0), true);
+        let b = builder.var(VarLabel::new(1), true);
+        let ops: [(BinOp, TruthTable); 7] = [
+            (BinOp::And, |x, y| x && y),
+            (BinOp::Or, |x, y| x || y),
+            (BinOp::Xor, |x, y| x ^ y),
+            (BinOp::Nand, |x, y| !(x && y)),
+            (BinOp::Nor, |x, y| !(x || y)),
+            (BinOp::Imp, |x, y| !x || y),
+            (BinOp::Iff, |x, y| x == y),
+        ];
+        for (op, expected) in ops {
+            let r = builder.apply_binop(op, a, b);
+            for x in [false, true] {
+                for y in [false, true] {
+                    assert_eq!(
+                        r.evaluate(&[x, y]),
+                        expected(x, y),
+                        "{:?}({}, {})",
+                        op,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+        assert_eq!(builder.imp(a, b), builder.apply_binop(BinOp::Imp, a, b));
+        assert_eq!(builder.xor(a, b), builder.apply_binop(BinOp::Xor, a, b));
+    }
+
+    #[test]
+    fn test_rename_vars() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let v: Vec<BddPtr> = (0..4)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        let f = builder.or(builder.and(v[0], v[1].neg()), v[1]);
+        let expected = builder.or(builder.and(v[2], v[3].neg()), v[3]);
+        let map = HashMap::from([
+            (VarLabel::new(0), VarLabel::new(2)),
+            (VarLabel::new(1), VarLabel::new(3)),
+        ]);
+        assert_eq!(builder.rename_vars(f, &map), expected);
+
+        // a renaming that reverses the order must still produce the same function
+        let g = builder.compile_cnf(&Cnf::from_string("(1 || 2) && (-2 || 3)"));
+        let swapped = builder.compile_cnf(&Cnf::from_string("(3 || 2) && (-2 || 1)"));
+        let map = HashMap::from([
+            (VarLabel::new(1), VarLabel::new(3)),
+            (VarLabel::new(3), VarLabel::new(1)),
+        ]);
+        assert_eq!(builder.rename_vars(g, &map), swapped);
+    }
+
+    #[test]
+    fn test_smooth_over_sparse_support() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(10);
+        let v2 = builder.var(VarLabel::new(2), true);
+        let v5 = builder.var(VarLabel::new(5), true);
+        let v7 = builder.var(VarLabel::new(7), true);
+        let f = builder.or(builder.and(v2, v5), v7);
+
+        let mut vars = VarSet::new();
+        for v in [2, 5, 7] {
+            vars.insert(VarLabel::new(v));
+        }
+        let smoothed = builder.smooth_over(f, &vars);
+
+        let weights = WmcParams::new(HashMap::from_iter(
+            (0..10).map(|v| (VarLabel::new(v), (RealSemiring(0.3), RealSemiring(0.5)))),
+        ));
+        // brute-force sum over assignments to the three variables
+        let mut expected = 0.0;
+        for assgn in 0..8 {
+            let (a, b, c) = (assgn & 1 == 1, assgn & 2 == 2, assgn & 4 == 4);
+            if (a && b) || c {
+                expected += [a, b, c]
+                    .iter()
+                    .map(|&x| if x { 0.5 } else { 0.3 })
+                    .product::<f64>();
+            }
+        }
+        assert!((smoothed.unsmoothed_wmc(&weights).0 - expected).abs() < 1e-9);
+        assert!((f.unsmoothed_wmc(&weights).0 - expected).abs() > 1e-3);
+        // only the requested variables are mentioned
+        assert!(!builder.has_variable(smoothed, VarLabel::new(3)));
+    }
+
+    #[test]
+    fn test_interval_wmc_brackets_real_wmc() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(5);
+        let bdd = builder.compile_cnf(&Cnf::from_string("(1 || 2) && (-2 || 3 || -4) && (1 || 4)"));
+        // variable 0 is unused
+        let point = [(0.5, 0.5), (0.3, 0.7), (0.6, 0.4), (0.5, 0.5), (0.2, 0.8)];
+        let eps = 0.05;
+
+        let real =
+            WmcParams::new(HashMap::from_iter(point.iter().enumerate().map(
+                |(v, &(l, h))| (VarLabel::new_usize(v), (RealSemiring(l), RealSemiring(h))),
+            )));
+        let interval = WmcParams::new(HashMap::from_iter(point.iter().enumerate().map(
+            |(v, &(l, h))| {
+                (
+                    VarLabel::new_usize(v),
+                    (
+                        IntervalSemiring(l - eps, l + eps),
+                        IntervalSemiring(h - eps, h + eps),
+                    ),
+                )
+            },
+        )));
+
+        let exact = bdd.unsmoothed_wmc(&real).0;
+        let bounds = bdd.unsmoothed_wmc(&interval);
+        assert!(bounds.lo() < bounds.hi());
+        assert!(bounds.contains(exact), "{} not in {}", exact, bounds);
+    }
+
+    #[test]
+    fn test_hyperdual_hessian_finite_differences() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+        let x = builder.var(VarLabel::new(0), true);
+        let y = builder.var(VarLabel::new(1), true);
+        let bdd = builder.or(builder.and(x, y), builder.and(x.neg(), y.neg()));
+        let params = [0.3, 0.8];
+
+        // each variable is weighted (1 - p, p)
+        let wmc_at = |p: [f64; 2]| {
+            let w = WmcParams::new(HashMap::from_iter((0..2).map(|v| {
+                (
+                    VarLabel::new_usize(v),
+                    (RealSemiring(1.0 - p[v]), RealSemiring(p[v])),
+                )
+            })));
+            bdd.unsmoothed_wmc(&w).0
+        };
+        let w = WmcParams::new(HashMap::from_iter((0..2).map(|v| {
+            let p = HyperDual::<2>::param(params[v], v);
+            (VarLabel::new_usize(v), (HyperDual::constant(1.0) - p, p))
+        })));
+        let r = bdd.unsmoothed_wmc(&w);
+        assert!((r.real - wmc_at(params)).abs() < 1e-9);
+
+        let h = 1e-3;
+        let shifted = |di: f64, dj: f64, i: usize, j: usize| {
+            let mut p = params;
+            p[i] += di;
+            p[j] += dj;
+            wmc_at(p)
+        };
+        for i in 0..2 {
+            for j in 0..2 {
+                let fd = (shifted(h, h, i, j) - shifted(h, -h, i, j) - shifted(-h, h, i, j)
+                    + shifted(-h, -h, i, j))
+                    / (4.0 * h * h);
+                assert!(
+                    (r.hessian[i][j] - fd).abs() < 1e-4,
+                    "hessian[{}][{}]: {} vs {}",
+                    i,
+                    j,
+                    r.hessian[i][j],
+                    fd
+                );
+            }
+        }
+        assert_eq!(r.hessian[0][1], 2.0);
+    }
+
+    #[test]
+    fn test_mpe_brute_force() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(5);
+        let bdd = builder.compile_cnf(&Cnf::from_string(
+            "(1 || 2 || -3) && (-1 || 4) && (-2 || -4)",
+        ));
+        let weights = [(0.5, 0.5), (0.3, 0.7), (0.55, 0.45), (0.8, 0.2), (0.1, 0.9)];
+        let wmc = WmcParams::new(HashMap::from_iter(weights.iter().enumerate().map(
+            |(v, &(l, h))| (VarLabel::new_usize(v), (RealSemiring(l), RealSemiring(h))),
+        )));
+
+        let (best, model) = builder.mpe(bdd, &wmc);
+
+        // brute-force argmax over all 32 assignments, preferring later
+        // (i.e., more-true) assignments on ties
+        let mut expected = (0.0, vec![]);
+        for bits in 0..(1 << weights.len()) {
+            let assgn: Vec<bool> = (0..weights.len()).map(|v| bits & (1 << v) != 0).collect();
+            if !bdd.evaluate(&assgn) {
+                continue;
+            }
+            let w: f64 = assgn
+                .iter()
+                .zip(weights.iter())
+                .map(|(&b, &(l, h))| if b { h } else { l })
+                .product();
+            if w >= expected.0 {
+                expected = (w, assgn);
+            }
+        }
+        assert!((best - expected.0).abs() < 1e-12);
+        for (v, b) in expected.1.iter().enumerate() {
+            assert_eq!(model.get(VarLabel::new_usize(v)), Some(*b));
+        }
+        // variable 0 has a tied weight and does not appear in the formula
+        assert_eq!(model.get(VarLabel::new(0)), Some(true));
+    }
+
+    #[test]
+    fn test_longest_weighted_path() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        // exactly one of variables 1, 2, and 3 holds; variable 0 is unused
+        let bdd = builder.compile_cnf(&Cnf::from_string(
+            "(-1 || -2) && (-1 || -3) && (-2 || -3) && (1 || 2 || 3)",
+        ));
+        let weights = WmcParams::from_slice(&[
+            (Tropical(0.0), Tropical(-1.0)),
+            (Tropical(2.0), Tropical(4.0)),
+            (Tropical(1.0), Tropical(6.0)),
+            (Tropical(0.5), Tropical(3.5)),
+        ]);
+        let (reward, model) = builder.longest_weighted_path(bdd, &weights);
+        // setting variable 1, 2, or 3 earns 5.5, 8.5, or 6.5 respectively,
+        // and variable 0 earns the most when false
+        assert_eq!(reward, 0.0 + 2.0 + 6.0 + 0.5);
+        let expected = [false, false, true, false];
+        for (v, b) in expected.iter().enumerate() {
+            assert_eq!(model.get(VarLabel::new_usize(v)), Some(*b));
+        }
+
+        let (reward, model) = builder.longest_weighted_path(BddPtr::PtrFalse, &weights);
+        assert_eq!(reward, f64::NEG_INFINITY);
+        assert!(model.assignment_iter().next().is_none());
+    }
+
+    #[test]
+    fn test_viterbi_wmc_brute_force() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(5);
+        let bdd = builder.compile_cnf(&Cnf::from_string(
+            "(1 || -2) && (2 || 3 || 4) && (-1 || -4)",
+        ));
+        let weights = [(0.5, 0.5), (0.3, 0.7), (0.6, 0.4), (0.45, 0.55), (0.8, 0.2)];
+        let wmc = WmcParams::new(HashMap::from_iter(weights.iter().enumerate().map(
+            |(v, &(l, h))| {
+                (
+                    VarLabel::new_usize(v),
+                    (ViterbiSemiring(l), ViterbiSemiring(h)),
+                )
+            },
+        )));
+
+        let mut expected: f64 = 0.0;
+        for bits in 0..(1 << weights.len()) {
+            let assgn: Vec<bool> = (0..weights.len()).map(|v| bits & (1 << v) != 0).collect();
+            if bdd.evaluate(&assgn) {
+                let w = assgn
+                    .iter()
+                    .zip(weights.iter())
+                    .map(|(&b, &(l, h))| if b { h } else { l })
+                    .product();
+                expected = expected.max(w);
+            }
+        }
+
+        // every variable must appear on every path for the max to be exact
+        let mut all_vars = VarSet::new();
+        for v in 0..weights.len() {
+            all_vars.insert(VarLabel::new_usize(v));
+        }
+        let smoothed = builder.smooth_over(bdd, &all_vars);
+        assert!((smoothed.unsmoothed_wmc(&wmc).0 - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_top_k_models() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(5);
+        let bdd = builder.compile_cnf(&Cnf::from_string("(1 || 2 || 3) && (-2 || 4) && (3 || -4)"));
+        let wmc = WmcParams::new(HashMap::from_iter((0..5).map(|v| {
+            let p = 0.15 * (v as f64) + 0.1;
+            (VarLabel::new(v), (RealSemiring(1.0 - p), RealSemiring(p)))
+        })));
+
+        let k = 3;
+        let models = builder.top_k_models(bdd, k, &wmc);
+        assert_eq!(models.len(), k);
+        assert!(models.windows(2).all(|w| w[0].1 >= w[1].1));
+
+        // every returned path lies within both the original and top-k BDDs
+        let top_k = builder.top_k_paths(bdd, k, &wmc);
+        for (model, _) in &models {
+            assert_eq!(builder.restrict(bdd, model), BddPtr::PtrTrue);
+            assert_eq!(builder.restrict(top_k, model), BddPtr::PtrTrue);
+        }
+    }
+
+    #[test]
+    fn test_bottom_k_paths_brute_force() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let bdd = builder.compile_cnf(&Cnf::from_string("(1 || 2) && (-1 || 3)"));
+        // variable 2 has a zero-weight low edge, so paths through it are skipped
+        let weights = [(0.5, 0.5), (0.2, 0.8), (0.35, 0.65), (0.0, 1.0)];
+        let wmc = WmcParams::new(HashMap::from_iter(weights.iter().enumerate().map(
+            |(v, &(l, h))| (VarLabel::new_usize(v), (RealSemiring(l), RealSemiring(h))),
+        )));
+
+        // smooth so that every accepting path decides variables 1, 2, and 3
+        let mut vars = VarSet::new();
+        for v in 1..4 {
+            vars.insert(VarLabel::new(v));
+        }
+        let bdd = builder.smooth_over(bdd, &vars);
+        let mut paths: Vec<(f64, u32)> = (0..8u32)
+            .map(|bits| {
+                let (a, b, c) = (bits & 1 != 0, bits & 2 != 0, bits & 4 != 0);
+                let w = [(a, weights[1]), (b, weights[2]), (c, weights[3])]
+                    .iter()
+                    .map(|&(x, (l, h))| if x { h } else { l })
+                    .product::<f64>();
+                (w, bits)
+            })
+            .filter(|&(w, bits)| {
+                let (a, b, c) = (bits & 1 != 0, bits & 2 != 0, bits & 4 != 0);
+                w != 0.0 && bdd.evaluate(&[false, a, b, c])
+            })
+            .collect();
+        paths.sort_by(|x, y| x.0.partial_cmp(&y.0).unwrap());
+
+        // the weights of each variable sum to 1, so the WMC of the result is
+        // the sum of the weights of its paths
+        let k = 2;
+        let bottom_k = builder.bottom_k_paths(bdd, k, &wmc);
+        let expected = paths[..k].iter().map(|x| x.0).sum::<f64>();
+        assert!((bottom_k.unsmoothed_wmc(&wmc).0 - expected).abs() < 1e-12);
+        // requesting more paths than exist returns every non-zero path
+        let all = builder.bottom_k_paths(bdd, 100, &wmc);
+        let total = paths.iter().map(|x| x.0).sum::<f64>();
+        assert!((all.unsmoothed_wmc(&wmc).0 - total).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_all_marginals_eq_conditioning() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(6);
+        let bdd = builder.compile_cnf(&Cnf::from_string(
+            "(1 || -2 || 3) && (-1 || 4) && (2 || -3 || -4) && (-4 || -3)",
+        ));
+        // variables 0 and 5 do not appear in the formula
+        let wmc = WmcParams::new(HashMap::from_iter((0..6).map(|v| {
+            let p = 0.12 * (v as f64) + 0.2;
+            (VarLabel::new(v), (RealSemiring(1.0 - p), RealSemiring(p)))
+        })));
+
+        let marginals = builder.all_marginals(bdd, &wmc);
+        let z = bdd.unsmoothed_wmc(&wmc).0;
+        for v in 0..6 {
+            let label = VarLabel::new(v);
+            let conditioned = builder.and(bdd, builder.var(label, true));
+            let expected = conditioned.unsmoothed_wmc(&wmc).0 / z;
+            assert!(
+                (marginals[&label] - expected).abs() < 1e-9,
+                "marginal of {}: {} vs {}",
+                v,
+                marginals[&label],
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_wmc_given_eq_manual() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(6);
+        let bdd = builder.compile_cnf(&Cnf::from_string(
+            "(1 || -2 || 3) && (-1 || 4) && (2 || -3 || -4) && (-4 || -3)",
+        ));
+        // weights that don't sum to one, so that summing over an observed
+        // variable would be detected
+        let wmc = WmcParams::new(HashMap::from_iter((0..6).map(|v| {
+            let p = 0.12 * (v as f64) + 0.2;
+            (VarLabel::new(v), (RealSemiring(1.1 - p), RealSemiring(p)))
+        })));
+        let mut all_vars = VarSet::new();
+        (0..6).for_each(|v| all_vars.insert(VarLabel::new(v)));
+
+        for assignments in [
+            vec![Some(true), None, None, None, None, None],
+            vec![None, Some(false), None, Some(true), None, None],
+            vec![None, None, Some(true), None, None, Some(false)],
+            vec![Some(false), Some(true), Some(true), Some(false), None, None],
+        ] {
+            let evidence = PartialModel::from_assignments(&assignments);
+            // manually: conjoin the evidence cube, smooth over everything,
+            // and count
+            let cube = evidence
+                .assignment_iter()
+                .fold(BddPtr::PtrTrue, |acc, lit| {
+                    builder.and(acc, builder.var(lit.label(), lit.polarity()))
+                });
+            let conjoined = builder.smooth_over(builder.and(bdd, cube), &all_vars);
+            let expected = conjoined.unsmoothed_wmc(&wmc).0;
+            let got = builder.wmc_given(bdd, &evidence, &wmc);
+            assert!(
+                (got - expected).abs() < 1e-9,
+                "{:?}: {} vs {}",
+                assignments,
+                got,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_conditional_prob_sprinkler() {
+        // rain (0) and sprinkler (1) are independent causes of wet grass (2)
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let rain = builder.var(VarLabel::new(0), true);
+        let sprinkler = builder.var(VarLabel::new(1), true);
+        let wet = builder.var(VarLabel::new(2), true);
+        let bn = builder.iff(wet, builder.or(rain, sprinkler));
+        let wmc = WmcParams::new(HashMap::from([
+            (VarLabel::new(0), (RealSemiring(0.8), RealSemiring(0.2))),
+            (VarLabel::new(1), (RealSemiring(0.6), RealSemiring(0.4))),
+            (VarLabel::new(2), (RealSemiring(1.0), RealSemiring(1.0))),
+        ]));
+        let query_rain = PartialModel::from_assignments(&[Some(true), None, None]);
+
+        // no evidence: the prior
+        let none = PartialModel::from_assignments(&[None, None, None]);
+        let p = builder.conditional_prob(bn, &query_rain, &none, &wmc);
+        assert!((p.unwrap() - 0.2).abs() < 1e-9);
+
+        // P(rain | wet) = 0.2 / (1 - 0.8 * 0.6)
+        let wet_ev = PartialModel::from_assignments(&[None, None, Some(true)]);
+        let p = builder.conditional_prob(bn, &query_rain, &wet_ev, &wmc);
+        assert!((p.unwrap() - 0.2 / 0.52).abs() < 1e-9);
+
+        // explaining away: P(rain | wet, sprinkler) = P(rain)
+        let both = PartialModel::from_assignments(&[None, Some(true), Some(true)]);
+        let p = builder.conditional_prob(bn, &query_rain, &both, &wmc);
+        assert!((p.unwrap() - 0.2).abs() < 1e-9);
+
+        // P(!rain | rain, wet) = 0
+        let rain_wet = PartialModel::from_assignments(&[Some(true), None, Some(true)]);
+        let query_dry = PartialModel::from_assignments(&[Some(false), None, None]);
+        let p = builder.conditional_prob(bn, &query_dry, &rain_wet, &wmc);
+        assert_eq!(p, Ok(0.0));
+
+        // rain but not wet is impossible
+        let impossible = PartialModel::from_assignments(&[Some(true), None, Some(false)]);
+        let p = builder.conditional_prob(bn, &query_rain, &impossible, &wmc);
+        assert_eq!(p, Err(ZeroEvidenceError));
+    }
+
+    #[test]
+    fn test_count_nodes_with_opts() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let a = builder.var(VarLabel::new(0), true);
+        let b = builder.var(VarLabel::new(1), true);
+        let c = builder.var(VarLabel::new(2), true);
+        // a xor b xor c needs one node for a and two each for b and c, but
+        // complemented edges share the b and c nodes between polarities
+        let f = builder.xor(builder.xor(a, b), c);
+        assert_eq!(f.count_nodes_with_opts(false), 3);
+        // the c nodes have both a true and a false child
+        assert_eq!(f.count_nodes_with_opts(true), 5);
+        assert_eq!(f.count_nodes_with_opts(false), f.count_nodes());
+        assert_eq!(a.count_nodes_with_opts(true), 3);
+        // a hand-built, unreduced node reaches only one terminal
+        let n = BddNode::new(VarLabel::new(0), BddPtr::PtrTrue, BddPtr::PtrTrue);
+        assert_eq!(BddPtr::Reg(&n).count_nodes_with_opts(true), 2);
+        assert_eq!(BddPtr::true_ptr().count_nodes_with_opts(false), 0);
+        assert_eq!(BddPtr::false_ptr().count_nodes_with_opts(true), 1);
+    }
+
+    #[test]
+    fn test_path_count() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let a = builder.var(VarLabel::new(0), true);
+        let b = builder.var(VarLabel::new(1), true);
+        let c = builder.var(VarLabel::new(2), true);
+        // a | c: the path a=1 skips c and the path a=0 skips b, so there are
+        // two paths but six models over three variables
+        let f = builder.or(a, c);
+        assert_eq!(f.path_count(), 2);
+        assert_eq!(builder.model_count(f), 6);
+        // the negation's true paths are f's false paths
+        assert_eq!(f.neg().path_count(), 1);
+        assert_eq!(builder.model_count(f.neg()), 2);
+        // parity reaches every variable on every path
+        let g = builder.xor(builder.xor(a, b), c);
+        assert_eq!(g.path_count(), 4);
+        assert_eq!(g.neg().path_count(), 4);
+        assert_eq!(BddPtr::true_ptr().path_count(), 1);
+        assert_eq!(BddPtr::false_ptr().path_count(), 0);
+    }
+
+    /// parity over `n` variables, which has `2^(n-1)` paths
+    fn parity<'a>(builder: &'a RobddBuilder<'a, AllIteTable<BddPtr<'a>>>, n: u64) -> BddPtr<'a> {
+        (0..n).fold(BddPtr::false_ptr(), |acc, v| {
+            builder.xor(acc, builder.var(VarLabel::new(v), true))
+        })
+    }
+
+    #[test]
+    fn test_path_count_large() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(128);
+        assert_eq!(parity(&builder, 128).path_count(), 1 << 127);
+    }
+
+    #[test]
+    #[should_panic(expected = "path count overflowed u128")]
+    fn test_path_count_overflow_panics() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(130);
+        parity(&builder, 130).path_count();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "scratch holds a `usize` but was read as a `u64`")]
+    fn test_scratch_type_mismatch_panics() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(1);
+        let a = builder.var(VarLabel::new(0), true);
+        a.set_scratch::<usize>(3);
+        a.scratch::<u64>();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "still holds scratch")]
+    fn test_stale_scratch_detected() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(130);
+        let f = parity(&builder, 130);
+        // the path count overflows partway up, so the pass stops before
+        // caching at (or clearing from) the root, leaving scratch on the
+        // nodes below it
+        let interrupted = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f.path_count()));
+        assert!(interrupted.is_err());
+        assert!(f.is_scratch_cleared());
+        builder.condition_model(f, &PartialModel::from_litvec(&[], 130));
+    }
+
+    #[test]
+    fn test_sample_distinct() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let a = builder.var(VarLabel::new(0), true);
+        let b = builder.var(VarLabel::new(1), true);
+        let f = builder.or(a, b);
+        let wmc = WmcParams::new(HashMap::from_iter((0..3).map(|v| {
+            (
+                VarLabel::new_usize(v),
+                (RealSemiring(0.3), RealSemiring(0.7)),
+            )
+        })));
+        let to_vec = |m: &PartialModel| -> Vec<bool> {
+            (0..3).map(|v| m.get(VarLabel::new(v)).unwrap()).collect()
+        };
+
+        let few = builder.sample_distinct(f, 4, &wmc);
+        assert_eq!(few.len(), 4);
+        let mut seen: Vec<Vec<bool>> = few.iter().map(|(m, _)| to_vec(m)).collect();
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 4);
+
+        // asking for more samples than there are models returns every model
+        let all = builder.sample_distinct(f, 100, &wmc);
+        assert_eq!(all.len() as u128, builder.model_count(f));
+        for (m, w) in all.iter() {
+            let assgn = to_vec(m);
+            assert!(f.evaluate(&assgn));
+            let expected: f64 = assgn.iter().map(|&v| if v { 0.7 } else { 0.3 }).product();
+            assert!((w - expected).abs() < 1e-12);
+        }
+        let mut seen: Vec<Vec<bool>> = all.iter().map(|(m, _)| to_vec(m)).collect();
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 6);
+        assert!(builder
+            .sample_distinct(BddPtr::false_ptr(), 3, &wmc)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_gumbel_top_k_frequencies() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let a = builder.var(VarLabel::new(0), true);
+        let b = builder.var(VarLabel::new(1), true);
+        let f = builder.or(a, b);
+        let p = [0.6, 0.3, 0.5];
+        let wmc = WmcParams::new(HashMap::from_iter((0..3).map(|v| {
+            (
+                VarLabel::new_usize(v),
+                (RealSemiring(1.0 - p[v]), RealSemiring(p[v])),
+            )
+        })));
+        let index = |m: &PartialModel| -> usize {
+            (0..3)
+                .filter(|&v| m.get(VarLabel::new_usize(v)).unwrap())
+                .map(|v| 1 << v)
+                .sum()
+        };
+        let weight = |i: usize| -> f64 {
+            (0..3)
+                .map(|v| if i & (1 << v) != 0 { p[v] } else { 1.0 - p[v] })
+                .product()
+        };
+        let total = 1.0 - 0.4 * 0.7;
+
+        // the first-ranked assignment is an exact weighted sample
+        let mut rng = ChaCha8Rng::seed_from_u64(571);
+        let runs = 20000;
+        let mut counts = [0usize; 8];
+        for _ in 0..runs {
+            let r = builder.gumbel_top_k(f, 3, &wmc, &mut rng);
+            assert_eq!(r.len(), 3);
+            let mut idx: Vec<usize> = r.iter().map(|(m, _)| index(m)).collect();
+            for (i, (_, w)) in idx.iter().zip(r.iter()) {
+                assert!((w - weight(*i)).abs() < 1e-12);
+            }
+            counts[idx[0]] += 1;
+            idx.sort();
+            idx.dedup();
+            assert_eq!(idx.len(), 3);
+        }
+        for (i, c) in counts.iter().enumerate() {
+            let expected = if i & 0b11 == 0 {
+                0.0
+            } else {
+                weight(i) / total
+            };
+            assert!((*c as f64 / runs as f64 - expected).abs() < 0.015);
+        }
+
+        // asking for more than the number of models returns all of them
+        assert_eq!(builder.gumbel_top_k(f, 10, &wmc, &mut rng).len(), 6);
+        assert!(builder
+            .gumbel_top_k(BddPtr::false_ptr(), 2, &wmc, &mut rng)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_top_k_paths_log_matches_linear() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let x: Vec<BddPtr> = (0..4)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        let f = builder.or(builder.and(x[0], x[1]), builder.xor(x[2], x[3]));
+        let p = [0.15, 0.35, 0.6, 0.8];
+        let weights = |f: fn(f64) -> f64| {
+            WmcParams::new(HashMap::from_iter((0..4).map(|v| {
+                (
+                    VarLabel::new_usize(v),
+                    (RealSemiring(f(1.0 - p[v])), RealSemiring(f(p[v]))),
+                )
+            })))
+        };
+        let linear = weights(|w| w);
+        let log = weights(f64::ln);
+        for k in 1..=6 {
+            let expected = builder.top_k_paths(f, k, &linear);
+            let r = builder.top_k_paths_log(f, k, &log);
+            assert!(r == expected);
+            // the selected paths carry the same total weight
+            let w = r.unsmoothed_wmc(&linear).0;
+            assert!((w - expected.unsmoothed_wmc(&linear).0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_top_k_ties_ordered_by_decisions() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let x: Vec<BddPtr> = (0..3)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        // odd parity has four paths, all of weight 1/8
+        let f = builder.xor(builder.xor(x[0], x[1]), x[2]);
+        let mut wmc = WmcParams::default();
+        wmc.set_default(RealSemiring(0.5), RealSemiring(0.5));
+        let decode = |r: Vec<(PartialModel, f64)>| -> Vec<[bool; 3]> {
+            r.iter()
+                .map(|(m, w)| {
+                    assert_eq!(*w, 0.125);
+                    [0, 1, 2].map(|v| m.get(VarLabel::new(v)).unwrap())
+                })
+                .collect()
+        };
+        let all = vec![
+            [false, false, true],
+            [false, true, false],
+            [true, false, false],
+            [true, true, true],
+        ];
+        assert_eq!(decode(builder.top_k_models(f, 4, &wmc)), all);
+        assert_eq!(decode(builder.top_k_models(f, 2, &wmc)), all[..2]);
+        assert_eq!(
+            decode(builder.top_k_models(f.neg(), 1, &wmc)),
+            vec![[false; 3]]
+        );
+    }
+
+    #[test]
+    fn test_clause() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let lit = |v: u64, p: bool| Literal::new(VarLabel::new(v), p);
+        assert!(builder.clause(&[]).is_false());
+        assert_eq!(
+            builder.clause(&[lit(1, false)]),
+            builder.var(VarLabel::new(1), false)
+        );
+        assert!(builder
+            .clause(&[lit(0, true), lit(2, false), lit(0, false)])
+            .is_true());
+        let expected = builder.or(
+            builder.var(VarLabel::new(2), true),
+            builder.var(VarLabel::new(0), false),
+        );
+        assert_eq!(builder.clause(&[lit(2, true), lit(0, false)]), expected);
+    }
+
+    #[test]
+    fn test_cube() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let lit = |v: u64, p: bool| Literal::new(VarLabel::new(v), p);
+        assert!(builder.cube(&[]).is_true());
+        assert!(builder
+            .cube(&[lit(1, true), lit(2, false), lit(1, false)])
+            .is_false());
+        let c = builder.cube(&[lit(2, true), lit(0, false)]);
+        let expected = builder.and(
+            builder.var(VarLabel::new(2), true),
+            builder.var(VarLabel::new(0), false),
+        );
+        assert_eq!(c, expected);
+        // a cube over every variable has exactly one model
+        let full = builder.cube(&[lit(0, true), lit(1, false), lit(2, true)]);
+        assert_eq!(builder.model_count(full), 1);
+        assert!(full.evaluate(&[true, false, true]));
+    }
+
+    #[test]
+    fn test_entropy() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let x: Vec<BddPtr> = (0..3)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        let mut uniform = WmcParams::default();
+        uniform.set_default(RealSemiring(0.5), RealSemiring(0.5));
+        // uniform over k models has entropy ln k
+        let f = builder.or(x[0], builder.and(x[1], x[2]));
+        assert_eq!(builder.model_count(f), 5);
+        assert!((builder.entropy(f, &uniform) - 5f64.ln()).abs() < 1e-12);
+        assert!((builder.entropy(x[1], &uniform) - 4f64.ln()).abs() < 1e-12);
+        // equal weights per model, even when literal weights are uneven
+        let mut uneven = WmcParams::default();
+        uneven.set_default(RealSemiring(0.2), RealSemiring(0.2));
+        assert!((builder.entropy(f, &uneven) - 5f64.ln()).abs() < 1e-12);
+
+        // a skewed distribution, checked against direct enumeration
+        let p = [0.1, 0.6, 0.75];
+        let skewed = WmcParams::new(HashMap::from_iter((0..3).map(|v| {
+            (
+                VarLabel::new_usize(v),
+                (RealSemiring(1.0 - p[v]), RealSemiring(p[v])),
+            )
+        })));
+        let weights: Vec<f64> = (0..8usize)
+            .map(|i| [0, 1, 2].map(|v| i & (1 << v) != 0))
+            .filter(|assgn| f.evaluate(assgn))
+            .map(|assgn| {
+                (0..3)
+                    .map(|v| if assgn[v] { p[v] } else { 1.0 - p[v] })
+                    .product()
+            })
+            .collect();
+        let z: f64 = weights.iter().sum();
+        let expected: f64 = weights.iter().map(|w| -(w / z) * (w / z).ln()).sum();
+        assert!((builder.entropy(f, &skewed) - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_kl_divergence() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let x: Vec<BddPtr> = (0..3)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        let f = builder.or(x[0], builder.and(x[1], x[2]));
+        let params = |probs: [f64; 3]| {
+            WmcParams::new(HashMap::from_iter((0..3).map(|v| {
+                (
+                    VarLabel::new_usize(v),
+                    (RealSemiring(1.0 - probs[v]), RealSemiring(probs[v])),
+                )
+            })))
+        };
+        let p = params([0.1, 0.6, 0.75]);
+        let q = params([0.5, 0.3, 0.9]);
+        assert!(builder.kl_divergence(f, &p, &p).abs() < 1e-12);
+        assert!(builder.kl_divergence(f, &q, &q).abs() < 1e-12);
+
+        // check against direct enumeration over the models of f
+        let dist = |probs: [f64; 3]| {
+            let w: Vec<f64> = (0..8usize)
+                .map(|i| [0, 1, 2].map(|v| i & (1 << v) != 0))
+                .filter(|assgn| f.evaluate(assgn))
+                .map(|assgn| {
+                    (0..3)
+                        .map(|v| if assgn[v] { probs[v] } else { 1.0 - probs[v] })
+                        .product::<f64>()
+                })
+                .collect();
+            let z: f64 = w.iter().sum();
+            w.into_iter().map(|w| w / z).collect::<Vec<f64>>()
+        };
+        let (dp, dq) = (dist([0.1, 0.6, 0.75]), dist([0.5, 0.3, 0.9]));
+        let expected: f64 = dp.iter().zip(&dq).map(|(a, b)| a * (a / b).ln()).sum();
+        assert!((builder.kl_divergence(f, &p, &q) - expected).abs() < 1e-12);
+        assert!(builder.kl_divergence(f, &p, &q) > 0.0);
+
+        // q rules out x0, which p allows within f
+        let q_zero = params([0.0, 0.3, 0.9]);
+        assert_eq!(builder.kl_divergence(f, &p, &q_zero), f64::INFINITY);
+        // ...but is fine when p also rules it out
+        let p_zero = params([0.0, 0.6, 0.75]);
+        let kl = builder.kl_divergence(f, &p_zero, &q_zero);
+        assert!(kl.is_finite());
+        // both are then a point mass on x0=0, x1=1, x2=1
+        assert!(kl.abs() < 1e-12);
+        // a q-weight of zero outside the models of f does not matter
+        let g = builder.and(x[1], x[2]);
+        assert!(builder.kl_divergence(g, &p_zero, &q_zero).is_finite());
+    }
+
+    #[test]
+    fn test_complex_wmc() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let a = builder.var(VarLabel::new(0), true);
+        let b = builder.var(VarLabel::new(1), true);
+        let c = builder.var(VarLabel::new(2), true);
+        let f = builder.smooth(builder.or(a, builder.and(b, c.neg())), 3);
+
+        // purely real weights agree with the real semiring
+        let probs = [0.3, 0.6, 0.8];
+        let real = WmcParams::new(HashMap::from_iter((0..3).map(|v| {
+            (
+                VarLabel::new_usize(v),
+                (RealSemiring(1.0 - probs[v]), RealSemiring(probs[v])),
+            )
+        })));
+        let complex = WmcParams::new(HashMap::from_iter((0..3).map(|v| {
+            (
+                VarLabel::new_usize(v),
+                (
+                    ComplexSemiring::new(1.0 - probs[v], 0.0),
+                    ComplexSemiring::new(probs[v], 0.0),
+                ),
+            )
+        })));
+        let r = f.unsmoothed_wmc(&complex);
+        assert!((r.re - f.unsmoothed_wmc(&real).0).abs() < 1e-12);
+        assert_eq!(r.im, 0.0);
+
+        // the models of a & b are the two values of c, with amplitudes
+        // i * (1 + i) * (2 - i) and i * (1 + i) * 3i
+        let mut params = WmcParams::default();
+        params.set_weight(
+            VarLabel::new(0),
+            ComplexSemiring::zero(),
+            ComplexSemiring::new(0.0, 1.0),
+        );
+        params.set_weight(
+            VarLabel::new(1),
+            ComplexSemiring::zero(),
+            ComplexSemiring::new(1.0, 1.0),
+        );
+        params.set_weight(
+            VarLabel::new(2),
+            ComplexSemiring::new(2.0, -1.0),
+            ComplexSemiring::new(0.0, 3.0),
+        );
+        let g = builder.smooth(builder.and(a, b), 3);
+        // i * (1 + i) = -1 + i; (-1 + i) * (2 + 2i) = -4
+        assert_eq!(g.unsmoothed_wmc(&params), ComplexSemiring::new(-4.0, 0.0));
+        // a single model: (-1 + i) * 3i = -3 - 3i, of squared magnitude 18
+        let h = builder.smooth(builder.and(builder.and(a, b), c), 3);
+        let amp = h.unsmoothed_wmc(&params);
+        assert_eq!(amp, ComplexSemiring::new(-3.0, -3.0));
+        assert_eq!(amp.norm_sqr(), 18.0);
+        assert_eq!(amp * amp.conj(), ComplexSemiring::new(18.0, 0.0));
+    }
+
+    #[test]
+    fn test_wmc_trace() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let x: Vec<BddPtr> = (0..4)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        let f = builder.or(
+            builder.xor(x[0], x[1]),
+            builder.and(x[2], builder.iff(x[1], x[3])),
+        );
+        let wmc = WmcParams::new(HashMap::from_iter((0..4).map(|v| {
+            let p = 0.1 + 0.2 * v as f64;
+            (
+                VarLabel::new_usize(v),
+                (RealSemiring(1.0 - p), RealSemiring(p)),
+            )
+        })));
+        let trace = builder.wmc_trace(f, &wmc);
+        assert_eq!(trace[&f], f.unsmoothed_wmc(&wmc));
+        // every intermediate value is that sub-diagram's own count
+        for (ptr, v) in trace.iter() {
+            assert!((ptr.unsmoothed_wmc(&wmc).0 - v.0).abs() < 1e-12);
+        }
+        assert_eq!(trace[&BddPtr::true_ptr()], RealSemiring(1.0));
+        // x0 xor x1 reaches the node for x1 in both polarities
+        let g = builder.xor(x[0], x[1]);
+        let trace_g = builder.wmc_trace(g, &wmc);
+        assert_eq!(trace_g.len(), 5);
+        assert!((trace_g[&x[1]].0 - 0.3).abs() < 1e-12);
+        assert!((trace_g[&x[1].neg()].0 - 0.7).abs() < 1e-12);
+
+        // any semiring works; with unit weights the root holds the model count
+        let smoothed = builder.smooth(f, 4);
+        let mut counts = WmcParams::<FiniteField<1000001>>::default();
+        counts.set_default(FiniteField::new(1), FiniteField::new(1));
+        let trace = builder.wmc_trace(smoothed, &counts);
+        assert_eq!(trace[&smoothed].value(), builder.model_count(f));
+    }
+
+    #[test]
+    fn test_smooth_skipped_levels() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let a = builder.var(VarLabel::new(0), true);
+        let c = builder.var(VarLabel::new(2), true);
+        // the high edge of a skips b and c, and the low edge skips b
+        let f = builder.or(a, c);
+        let smoothed = builder.smooth(f, 4);
+        // each path now decides every variable exactly once
+        assert_eq!(smoothed.path_count(), 12);
+        for i in 0..16usize {
+            let assgn = [0, 1, 2, 3].map(|v| i & (1 << v) != 0);
+            assert_eq!(smoothed.evaluate(&assgn), f.evaluate(&assgn));
+        }
+        // weights that do not sum to one expose a variable that is skipped
+        // or decided twice
+        let mut wmc = WmcParams::default();
+        wmc.set_default(RealSemiring(2.0), RealSemiring(3.0));
+        // 5^4 assignments minus those with a = c = false, weighted 2 * 2 * 5^2
+        assert_eq!(smoothed.unsmoothed_wmc(&wmc).0, 625.0 - 100.0);
+    }
+
+    #[test]
+    fn test_compose_constant() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let x: Vec<BddPtr> = (0..4)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        let f = builder.or(
+            builder.and(x[0], x[2]),
+            builder.xor(x[1], builder.iff(x[2], x[3])),
+        );
+        let v = VarLabel::new(2);
+        let lookups = || builder.stats().apply_cache_hits + builder.stats().apply_cache_misses;
+        let before = lookups();
+        let t = builder.compose(f, v, BddPtr::true_ptr());
+        let e = builder.compose(f, v, BddPtr::false_ptr());
+        // conditioning never consults the apply cache
+        assert_eq!(lookups(), before);
+        assert_eq!(t, builder.condition(f, v, true));
+        assert_eq!(e, builder.condition(f, v, false));
+
+        // a non-constant substitution still goes through the general path
+        let g = builder.compose(f, v, x[0]);
+        for i in 0..16usize {
+            let assgn = [0, 1, 2, 3].map(|v| i & (1 << v) != 0);
+            let mut substituted = assgn;
+            substituted[2] = assgn[0];
+            assert_eq!(g.evaluate(&assgn), f.evaluate(&substituted));
+        }
+    }
+
+    #[test]
+    fn test_compose_multiple_simultaneous() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let x: Vec<BddPtr> = (0..3)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        let f = builder.or(builder.and(x[0], x[1].neg()), x[2]);
+        // x0 := x1 & x2 and x1 := x0 | x2 refer to each other
+        let g0 = builder.and(x[1], x[2]);
+        let g1 = builder.or(x[0], x[2]);
+        let subs = HashMap::from([(VarLabel::new(0), g0), (VarLabel::new(1), g1)]);
+        let composed = builder.compose_multiple(f, &subs);
+        for i in 0..8usize {
+            let assgn = [0, 1, 2].map(|v| i & (1 << v) != 0);
+            let substituted = [g0.evaluate(&assgn), g1.evaluate(&assgn), assgn[2]];
+            assert_eq!(composed.evaluate(&assgn), f.evaluate(&substituted));
+        }
+
+        // swapping x0 and x1 one variable at a time first turns x0 & !x1
+        // into x1 & !x1, losing the x0 branch entirely
+        let swap = HashMap::from([(VarLabel::new(0), x[1]), (VarLabel::new(1), x[0])]);
+        let swapped = builder.compose_multiple(f, &swap);
+        assert_eq!(swapped, builder.or(builder.and(x[1], x[0].neg()), x[2]));
+        let sequential = builder.compose(
+            builder.compose(f, VarLabel::new(0), x[1]),
+            VarLabel::new(1),
+            x[0],
+        );
+        assert_eq!(sequential, x[2]);
+
+        // an empty substitution is the identity
+        assert_eq!(builder.compose_multiple(f, &HashMap::new()), f);
+    }
+
+    #[test]
+    fn test_force_order_chain() {
+        // a chain x_p(0) - x_p(1) - ... of implications whose labels are
+        // scattered, so that the natural order separates chain neighbors
+        let n = 12;
+        let perm: Vec<u64> = (0..n).map(|i| (i * 5) % n).collect();
+        let clauses: Vec<Vec<Literal>> = perm
+            .windows(2)
+            .map(|w| {
+                vec![
+                    Literal::new(VarLabel::new(w[0]), false),
+                    Literal::new(VarLabel::new(w[1]), true),
+                ]
+            })
+            .collect();
+        let cnf = Cnf::new(&clauses);
+        let size = |order: VarOrder| {
+            let builder = RobddBuilder::<AllIteTable<BddPtr>>::new(order, None);
+            builder.compile_cnf(&cnf).count_nodes()
+        };
+        let natural = size(VarOrder::linear_order(n as usize));
+        let force = size(VarOrder::force_order(&cnf));
+        assert!(force <= natural, "force {} > natural {}", force, natural);
+        // FORCE stops at a local optimum, which can be worse than simply
+        // following the chain
+        let chain = VarOrder::new(&perm.iter().map(|v| VarLabel::new(*v)).collect::<Vec<_>>());
+        assert!(size(chain) <= force);
+    }
+
+    #[test]
+    fn test_cofactors_respect_complement() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let x: Vec<BddPtr> = (0..3)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        // the negation of an or is stored as a complemented pointer
+        let f = builder.or(builder.and(x[0], x[1]), x[2]).neg();
+        assert!(f.is_neg());
+        let top = f.var_safe().unwrap();
+        assert_eq!(top, VarLabel::new(0));
+        let (hi, lo) = (f.cofactor_true(), f.cofactor_false());
+        assert_eq!(hi, builder.condition(f, top, true));
+        assert_eq!(lo, builder.condition(f, top, false));
+        for i in 0..8usize {
+            let assgn = [0, 1, 2].map(|v| i & (1 << v) != 0);
+            let cofactor = if assgn[0] { hi } else { lo };
+            assert_eq!(cofactor.evaluate(&assgn), f.evaluate(&assgn));
+        }
+        // the raw children belong to the uncomplemented function
+        assert_eq!(f.high_raw(), hi.neg());
+        assert_eq!(f.low_raw(), lo.neg());
+    }
+
+    /// whether no complemented pointer is reachable from `ptr`
+    fn is_plain(ptr: BddPtr) -> bool {
+        match ptr {
+            BddPtr::PtrTrue | BddPtr::PtrFalse => true,
+            BddPtr::Compl(_) => false,
+            BddPtr::Reg(node) => is_plain(node.low) && is_plain(node.high),
+        }
+    }
+
+    #[test]
+    fn test_without_complement_edges_agrees() {
+        let cnfs = [
+            "(1 || 2) && (-2 || 3 || -4) && (1 || 4)",
+            "(-1 || -2) && (2 || 3) && (-3 || 4 || 5) && (-5 || -1)",
+            "(1 || -3) && (-1 || 3) && (2 || 4) && (-2 || -4)",
+        ];
+        let mut params = WmcParams::default();
+        for (v, p) in [0.5, 0.3, 0.6, 0.1, 0.2, 0.8].iter().enumerate() {
+            params.set_weight(
+                VarLabel::new_usize(v),
+                RealSemiring(1.0 - p),
+                RealSemiring(*p),
+            );
+        }
+
+        let compl = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(6);
+        let plain = RobddBuilder::<AllIteTable<BddPtr>>::without_complement_edges(
+            VarOrder::linear_order(6),
+        );
+        assert!(compl.complement_edges());
+        assert!(!plain.complement_edges());
+        for (i, s1) in cnfs.iter().enumerate() {
+            for s2 in cnfs.iter().skip(i) {
+                let (c1, c2) = (Cnf::from_string(s1), Cnf::from_string(s2));
+                let builders = [&compl, &plain];
+                let results = builders.map(|b| {
+                    let (f, g) = (b.compile_cnf(&c1), b.compile_cnf(&c2));
+                    let xor = b.xor(f, g);
+                    let not_or = b.negate(b.or(f, g));
+                    let de_morgan = b.and(b.negate(f), b.negate(g));
+                    let cond = b.condition(b.iff(f, g), VarLabel::new(1), false);
+                    let ex = b.exists(not_or, VarLabel::new(2));
+                    // equal functions are the same pointer in both modes
+                    assert!(b.eq(not_or, de_morgan));
+                    assert_eq!(b.eq(f, g), s1 == s2);
+                    [xor, not_or, cond, ex]
+                });
+                for (c, p) in results[0].iter().zip(results[1].iter()) {
+                    assert!(is_plain(*p));
+                    let (wc, wp) = (c.unsmoothed_wmc(&params).0, p.unsmoothed_wmc(&params).0);
+                    assert!((wc - wp).abs() < 1e-12);
+                }
+            }
+        }
+        assert!(plain.num_nodes() >= compl.num_nodes());
+    }
+
+    #[test]
+    fn test_completions() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let v: Vec<BddPtr> = (0..3)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        // (a => b) & (b xor c), with d unused
+        let f = builder.and(builder.imp(v[0], v[1]), builder.xor(v[1], v[2]));
+        let partial = PartialModel::from_assignments(&[None, Some(true), None, None]);
+        let completions = builder.completions(f, &partial);
+        // b = true forces c = false; a and d are free
+        assert_eq!(completions.len(), 4);
+        for m in completions.iter() {
+            assert_eq!(m.get(VarLabel::new(1)), Some(true));
+            let assgn: Vec<bool> = (0..4).map(|v| m.get(VarLabel::new(v)).unwrap()).collect();
+            assert!(f.evaluate(&assgn));
+        }
+        let distinct: HashSet<Vec<Option<bool>>> = completions
+            .iter()
+            .map(|m| (0..4).map(|v| m.get(VarLabel::new(v))).collect())
+            .collect();
+        assert_eq!(distinct.len(), 4);
+
+        // a = true, b = false contradicts a => b
+        let contradiction = PartialModel::from_assignments(&[Some(true), Some(false), None, None]);
+        assert!(builder.completions(f, &contradiction).is_empty());
+    }
+
+    #[test]
+    fn test_unsmoothed_wmc_cached_shares_subgraphs() {
+        use std::cell::Cell;
+        use std::fmt::Display;
+
+        thread_local! {
+            static MULS: Cell<usize> = const { Cell::new(0) };
+        }
+        fn count_mul() {
+            MULS.with(|m| m.set(m.get() + 1));
+        }
+
+        /// a real semiring that counts its multiplications
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        struct Counting(f64);
+        impl Display for Counting {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+        impl std::ops::Add for Counting {
+            type Output = Counting;
+            fn add(self, rhs: Counting) -> Counting {
+                Counting(self.0 + rhs.0)
+            }
+        }
+        impl std::ops::Mul for Counting {
+            type Output = Counting;
+            fn mul(self, rhs: Counting) -> Counting {
+                count_mul();
+                Counting(self.0 * rhs.0)
+            }
+        }
+        impl Semiring for Counting {
+            fn one() -> Self {
+                Counting(1.0)
+            }
+            fn zero() -> Self {
+                Counting(0.0)
+            }
+        }
+        let muls = |f: &mut dyn FnMut()| {
+            MULS.with(|m| m.set(0));
+            f();
+            MULS.with(|m| m.get())
+        };
+
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(6);
+        let x: Vec<BddPtr> = (0..6)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        // `shared` sits below both roots in the order
+        let shared = builder.xor(builder.and(x[3], x[4]), x[5]);
+        let f = builder.and(x[0], shared);
+        let g = builder.or(builder.and(x[1], x[2]), shared);
+        let params = WmcParams::from_slice(
+            &(0..6)
+                .map(|i| {
+                    (
+                        Counting(0.9 - i as f64 / 10.0),
+                        Counting(0.1 + i as f64 / 10.0),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        let mut fresh = HashMap::new();
+        let mut g_fresh = Counting(0.0);
+        let fresh_muls = muls(&mut || g_fresh = g.unsmoothed_wmc_cached(&params, &mut fresh));
+
+        let mut cache = HashMap::new();
+        let mut f_v = Counting(0.0);
+        muls(&mut || f_v = f.unsmoothed_wmc_cached(&params, &mut cache));
+        let f_nodes: HashSet<BddPtr> = cache.keys().copied().collect();
+        let mut g_v = Counting(0.0);
+        let shared_muls = muls(&mut || g_v = g.unsmoothed_wmc_cached(&params, &mut cache));
+
+        // each node costs two multiplications, and only the nodes of `g`
+        // that `f` did not reach are counted again
+        let new_nodes = fresh
+            .keys()
+            .filter(|p| !p.is_const() && !f_nodes.contains(p))
+            .count();
+        assert_eq!(shared_muls, 2 * new_nodes);
+        assert!(shared_muls < fresh_muls);
+        assert!((g_v.0 - g_fresh.0).abs() < 1e-12);
+        assert!((f_v.0 - f.unsmoothed_wmc(&params).0).abs() < 1e-12);
+        assert!((g_v.0 - g.unsmoothed_wmc(&params).0).abs() < 1e-12);
+        // a warm cache answers without any work
+        assert_eq!(
+            muls(&mut || {
+                f.unsmoothed_wmc_cached(&params, &mut cache);
+            }),
+            0
+        );
+    }
+
+    #[test]
+    fn test_tautology_and_unsat() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+        let a = builder.var(VarLabel::new(0), true);
+        let valid = builder.or(a, builder.negate(a));
+        let unsat = builder.and(a, builder.negate(a));
+        assert!(valid.is_tautology() && !valid.is_unsat());
+        assert!(unsat.is_unsat() && !unsat.is_tautology());
+        assert!(!a.is_tautology() && !a.is_unsat());
+        assert!(!a.neg().is_tautology() && !a.neg().is_unsat());
+    }
+
+    #[test]
+    fn test_new_vars_contiguous() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let vars = builder.new_vars(4, false);
+        assert_eq!(builder.num_vars(), 7);
+        for (i, (label, ptr)) in vars.iter().enumerate() {
+            assert_eq!(*label, VarLabel::new_usize(3 + i));
+            assert_eq!(*ptr, builder.var(*label, false));
+            assert_eq!(builder.order().get(*label), 3 + i);
+        }
+        assert!(builder.new_vars(0, true).is_empty());
+        assert_eq!(builder.num_vars(), 7);
+    }
+
+    #[test]
+    fn test_distinct_subfunctions() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let x: Vec<BddPtr> = (0..4)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        // parity: every node below the root is reached with both polarities
+        let parity = builder.xor(builder.xor(x[0], x[1]), builder.xor(x[2], x[3]));
+        assert_eq!(parity.count_nodes(), 4);
+        assert_eq!(parity.distinct_subfunctions(), 7);
+        assert_eq!(parity.neg().distinct_subfunctions(), 7);
+
+        // a conjunction never reaches a node through a complemented edge
+        let conj = builder.and(builder.and(x[0], x[1]), x[2]);
+        assert_eq!(conj.count_nodes(), conj.distinct_subfunctions());
+        assert_eq!(BddPtr::PtrTrue.distinct_subfunctions(), 0);
+
+        // without complemented edges, nodes and subfunctions coincide
+        let plain = RobddBuilder::<AllIteTable<BddPtr>>::without_complement_edges(
+            VarOrder::linear_order(4),
+        );
+        let y: Vec<BddPtr> = (0..4).map(|i| plain.var(VarLabel::new(i), true)).collect();
+        let plain_parity = plain.xor(plain.xor(y[0], y[1]), plain.xor(y[2], y[3]));
+        assert_eq!(plain_parity.count_nodes(), 7);
+        assert_eq!(plain_parity.distinct_subfunctions(), 7);
+    }
+
+    #[test]
+    fn test_from_aag_half_adder() {
+        // the half adder from the AIGER format description
+        let aag = "aag 7 2 0 2 3\n2\n4\n6\n12\n6 13 15\n12 2 4\n14 3 5\n\
+                   i0 x\ni1 y\no0 s\no1 c\nc\nhalf adder\n";
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(0);
+        let outputs = builder.from_aag(std::io::Cursor::new(aag)).unwrap();
+        assert_eq!(builder.num_vars(), 2);
+        let (x, y) = (
+            builder.var(VarLabel::new(0), true),
+            builder.var(VarLabel::new(1), true),
+        );
+        assert_eq!(outputs["s"], builder.xor(x, y));
+        assert_eq!(outputs["c"], builder.and(x, y));
+
+        let (px, py) = (0.3, 0.6);
+        let params = WmcParams::from_slice(&[
+            (RealSemiring(1.0 - px), RealSemiring(px)),
+            (RealSemiring(1.0 - py), RealSemiring(py)),
+        ]);
+        let sum = outputs["s"].unsmoothed_wmc(&params).0;
+        let carry = outputs["c"].unsmoothed_wmc(&params).0;
+        assert!((sum - (px * (1.0 - py) + (1.0 - px) * py)).abs() < 1e-12);
+        assert!((carry - px * py).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_implies() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let x: Vec<BddPtr> = (0..4)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        let (a, b) = (x[0], x[1]);
+        assert!(builder.implies(a, builder.or(a, b)));
+        assert!(builder.implies(builder.and(a, b), a));
+        assert!(!builder.implies(builder.or(a, b), a));
+        assert!(!builder.implies(a, b));
+        assert!(builder.implies(BddPtr::PtrFalse, a));
+        assert!(builder.implies(a, BddPtr::PtrTrue));
+        assert!(!builder.implies(BddPtr::PtrTrue, a));
+
+        // agrees with checking `and(f, !g)` on larger formulas
+        let f = builder.compile_cnf(&Cnf::from_string("(1 || 2) && (-2 || 3)"));
+        let g = builder.compile_cnf(&Cnf::from_string("(1 || 3)"));
+        let h = builder.compile_cnf(&Cnf::from_string("(1 || 2) && (3)"));
+        for (p, q) in [(f, g), (g, f), (f, h), (h, f), (h, g), (g, h)] {
+            let expected = builder.and(p, q.neg()).is_false();
+            assert_eq!(builder.implies(p, q), expected);
+            assert_eq!(builder.implies(q.neg(), p.neg()), expected);
+        }
+    }
+
+    #[test]
+    fn test_seeded_weighted_sample_is_deterministic() {
+        let cnf = Cnf::from_string("(1 || 2 || 3) && (-1 || 4) && (2 || -3 || 5)");
+        let mut wmc = WmcParams::default();
+        wmc.set_default(RealSemiring(0.4), RealSemiring(0.6));
+        let draw = |seed: u64| {
+            let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(6);
+            builder.seed_rng(seed);
+            let bdd = builder.compile_cnf(&cnf);
+            (0..20)
+                .map(|_| {
+                    let (sample, prob) = builder.weighted_sample(bdd, &wmc);
+                    (sample.to_string_debug(), prob)
+                })
+                .collect::<Vec<_>>()
+        };
+        let first = draw(7);
+        assert_eq!(first, draw(7));
+        // twenty draws from a function with several models are not all equal
+        assert!(first.iter().any(|s| *s != first[0]));
+    }
+
+    #[test]
+    fn test_weighted_maxsat() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let lit = |v: u64, p: bool| Literal::new(VarLabel::new(v), p);
+        let clauses = [
+            (vec![lit(0, true)], 5.0),
+            (vec![lit(0, false), lit(1, true)], 3.0),
+            (vec![lit(1, false)], 4.0),
+            (vec![lit(2, true), lit(1, true)], 1.0),
+        ];
+        // x0 & !x1 & x2 gives up only the second clause
+        let (reward, model) = builder.weighted_maxsat(&clauses);
+        assert_eq!(reward, 10.0);
+        assert_eq!(model.get(VarLabel::new(0)), Some(true));
+        assert_eq!(model.get(VarLabel::new(1)), Some(false));
+        assert_eq!(model.get(VarLabel::new(2)), Some(true));
+        // the indicator variables live in a scratch builder
+        assert_eq!(builder.num_vars(), 3);
+        assert_eq!(builder.num_nodes(), 0);
+
+        // agrees with brute force, including with a negative weight
+        let clauses = [
+            (vec![lit(0, true), lit(1, true)], 2.5),
+            (vec![lit(1, false), lit(2, true)], -1.0),
+            (vec![lit(2, false)], 1.5),
+            (vec![lit(0, false), lit(2, true)], 2.0),
+        ];
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let (reward, model) = builder.weighted_maxsat(&clauses);
+        let score = |assgn: &[bool]| -> f64 {
+            clauses
+                .iter()
+                .filter(|(c, _)| {
+                    c.iter()
+                        .any(|l| assgn[l.label().value_usize()] == l.polarity())
+                })
+                .map(|(_, w)| w)
+                .sum()
+        };
+        let best = (0..8)
+            .map(|i| score(&[i & 1 != 0, i & 2 != 0, i & 4 != 0]))
+            .fold(f64::NEG_INFINITY, f64::max);
+        assert_eq!(reward, best);
+        let assgn: Vec<bool> = (0..3)
+            .map(|v| model.get(VarLabel::new(v)).unwrap())
+            .collect();
+        assert_eq!(score(&assgn), best);
+    }
+
+    #[test]
+    fn test_hamming_weight_of_diff() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let mut wmc = WmcParams::default();
+        wmc.set_weight(VarLabel::new(0), RealSemiring(0.2), RealSemiring(0.8));
+        wmc.set_weight(VarLabel::new(1), RealSemiring(0.6), RealSemiring(0.4));
+        // variables 2 and 3 are unnormalized, so the total weight is 2 * 3
+        wmc.set_weight(VarLabel::new(2), RealSemiring(1.0), RealSemiring(1.0));
+        wmc.set_weight(VarLabel::new(3), RealSemiring(1.0), RealSemiring(2.0));
+        let f = builder.compile_cnf(&Cnf::from_string("(1 || -2) && (2 || 3)"));
+        let g = builder.compile_cnf(&Cnf::from_string("(1 || 3)"));
+
+        assert_eq!(builder.hamming_weight_of_diff(f, f, &wmc), 0.0);
+        let total = 6.0;
+        assert!((builder.hamming_weight_of_diff(f, f.neg(), &wmc) - total).abs() < 1e-9);
+        assert!((builder.hamming_weight_of_diff(g, g.neg(), &wmc) - total).abs() < 1e-9);
+
+        // the disagreement of f and g, plus their agreement, is everything
+        let d = builder.hamming_weight_of_diff(f, g, &wmc);
+        let agree = builder.hamming_weight_of_diff(f, g.neg(), &wmc);
+        assert!(d > 0.0);
+        assert!((d + agree - total).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_wmc_custom() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let x: Vec<BddPtr> = (0..3)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        // (x0 \/ !x1) /\ (x1 \/ x2)
+        let f = builder.and(builder.or(x[0], x[1].neg()), builder.or(x[1], x[2]));
+        let probs = [0.3, 0.6, 0.8];
+        let weight = |v: VarLabel| (1.0 - probs[v.value_usize()], probs[v.value_usize()]);
+        let wmc = WmcParams::new(HashMap::from_iter((0..3).map(|v| {
+            let (lo, hi) = weight(VarLabel::new(v));
+            (VarLabel::new(v), (RealSemiring(lo), RealSemiring(hi)))
+        })));
+
+        // the real semiring, re-derived from closures
+        let custom = builder.wmc_custom(f, |_, lo, hi| lo + hi, |w, v| w * v, weight, 0.0, 1.0);
+        assert!((custom - f.unsmoothed_wmc(&wmc).0).abs() < 1e-12);
+
+        // maximize over variable 0 and sum over the rest, on the smoothed
+        // diagram, since variable 0 comes first in the order
+        let mixed = builder.wmc_custom(
+            builder.smooth(f, 3),
+            |v, lo: f64, hi| {
+                if v == VarLabel::new(0) {
+                    lo.max(hi)
+                } else {
+                    lo + hi
+                }
+            },
+            |w, v| w * v,
+            weight,
+            0.0,
+            1.0,
+        );
+        // x0 = true leaves x1 \/ x2; x0 = false leaves !x1 /\ x2
+        let expected = f64::max(probs[0] * (1.0 - 0.4 * 0.2), (1.0 - probs[0]) * 0.4 * 0.8);
+        assert!((mixed - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_compile_cnf_ordered() {
+        // x_i <=> y_i for each i, with every x ordered before every y, is
+        // exponential in n; unit clauses at the end then fix every x_i false,
+        // collapsing the result to a single cube
+        let n = 6;
+        let lit = |v: usize, p: bool| Literal::new(VarLabel::new_usize(v), p);
+        let mut clauses = Vec::new();
+        for i in 0..n {
+            clauses.push(vec![lit(i, false), lit(n + i, true)]);
+            clauses.push(vec![lit(i, true), lit(n + i, false)]);
+        }
+        for i in 0..n {
+            clauses.push(vec![lit(i, false)]);
+        }
+        let cnf = Cnf::new(&clauses);
+
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2 * n);
+        let expected = builder.compile_cnf(&cnf);
+        // the largest intermediate BDD when conjoining in the given order
+        let peak = |strategy| {
+            let mut r = BddPtr::true_ptr();
+            let mut peak = 0;
+            for clause in order_clauses(cnf.clauses(), strategy) {
+                r = builder.and(r, builder.clause(clause));
+                peak = peak.max(r.count_nodes());
+            }
+            peak
+        };
+        for strategy in [
+            ClauseOrder::AsGiven,
+            ClauseOrder::BySize,
+            ClauseOrder::ByShared,
+        ] {
+            assert_eq!(builder.compile_cnf_ordered(&cnf, strategy), expected);
+        }
+        assert!(peak(ClauseOrder::ByShared) < peak(ClauseOrder::AsGiven));
+
+        let sizes: Vec<usize> = order_clauses(cnf.clauses(), ClauseOrder::BySize)
+            .iter()
+            .map(|c| c.len())
+            .collect();
+        assert!(sizes.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(
+            builder.compile_cnf_ordered(
+                &Cnf::new(&[vec![lit(0, true)], vec![lit(0, false)]]),
+                ClauseOrder::AsGiven
+            ),
+            BddPtr::false_ptr()
+        );
+    }
+
+    #[test]
+    fn test_peak_node_count() {
+        // as in `test_compile_cnf_ordered`: conjoined as given, the
+        // equivalences blow up before the unit clauses collapse them
+        let n = 6;
+        let lit = |v: usize, p: bool| Literal::new(VarLabel::new_usize(v), p);
+        let mut clauses = Vec::new();
+        for i in 0..n {
+            clauses.push(vec![lit(i, false), lit(n + i, true)]);
+            clauses.push(vec![lit(i, true), lit(n + i, false)]);
+        }
+        for i in 0..n {
+            clauses.push(vec![lit(i, false)]);
+        }
+        let cnf = Cnf::new(&clauses);
+
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2 * n);
+        assert_eq!(builder.peak_node_count(), 0);
+        let bdd = builder.compile_cnf_ordered(&cnf, ClauseOrder::AsGiven);
+        let before_gc = builder.num_nodes();
+        assert_eq!(builder.peak_node_count(), before_gc);
+
+        let fresh = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2 * n);
+        let bdd = builder.gc(&[bdd], &fresh)[0];
+        assert_eq!(fresh.num_nodes(), bdd.count_nodes());
+        assert_eq!(fresh.peak_node_count(), before_gc);
+        assert!(fresh.peak_node_count() > 10 * fresh.num_nodes());
+
+        fresh.reset_peak();
+        assert_eq!(fresh.peak_node_count(), fresh.num_nodes());
+    }
+
+    #[test]
+    fn test_bdd_ptr_ord_is_deterministic() {
+        use rand::seq::SliceRandom;
+        use std::collections::BTreeMap;
+
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let x: Vec<BddPtr> = (0..4)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        let mut ptrs = vec![BddPtr::PtrTrue, BddPtr::PtrFalse];
+        for i in 0..4 {
+            ptrs.push(x[i]);
+            ptrs.push(x[i].neg());
+            ptrs.push(builder.and(x[i], x[(i + 1) % 4]));
+            ptrs.push(builder.xor(x[i], x[(i + 2) % 4]).neg());
+        }
+        ptrs.sort();
+        ptrs.dedup();
+
+        // the order does not depend on the order the pointers arrive in
+        for seed in 0..5 {
+            let mut shuffled = ptrs.clone();
+            shuffled.shuffle(&mut ChaCha8Rng::seed_from_u64(seed));
+            shuffled.sort();
+            assert_eq!(shuffled, ptrs);
+        }
+        assert_eq!(ptrs[0], BddPtr::PtrFalse);
+        assert_eq!(ptrs[1], BddPtr::PtrTrue);
+        // consistent with equality, and a node's two polarities are adjacent
+        for (i, a) in ptrs.iter().enumerate() {
+            for (j, b) in ptrs.iter().enumerate() {
+                assert_eq!(a.cmp(b), i.cmp(&j));
+            }
+        }
+        let a = ptrs.iter().position(|p| *p == x[2]).unwrap();
+        assert_eq!(ptrs[a + 1], x[2].neg());
+
+        let map: BTreeMap<BddPtr, usize> =
+            ptrs.iter().rev().map(|p| (*p, p.count_nodes())).collect();
+        assert!(map.keys().copied().eq(ptrs.iter().copied()));
+    }
+
+    #[test]
+    fn test_variables_and_levels() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(0);
+        assert!(builder.variables().is_empty());
+        let labels: Vec<VarLabel> = (0..3).map(|_| builder.new_var(true).0).collect();
+        assert_eq!(builder.variables(), labels);
+        assert_eq!(
+            builder.levels(),
+            vec![(0, labels[0]), (1, labels[1]), (2, labels[2])]
+        );
+
+        let order = VarOrder::new(&[VarLabel::new(2), VarLabel::new(0), VarLabel::new(1)]);
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new(order, None);
+        let (fresh, _) = builder.new_var(true);
+        assert_eq!(
+            builder.levels(),
+            vec![
+                (0, VarLabel::new(2)),
+                (1, VarLabel::new(0)),
+                (2, VarLabel::new(1)),
+                (3, fresh)
+            ]
+        );
+        for (level, v) in builder.levels() {
+            assert_eq!(builder.order().get(v), level);
+        }
+    }
+
+    #[test]
+    fn test_weight_sensitivities() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(5);
+        let x: Vec<BddPtr> = (0..5)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        // variable 0 sits above the root, and 2 is skipped on some paths
+        let f = builder.or(
+            builder.and(x[1], x[3].neg()),
+            builder.and(x[2], builder.xor(x[3], x[4])),
+        );
+        let weights = [(0.3, 0.7), (0.6, 0.4), (0.0, 1.5), (0.25, 0.5), (2.0, 1.0)];
+        let params = |w: &[(f64, f64)]| {
+            let mut p = WmcParams::default();
+            for (v, (lo, hi)) in w.iter().enumerate() {
+                p.set_weight(VarLabel::new_usize(v), RealSemiring(*lo), RealSemiring(*hi));
+            }
+            p
+        };
+        let z = |w: &[(f64, f64)]| builder.smooth(f, 5).unsmoothed_wmc(&params(w)).0;
+
+        let sens = builder.weight_sensitivities(f, &params(&weights));
+        assert_eq!(sens.len(), 5);
+        let eps = 1e-6;
+        for v in 0..5 {
+            // Z is linear in each weight, so central differences are exact
+            // up to rounding
+            let diff = |high: bool| {
+                let (mut plus, mut minus) = (weights, weights);
+                if high {
+                    plus[v].1 += eps;
+                    minus[v].1 -= eps;
+                } else {
+                    plus[v].0 += eps;
+                    minus[v].0 -= eps;
+                }
+                (z(&plus) - z(&minus)) / (2.0 * eps)
+            };
+            let expected = diff(true) - diff(false);
+            let actual = sens[&VarLabel::new_usize(v)];
+            assert!(
+                (actual - expected).abs() < 1e-6,
+                "variable {}: {} vs {}",
+                v,
+                actual,
+                expected
+            );
+        }
+        // a variable that does not affect `f` only scales it
+        assert!((sens[&VarLabel::new(0)]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_collect_nodes() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(5);
+        assert!(builder.collect_nodes().is_empty());
+        let f = builder.compile_cnf(&Cnf::from_string(
+            "(1 || -2 || 4) && (2 || 3) && (-3 || -4)",
+        ));
+
+        let nodes: Vec<&BddNode> = builder.collect_nodes();
+        assert_eq!(nodes.len(), builder.table_stats().num_nodes);
+        // each node is visited once, and the table is closed under children
+        let addrs: HashSet<*const BddNode> = nodes.iter().map(|n| *n as *const BddNode).collect();
+        assert_eq!(addrs.len(), nodes.len());
+        for n in nodes {
+            for child in [n.low, n.high] {
+                match child {
+                    BddPtr::Reg(c) | BddPtr::Compl(c) => {
+                        assert!(addrs.contains(&(c as *const BddNode)))
+                    }
+                    BddPtr::PtrTrue | BddPtr::PtrFalse => (),
+                }
+            }
+        }
+        assert!(builder.table_stats().num_nodes >= f.count_nodes());
+    }
+
+    #[test]
+    fn test_semantically_eq() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::without_complement_edges(
+            VarOrder::linear_order(3),
+        );
+        let x: Vec<BddPtr> = (0..3)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        // !(x0 /\ (x1 \/ x2)), as a complemented edge and as built by the
+        // builder from De Morgan's laws
+        let f = builder.and(x[0], builder.or(x[1], x[2]));
+        let complemented = f.neg();
+        let explicit = builder.or(
+            builder.negate(x[0]),
+            builder.and(builder.negate(x[1]), builder.negate(x[2])),
+        );
+        assert!(matches!(complemented, BddPtr::Compl(_)));
+        assert!(matches!(explicit, BddPtr::Reg(_)));
+        assert!(complemented != explicit);
+        assert!(builder.semantically_eq(complemented, explicit));
+        assert!(builder.semantically_eq(explicit, complemented));
+        assert!(!builder.semantically_eq(f, explicit));
+        assert!(!builder.semantically_eq(complemented, x[0].neg()));
+
+        // with complemented edges, equivalent functions share a pointer
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let x: Vec<BddPtr> = (0..3)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        let f = builder.and(x[0], builder.or(x[1], x[2])).neg();
+        let g = builder.or(x[0].neg(), builder.and(x[1].neg(), x[2].neg()));
+        assert!(f == g && builder.semantically_eq(f, g));
+    }
+
+    #[test]
+    fn test_with_ite_normalization() {
+        let cnf = Cnf::from_string(
+            "(1 || -2 || 4) && (2 || 3 || -5) && (-1 || 5 || 6) && (3 || -4 || 5) && (-3 || -6 || 2)",
+        );
+        let default = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(7);
+        let reversed = RobddBuilder::<AllIteTable<BddPtr>>::with_ite_normalization(
+            VarOrder::linear_order(7),
+            |a, b, order| match (a.var_safe(), b.var_safe()) {
+                (Some(va), Some(vb)) => order.lt(vb, va),
+                (None, _) => true,
+                (_, None) => false,
+            },
+        );
+        let f = default.compile_cnf(&cnf);
+        let g = reversed.compile_cnf(&cnf);
+        assert_eq!(f.structural_hash(), g.structural_hash());
+        assert_eq!(default.model_count(f), reversed.model_count(g));
+
+        let x: Vec<BddPtr> = (0..7)
+            .map(|i| reversed.var(VarLabel::new(i), true))
+            .collect();
+        for (a, b, c) in [(0, 1, 2), (3, 0, 5), (6, 4, 1)] {
+            let r = reversed.ite(x[a], x[b].neg(), reversed.or(x[c], x[b]));
+            let expected = reversed.or(
+                reversed.and(x[a], x[b].neg()),
+                reversed.and(x[a].neg(), reversed.or(x[c], x[b])),
+            );
+            assert_eq!(r, expected);
+        }
+
+        assert_ne!(
+            default.stats().apply_cache_hits,
+            reversed.stats().apply_cache_hits
+        );
+    }
+
+    #[test]
+    fn test_without_complement_edges_operations() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::without_complement_edges(
+            VarOrder::linear_order(4),
+        );
+        let expr = Expr::parse("!(0 && !1) || (2 ^ 3)").unwrap();
+        let f = builder.compile_expr(&expr);
+        assert!(is_plain(f));
+        // a raw complemented input must not leak into the results
+        let g = f.neg();
+        let cube = PartialModel::from_assignments(&[Some(true), None, None, None]);
+        let rename = HashMap::from([(VarLabel::new(2), VarLabel::new(3))]);
+        let subs = HashMap::from([(VarLabel::new(1), builder.var(VarLabel::new(3), false))]);
+        let results = [
+            builder.restrict(g, &cube),
+            builder.condition(g, VarLabel::new(0), true),
+            builder.condition_model(g, &cube),
+            builder.rename_vars(g, &rename),
+            builder.compose_multiple(g, &subs),
+            builder.smooth(g, 4),
+            builder.and_many_parallel(vec![builder.negate(f), builder.var(VarLabel::new(2), true)]),
+            builder.compile_expr(&Expr::parse("!(!(0 && !1) || (2 ^ 3))").unwrap()),
+        ];
+        for r in results {
+            assert!(is_plain(r));
+        }
+        let not_f = builder.negate(f);
+        assert_eq!(builder.restrict(g, &cube), builder.restrict(not_f, &cube));
+        assert_eq!(results[7], not_f);
+        let fresh = RobddBuilder::<AllIteTable<BddPtr>>::without_complement_edges(
+            VarOrder::linear_order(4),
+        );
+        let roots = builder.gc(&[g], &fresh);
+        assert!(is_plain(roots[0]));
+    }
+
+    #[test]
+    fn test_sample_distinct_zero_mass_variable() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        builder.seed_rng(7);
+        let a = builder.var(VarLabel::new(0), true);
+        let mut wmc = WmcParams::default();
+        wmc.set_default(RealSemiring(0.5), RealSemiring(0.5));
+        wmc.set_weight(VarLabel::new(2), RealSemiring(0.0), RealSemiring(0.0));
+        // `a` has two models once the weightless variable 2 is set aside
+        let samples = builder.sample_distinct(a, 5, &wmc);
+        assert_eq!(samples.len(), 2);
+        for (model, weight) in samples.iter() {
+            assert_eq!(model.get(VarLabel::new(0)), Some(true));
+            assert_eq!(model.get(VarLabel::new(2)), None);
+            assert_eq!(*weight, 0.25);
+        }
+        assert!(samples[0].0 != samples[1].0);
+    }
+
+    #[test]
+    fn test_sample_distinct_stops_at_zero_mass() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(1);
+        let mut wmc = WmcParams::default();
+        wmc.set_weight(VarLabel::new(0), RealSemiring(1.0), RealSemiring(0.0));
+        // the only model of non-zero weight is drawn, then the mass is gone
+        let samples = builder.sample_distinct(BddPtr::true_ptr(), 2, &wmc);
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].0.get(VarLabel::new(0)), Some(false));
+        assert_eq!(samples[0].1, 1.0);
+    }
+
+    #[test]
+    fn test_sample_distinct_first_draw_frequencies() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+        builder.seed_rng(570);
+        let a = builder.var(VarLabel::new(0), true);
+        let b = builder.var(VarLabel::new(1), true);
+        let f = builder.or(a, b);
+        let mut wmc = WmcParams::default();
+        wmc.set_default(RealSemiring(1.0), RealSemiring(1.0));
+        // two of the three equally weighted models set `a`
+        let runs = 20000;
+        let hits = (0..runs)
+            .filter(|_| {
+                let samples = builder.sample_distinct(f, 1, &wmc);
+                samples[0].0.get(VarLabel::new(0)) == Some(true)
+            })
+            .count();
+        let freq = hits as f64 / runs as f64;
+        assert!((freq - 2.0 / 3.0).abs() < 0.02, "P(a) = {freq}");
+    }
+
+    #[test]
+    fn test_structural_hash() {
+        fn build<'a>(builder: &'a RobddBuilder<'a, AllIteTable<BddPtr<'a>>>) -> BddPtr<'a> {
+            let x: Vec<BddPtr> = (0..4)
+                .map(|v| builder.var(VarLabel::new(v), true))
+                .collect();
+            builder.or(builder.and(x[0], x[2]), builder.and(x[1], x[3].neg()))
+        }
+        let linear = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let linear2 = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let interleaved = RobddBuilder::<AllIteTable<BddPtr>>::new(
+            VarOrder::new(&[0, 2, 1, 3].map(VarLabel::new)),
+            None,
+        );
+        let f1 = build(&linear);
+        let f2 = build(&linear2);
+        let f3 = build(&interleaved);
+
+        // identical structure in different builders
+        assert_eq!(f1.structural_hash(), f2.structural_hash());
+        // the same function under a different order has a different structure
+        assert_ne!(f1.count_nodes(), f3.count_nodes());
+        assert_ne!(f1.structural_hash(), f3.structural_hash());
+        assert_ne!(f1.structural_hash(), f1.neg().structural_hash());
+        assert_ne!(
+            BddPtr::PtrTrue.structural_hash(),
+            BddPtr::PtrFalse.structural_hash()
+        );
+        // the fingerprint is memoized in scratch, which is cleared afterwards
+        assert!(f1.is_scratch_cleared());
+    }
+
+    #[test]
+    fn test_wmc_default_weight() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(6);
+        let bdd = builder.compile_cnf(&Cnf::from_string(
+            "(1 || -2 || 3) && (-1 || 4) && (2 || -3 || -4) && (-4 || -3)",
+        ));
+        let mut all_vars = VarSet::new();
+        (0..6).for_each(|v| all_vars.insert(VarLabel::new(v)));
+        let smoothed = builder.smooth_over(bdd, &all_vars);
+
+        let mut uniform = WmcParams::default();
+        uniform.set_default(RealSemiring(1.0), RealSemiring(1.0));
+        let explicit = WmcParams::new(HashMap::from_iter(
+            (0..6).map(|v| (VarLabel::new(v), (RealSemiring(1.0), RealSemiring(1.0)))),
+        ));
+        let count = smoothed.unsmoothed_wmc(&uniform).0;
+        assert_eq!(count, smoothed.unsmoothed_wmc(&explicit).0);
+        assert_eq!(count, builder.model_count(bdd) as f64);
+
+        // explicit weights take precedence over the default
+        uniform.set_weight(VarLabel::new(5), RealSemiring(0.0), RealSemiring(1.0));
+        assert_eq!(smoothed.unsmoothed_wmc(&uniform).0, count / 2.0);
+    }
+
+    #[test]
+    fn test_dyn_finite_field_crt() {
+        // (x0 \/ x7) /\ (x1 \/ x8) /\ ... has 3^7 models over 14 variables
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(14);
+        let clauses: Vec<Vec<Literal>> = (0..7)
+            .map(|i| {
+                vec![
+                    Literal::new(VarLabel::new(i), true),
+                    Literal::new(VarLabel::new(i + 7), true),
+                ]
+            })
+            .collect();
+        let bdd = builder.compile_cnf(&Cnf::new(&clauses));
+        let mut all_vars = VarSet::new();
+        (0..14).for_each(|v| all_vars.insert(VarLabel::new(v)));
+        let smoothed = builder.smooth_over(bdd, &all_vars);
+
+        let count_mod = |p: u64| {
+            let mut params = WmcParams::default();
+            params.set_default(DynFiniteField::new(1, p), DynFiniteField::new(1, p));
+            let r = smoothed.unsmoothed_wmc(&params);
+            assert_eq!(r.modulus, p);
+            r.val
+        };
+        let (p1, p2) = (251, 257);
+        let (a1, a2) = (count_mod(p1), count_mod(p2));
+        assert!(a1 != 3u64.pow(7) && a2 != 3u64.pow(7));
+
+        // x = a1 + p1 * ((a2 - a1) * p1^-1 mod p2), with p1^-1 = p1^(p2 - 2)
+        let inv = (0..p2 - 2).fold(1, |acc, _| acc * p1 % p2);
+        let k = (a2 + p2 - a1 % p2) % p2 * inv % p2;
+        assert_eq!(a1 + p1 * k, 3u64.pow(7));
+    }
+
+    #[test]
+    fn test_exact_model_count_crt_beyond_u64() {
+        // (x0 \/ x1) /\ (x2 \/ x3) /\ ... has 3^41 models over 82 variables
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(82);
+        let clauses: Vec<Vec<Literal>> = (0..41)
+            .map(|i| {
+                vec![
+                    Literal::new(VarLabel::new(2 * i), true),
+                    Literal::new(VarLabel::new(2 * i + 1), true),
+                ]
+            })
+            .collect();
+        let bdd = builder.compile_cnf(&Cnf::new(&clauses));
+        let expected = (0..41).fold(num_bigint::BigUint::from(1u32), |acc, _| acc * 3u32);
+        assert!(expected > num_bigint::BigUint::from(u64::MAX));
+
+        let moduli = [2305843009213693951, 2147483647, 4294967291];
+        assert_eq!(builder.exact_model_count_crt(bdd, &moduli), expected);
+    }
+
+    #[test]
+    fn test_nodes_per_var() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let x: Vec<BddPtr> = (0..4)
+            .map(|v| builder.var(VarLabel::new(v), true))
+            .collect();
+
+        // a chain has one node per variable
+        let chain = builder.and(builder.and(x[0], x[1]), x[2]);
+        let counts = chain.nodes_per_var();
+        assert_eq!(counts.len(), 3);
+        assert!((0..3).all(|v| counts[&VarLabel::new(v)] == 1));
+
+        // (x0 <=> x2) /\ (x1 <=> x3) must remember x0 and x1 while deciding x2
+        let f = builder.and(builder.iff(x[0], x[2]), builder.iff(x[1], x[3]));
+        let counts = f.nodes_per_var();
+        assert_eq!(counts[&VarLabel::new(0)], 1);
+        assert_eq!(counts[&VarLabel::new(1)], 2);
+        assert_eq!(counts[&VarLabel::new(2)], 4);
+        assert_eq!(counts[&VarLabel::new(3)], 1);
+        assert_eq!(counts.values().sum::<usize>(), f.count_nodes());
+    }
+
+    #[test]
+    fn test_path_length_bounds() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let x: Vec<BddPtr> = (0..4)
+            .map(|v| builder.var(VarLabel::new(v), true))
+            .collect();
+
+        // chains
+        let and_chain = builder.and(builder.and(x[0], x[1]), x[2]);
+        assert_eq!(builder.path_length_bounds(and_chain), (3, 3));
+        let or_chain = builder.or(builder.or(x[0], x[1]), x[2]);
+        assert_eq!(builder.path_length_bounds(or_chain), (1, 3));
+        // complemented: !(x0 \/ x1 \/ x2) has a single path through all three
+        assert_eq!(builder.path_length_bounds(or_chain.neg()), (3, 3));
+
+        // balanced
+        let f = builder.or(builder.and(x[0], x[1]), builder.and(x[2], x[3]));
+        assert_eq!(builder.path_length_bounds(f), (2, 4));
+        let parity = x
+            .iter()
+            .fold(BddPtr::PtrFalse, |acc, v| builder.xor(acc, *v));
+        assert_eq!(builder.path_length_bounds(parity), (4, 4));
+
+        assert_eq!(builder.path_length_bounds(BddPtr::PtrTrue), (0, 0));
+        assert_eq!(
+            builder.path_length_bounds(builder.and(x[0], x[0].neg())),
+            (usize::MAX, 0)
+        );
+    }
+
+    #[test]
+    fn test_boolean_expr_provenance() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+        let a = builder.var(VarLabel::new(0), true);
+        let b = builder.var(VarLabel::new(1), true);
+        let f = builder.or(a, b.neg());
+        let weights = WmcParams::new(HashMap::from_iter((0..2).map(|v| {
+            (
+                VarLabel::new_usize(v),
+                (
+                    BooleanExprSemiring::lit(v, false),
+                    BooleanExprSemiring::lit(v, true),
+                ),
+            )
+        })));
+        let provenance = f.unsmoothed_wmc(&weights);
+        for (va, vb) in [(false, false), (false, true), (true, false), (true, true)] {
+            assert_eq!(provenance.eval(&[va, vb]), va || !vb, "{}", provenance);
+        }
+        assert_eq!(
+            BddPtr::PtrFalse.unsmoothed_wmc(&weights),
+            BooleanExprSemiring::zero()
+        );
+        assert_eq!(
+            a.unsmoothed_wmc(&weights),
+            BooleanExprSemiring::lit(0, true)
+        );
+    }
+
+    #[test]
+    fn test_boolean_expr_reset_arena() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+        let f = builder.xor(
+            builder.var(VarLabel::new(0), true),
+            builder.var(VarLabel::new(1), true),
+        );
+        let weights = WmcParams::from_slice(
+            &(0..2)
+                .map(|v| {
+                    (
+                        BooleanExprSemiring::lit(v, false),
+                        BooleanExprSemiring::lit(v, true),
+                    )
+                })
+                .collect::<Vec<_>>(),
         );
+        let before = BooleanExprSemiring::arena_size();
+        f.unsmoothed_wmc(&weights);
+        assert!(BooleanExprSemiring::arena_size() > before);
+        BooleanExprSemiring::reset_arena();
+        assert_eq!(BooleanExprSemiring::arena_size(), 2);
+        let x = BooleanExprSemiring::lit(0, true);
+        assert_eq!(x * BooleanExprSemiring::one(), x);
     }
 
     #[test]
-    fn test_wmc() {
-        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
-        let v1 = builder.var(VarLabel::new(0), true);
-        let v2 = builder.var(VarLabel::new(1), true);
-        let r1 = builder.or(v1, v2);
-        let weights = HashMap::from_iter([
-            (VarLabel::new(0), (RealSemiring(0.2), RealSemiring(0.8))),
-            (VarLabel::new(1), (RealSemiring(0.1), RealSemiring(0.9))),
-        ]);
-        let params = WmcParams::new(weights);
-        let wmc = r1.unsmoothed_wmc(&params);
-        assert!((wmc.0 - (1.0 - 0.2 * 0.1)).abs() < 0.000001);
+    #[should_panic(expected = "BooleanExprSemiring used after reset_arena")]
+    fn test_boolean_expr_stale_after_reset_panics() {
+        let x = BooleanExprSemiring::lit(0, true);
+        let y = BooleanExprSemiring::lit(1, true);
+        BooleanExprSemiring::reset_arena();
+        let _ = x + y;
     }
 
     #[test]
-    fn test_condition() {
+    fn test_expected_value_count_true_vars() {
         let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
-        let v1 = builder.var(VarLabel::new(0), true);
-        let v2 = builder.var(VarLabel::new(1), true);
-        let r1 = builder.or(v1, v2);
-        let r3 = builder.condition(r1, VarLabel::new(1), false);
-        assert!(builder.eq(r3, v1));
+        let a = builder.var(VarLabel::new(0), true);
+        let b = builder.var(VarLabel::new(1), true);
+        let p = [0.3, 0.6, 0.2];
+        let prob = WmcParams::new(HashMap::from_iter((0..3).map(|v| {
+            (
+                VarLabel::new_usize(v),
+                (RealSemiring(1.0 - p[v]), RealSemiring(p[v])),
+            )
+        })));
+        let value = HashMap::from_iter((0..3).map(|v| (VarLabel::new_usize(v), 1.0)));
+
+        // unconditionally, the expected count is the sum of the marginals
+        let ev = builder.expected_value(BddPtr::PtrTrue, &prob, &value);
+        assert!((ev - p.iter().sum::<f64>()).abs() < 1e-9);
+
+        // conditioned on a \/ b; c is independent
+        let z = 1.0 - (1.0 - p[0]) * (1.0 - p[1]);
+        let expected = p[0] / z + p[1] / z + p[2];
+        let ev = builder.expected_value(builder.or(a, b), &prob, &value);
+        assert!((ev - expected).abs() < 1e-9);
     }
 
     #[test]
-    fn test_condition_compl() {
+    fn test_compile_expr_eq_cnf() {
         let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
-        let v1 = builder.var(VarLabel::new(0), false);
-        let v2 = builder.var(VarLabel::new(1), false);
-        let r1 = builder.and(v1, v2);
-        let r3 = builder.condition(r1, VarLabel::new(1), false);
-        assert!(
-            builder.eq(r3, v1),
-            "Not eq:\nOne: {}\nTwo: {}",
-            r3.to_string_debug(),
-            v1.to_string_debug()
+        let a = Rc::new(Expr::Var(VarLabel::new(0)));
+        let b = Rc::new(Expr::Var(VarLabel::new(1)));
+        let c = Rc::new(Expr::Var(VarLabel::new(2)));
+        // (a xor b) /\ (c \/ !a)
+        let e = Expr::And(
+            Rc::new(Expr::Xor(a.clone(), b)),
+            Rc::new(Expr::Or(c, Rc::new(Expr::Not(a)))),
         );
+        // (a \/ b) /\ (!a \/ !b) /\ (c \/ !a), with DIMACS var i as label i-1
+        let cnf = Cnf::from_dimacs("p cnf 3 3\n1 2 0\n-1 -2 0\n3 -1 0\n");
+        let from_expr = builder.compile_expr(&e);
+        let from_cnf = builder.compile_cnf(&cnf);
+        assert!(from_expr == from_cnf);
+
+        let weights = WmcParams::new(HashMap::from_iter((0..3).map(|v| {
+            (
+                VarLabel::new_usize(v),
+                (
+                    RealSemiring(0.8 - 0.2 * v as f64),
+                    RealSemiring(0.2 + 0.2 * v as f64),
+                ),
+            )
+        })));
+        let expected = 0.2 * 0.6 * 0.6 + 0.8 * 0.4;
+        assert!((from_expr.unsmoothed_wmc(&weights).0 - expected).abs() < 1e-9);
+        assert!((from_cnf.unsmoothed_wmc(&weights).0 - expected).abs() < 1e-9);
     }
 
     #[test]
-    fn test_exist() {
-        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
-        // 1 /\ 2 /\ 3
-        let v1 = builder.var(VarLabel::new(0), true);
-        let v2 = builder.var(VarLabel::new(1), true);
-        let v3 = builder.var(VarLabel::new(2), true);
-        let a1 = builder.and(v1, v2);
-        let r1 = builder.and(a1, v3);
-        let r_expected = builder.and(v1, v3);
-        let res = builder.exists(r1, VarLabel::new(1));
-        assert!(
-            builder.eq(r_expected, res),
-            "Got:\nOne: {}\nExpected: {}",
-            res.to_string_debug(),
-            r_expected.to_string_debug()
-        );
+    fn test_compile_parsed_expr() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let parsed = builder.compile_expr(&Expr::parse("(1 ^ 2) && !3").unwrap());
+        let x: Vec<BddPtr> = (0..4)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        let manual = builder.and(builder.xor(x[1], x[2]), x[3].neg());
+        assert_eq!(parsed, manual);
+
+        let weights = WmcParams::new(HashMap::from_iter((0..4).map(|v| {
+            (
+                VarLabel::new_usize(v),
+                (
+                    RealSemiring(0.9 - 0.2 * v as f64),
+                    RealSemiring(0.1 + 0.2 * v as f64),
+                ),
+            )
+        })));
+        // (0.3 * 0.5 + 0.7 * 0.5) * 0.3
+        let expected = 0.15;
+        assert!((parsed.unsmoothed_wmc(&weights).0 - expected).abs() < 1e-9);
+        assert!((manual.unsmoothed_wmc(&weights).0 - expected).abs() < 1e-9);
+
+        // constants fold away
+        let e = Expr::parse("(1 || false) && (true ^ -2)").unwrap();
+        assert_eq!(builder.compile_expr(&e), builder.and(x[1], x[2]));
     }
 
     #[test]
-    fn test_exist_compl() {
-        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
-        // 1 /\ 2 /\ 3
-        let v1 = builder.var(VarLabel::new(0), false);
-        let v2 = builder.var(VarLabel::new(1), false);
-        let v3 = builder.var(VarLabel::new(2), false);
-        let a1 = builder.and(v1, v2);
-        let r1 = builder.and(a1, v3);
-        let r_expected = builder.and(v1, v3);
-        let res = builder.exists(r1, VarLabel::new(1));
-        // let res = r1;
-        assert!(
-            builder.eq(r_expected, res),
-            "Got:\n: {}\nExpected: {}",
-            res.to_string_debug(),
-            r_expected.to_string_debug()
-        );
+    fn test_compile_expr_shared() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let mut e = Rc::new(Expr::Var(VarLabel::new(0)));
+        // each level references the previous one twice, so the tree is
+        // exponential in depth but the DAG is linear
+        for i in 0..64 {
+            let v = Rc::new(Expr::Var(VarLabel::new(i % 4)));
+            e = Rc::new(Expr::Or(
+                Rc::new(Expr::And(e.clone(), v.clone())),
+                Rc::new(Expr::And(e, Rc::new(Expr::Not(v)))),
+            ));
+        }
+        let r = builder.compile_expr(&e);
+        assert!(r == builder.var(VarLabel::new(0), true));
     }
 
     #[test]
-    fn test_compose() {
-        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
-        let v0 = builder.var(VarLabel::new(0), true);
-        let v1 = builder.var(VarLabel::new(1), true);
-        let v2 = builder.var(VarLabel::new(2), true);
-        let v0_and_v1 = builder.and(v0, v1);
-        let v0_and_v2 = builder.and(v0, v2);
-        let res = builder.compose(v0_and_v1, VarLabel::new(1), v2);
-        assert!(
-            builder.eq(res, v0_and_v2),
-            "\nGot: {}\nExpected: {}",
-            res.to_string_debug(),
-            v0_and_v2.to_string_debug()
-        );
+    fn test_to_cnf_tseitin_equisat() {
+        let a = Rc::new(Expr::Var(VarLabel::new(0)));
+        let b = Rc::new(Expr::Var(VarLabel::new(1)));
+        let c = Rc::new(Expr::Var(VarLabel::new(2)));
+        let ab = Rc::new(Expr::Xor(a.clone(), b.clone()));
+        // each expression paired with the number of original variables
+        let exprs = [
+            // (a xor b) /\ (c \/ !a), with the xor shared
+            (
+                Expr::And(
+                    ab.clone(),
+                    Rc::new(Expr::Or(c.clone(), Rc::new(Expr::Not(a.clone())))),
+                ),
+                3,
+            ),
+            (
+                Expr::Not(Rc::new(Expr::Or(ab.clone(), Rc::new(Expr::True)))),
+                2,
+            ),
+            (
+                Expr::Or(Rc::new(Expr::And(a.clone(), b)), Rc::new(Expr::False)),
+                2,
+            ),
+            (Expr::And(a.clone(), Rc::new(Expr::Not(a))), 1),
+            (Expr::Not(Rc::new(Expr::Xor(ab, c))), 3),
+        ];
+        let mut weights = WmcParams::default();
+        weights.set_default(RealSemiring(0.5), RealSemiring(0.5));
+        for (e, num_orig) in exprs.iter() {
+            let (cnf, out) = e.to_cnf_tseitin();
+            assert!(out.value_usize() >= *num_orig);
+            let builder =
+                RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(cnf.num_vars());
+            let direct = builder.compile_expr(e);
+            let encoded = builder.compile_cnf(&cnf);
+            assert_eq!(direct.is_false(), encoded.is_false());
+            // projecting out the auxiliary variables recovers the expression
+            let aux: Vec<VarLabel> = (*num_orig..cnf.num_vars())
+                .map(VarLabel::new_usize)
+                .collect();
+            let projected = builder.exists_many(encoded, &aux);
+            let expected = direct.unsmoothed_wmc(&weights).0;
+            assert!((projected.unsmoothed_wmc(&weights).0 - expected).abs() < 1e-9);
+            assert!(projected == direct);
+        }
     }
 
     #[test]
-    fn test_compose_2() {
-        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
-        let v0 = builder.var(VarLabel::new(0), true);
-        let v1 = builder.var(VarLabel::new(1), true);
-        let v2 = builder.var(VarLabel::new(2), true);
-        let v3 = builder.var(VarLabel::new(3), true);
-        let v0_and_v1 = builder.and(v0, v1);
-        let v2_and_v3 = builder.and(v2, v3);
-        let v0v2v3 = builder.and(v0, v2_and_v3);
-        let res = builder.compose(v0_and_v1, VarLabel::new(1), v2_and_v3);
-        assert!(
-            builder.eq(res, v0v2v3),
-            "\nGot: {}\nExpected: {}",
-            res.to_string_debug(),
-            v0v2v3.to_string_debug()
-        );
+    fn test_prime_implicants_mux() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
+        let lit = |v: u64, p: bool| Literal::new(VarLabel::new(v), p);
+        let a = builder.var(VarLabel::new(0), true);
+        let b = builder.var(VarLabel::new(1), true);
+        let c = builder.var(VarLabel::new(2), true);
+        // a ? b : c has primes ab, !ac and the consensus term bc
+        let f = builder.ite(a, b, c);
+        let mut expected = vec![
+            vec![lit(0, true), lit(1, true)],
+            vec![lit(0, false), lit(2, true)],
+            vec![lit(1, true), lit(2, true)],
+        ];
+        expected.sort();
+        assert_eq!(builder.prime_implicants(f), expected);
+
+        // every prime implies f, and the primes together cover f
+        let cover = expected.iter().fold(BddPtr::false_ptr(), |acc, cube| {
+            let cube = cube.iter().fold(BddPtr::true_ptr(), |acc, l| {
+                builder.and(acc, builder.var(l.label(), l.polarity()))
+            });
+            assert!(builder.imp(cube, f).is_true());
+            builder.or(acc, cube)
+        });
+        assert!(cover == f);
+
+        assert_eq!(builder.prime_implicants(f.neg()).len(), 3);
+        assert_eq!(builder.prime_implicants(BddPtr::true_ptr()), vec![vec![]]);
+        assert!(builder.prime_implicants(BddPtr::false_ptr()).is_empty());
     }
 
     #[test]
-    fn test_compose_3() {
+    fn test_project() {
         let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
-        let v0 = builder.var(VarLabel::new(0), true);
-        let v1 = builder.var(VarLabel::new(1), true);
-        let v2 = builder.var(VarLabel::new(2), true);
-        let f = builder.ite(v0, BddPtr::false_ptr(), v1);
-        let res = builder.compose(f, VarLabel::new(1), v2);
-        let expected = builder.ite(v0, BddPtr::false_ptr(), v2);
-        assert!(
-            builder.eq(res, expected),
-            "\nGot: {}\nExpected: {}",
-            res.to_string_debug(),
-            expected.to_string_debug()
-        );
+        let a = builder.var(VarLabel::new(0), true);
+        let b = builder.var(VarLabel::new(1), true);
+        let c = builder.var(VarLabel::new(2), true);
+        let d = builder.var(VarLabel::new(3), true);
+        let f = builder.and(a, builder.or(b, c));
+        let mut keep = VarSet::new();
+        keep.insert(VarLabel::new(0));
+        assert_eq!(builder.project(f, &keep), a);
+
+        // agrees with quantifying the complement of `keep` one at a time
+        let g = builder.xor(builder.and(a, d), builder.or(b, c.neg())).neg();
+        keep.insert(VarLabel::new(3));
+        let drop = [VarLabel::new(1), VarLabel::new(2)];
+        assert_eq!(builder.project(g, &keep), builder.exists_many(g, &drop));
+        assert_eq!(builder.project(g, &VarSet::new()), BddPtr::true_ptr());
     }
 
     #[test]
-    fn test_compose_4() {
-        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(20);
-        let v0 = builder.var(VarLabel::new(4), true);
-        let v1 = builder.var(VarLabel::new(5), true);
-        let v2 = builder.var(VarLabel::new(6), true);
-        let f = builder.ite(v1, BddPtr::false_ptr(), v2);
-        let res = builder.compose(f, VarLabel::new(6), v0);
-        let expected = builder.ite(v1, BddPtr::false_ptr(), v0);
-        assert!(
-            builder.eq(res, expected),
-            "\nGot: {}\nExpected: {}",
-            res.to_string_debug(),
-            expected.to_string_debug()
+    fn test_image_two_state() {
+        // a two-state system over one bit, where state 0 may step to either
+        // state and state 1 always steps back to 0; vars: x = 0, x' = 1
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+        let (x, x_next) = (VarLabel::new(0), VarLabel::new(1));
+        let trans = builder.or(
+            builder.var(x, false),
+            builder.and(builder.var(x, true), builder.var(x_next, false)),
         );
+        let cur_to_next = HashMap::from([(x, x_next)]);
+        let s0 = builder.var(x, false);
+        let s1 = builder.var(x, true);
+        assert!(builder.image(trans, s0, &cur_to_next).is_true());
+        assert_eq!(builder.image(trans, s1, &cur_to_next), s0);
+        assert!(builder
+            .image(trans, BddPtr::false_ptr(), &cur_to_next)
+            .is_false());
     }
 
     #[test]
-    fn test_new_label() {
-        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(0);
-        let vlbl1 = builder.new_label();
-        let vlbl2 = builder.new_label();
-        let v1 = builder.var(vlbl1, false);
-        let v2 = builder.var(vlbl2, false);
-        let r1 = builder.and(v1, v2);
-        let r3 = builder.condition(r1, VarLabel::new(1), false);
-        assert!(
-            builder.eq(r3, v1),
-            "Not eq:\nOne: {}\nTwo: {}",
-            r3.to_string_debug(),
-            v1.to_string_debug()
+    fn test_reachable_ring() {
+        // a 2-bit counter stepping s -> s + 1 mod 4; current-state bits are
+        // x0, x1 (vars 0, 1) and next-state bits are vars 2, 3
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let x: Vec<BddPtr> = (0..4)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        let trans = builder.and(
+            builder.iff(x[2], x[0].neg()),
+            builder.iff(x[3], builder.xor(x[1], x[0])),
         );
+        let cur_to_next = HashMap::from([
+            (VarLabel::new(0), VarLabel::new(2)),
+            (VarLabel::new(1), VarLabel::new(3)),
+        ]);
+        let init = builder.and(x[0].neg(), x[1].neg());
+        let all = builder.reachable(trans, init, &cur_to_next, None);
+        assert!(all.is_true());
+
+        // a single step reaches only states 0 and 1, i.e. x1 = 0
+        let partial = builder.reachable(trans, init, &cur_to_next, Some(1));
+        assert_eq!(partial, x[1].neg());
+        assert_eq!(builder.reachable(trans, init, &cur_to_next, Some(0)), init);
     }
 
     #[test]
-    fn circuit1() {
+    fn test_support_vacuous_var() {
         let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
-        let x = builder.var(VarLabel::new(0), false);
-        let y = builder.var(VarLabel::new(1), true);
-        let delta = builder.and(x, y);
-        let yp = builder.var(VarLabel::new(2), true);
-        let inner = builder.iff(yp, y);
-        let conj = builder.and(inner, delta);
-        let res = builder.exists(conj, VarLabel::new(1));
+        let x1 = builder.var(VarLabel::new(1), true);
+        let x2 = builder.var(VarLabel::new(2), true);
+        let f = builder.xor(x1, x2);
+        let mut expected = VarSet::new();
+        expected.insert(VarLabel::new(1));
+        expected.insert(VarLabel::new(2));
+        assert_eq!(builder.support(f), expected);
+        assert_eq!(builder.essential_support(f), expected);
 
-        let expected = builder.and(x, yp);
-        assert!(
-            builder.eq(res, expected),
-            "Not eq:\nGot: {}\nExpected: {}",
-            res.to_string_debug(),
-            expected.to_string_debug()
-        );
+        // an unreduced node on x0 whose branches agree: x0 is vacuous
+        let g = builder.get_or_insert(BddNode::new(VarLabel::new(0), f, f));
+        let mut structural = expected.clone();
+        structural.insert(VarLabel::new(0));
+        assert_eq!(builder.support(g), structural);
+        assert_eq!(builder.essential_support(g), expected);
+
+        assert!(builder.support(BddPtr::PtrTrue).is_empty());
     }
 
     #[test]
-    fn simple_cond() {
-        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(3);
-        let x = builder.var(VarLabel::new(0), true);
-        let y = builder.var(VarLabel::new(1), false);
-        let z = builder.var(VarLabel::new(2), false);
-        let r1 = builder.and(x, y);
-        let r2 = builder.and(r1, z);
-        // now r2 is x /\ !y /\ !z
+    fn test_apply_cache_stats() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(6);
+        let cnf = Cnf::from_string("(1 || 2 || -3) && (-2 || 4) && (3 || -4 || 5) && (-1 || -5)");
+        builder.compile_cnf(&cnf);
+        let first = builder.stats();
+        assert!(first.apply_cache_misses > 0);
 
-        let res = builder.condition(r2, VarLabel::new(1), true); // condition on y=T
-        let expected = BddPtr::false_ptr();
-        assert!(
-            builder.eq(res, expected),
-            "\nOriginal BDD: {}\nNot eq:\nGot: {}\nExpected: {}",
-            r2.to_string_debug(),
-            res.to_string_debug(),
-            expected.to_string_debug()
-        );
+        // recompiling the same formula re-asks the same ITEs
+        builder.compile_cnf(&cnf);
+        let second = builder.stats();
+        assert!(second.apply_cache_hits > first.apply_cache_hits);
+        assert_eq!(second.apply_cache_misses, first.apply_cache_misses);
     }
 
     #[test]
-    fn wmc_test_2() {
-        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
-        let x = builder.var(VarLabel::new(0), true);
-        let y = builder.var(VarLabel::new(1), true);
-        let f1 = builder.var(VarLabel::new(2), true);
-        let f2 = builder.var(VarLabel::new(3), true);
+    fn test_with_capacity_fewer_grows() {
+        // (x0 \/ x8) /\ (x1 \/ x9) /\ ... is exponential in the linear order
+        let clauses: Vec<Vec<Literal>> = (0..8)
+            .map(|i| {
+                vec![
+                    Literal::new(VarLabel::new(i), true),
+                    Literal::new(VarLabel::new(i + 8), true),
+                ]
+            })
+            .collect();
+        let cnf = Cnf::new(&clauses);
+        let small =
+            RobddBuilder::<AllIteTable<BddPtr>>::with_capacity(VarOrder::linear_order(16), 4);
+        let large =
+            RobddBuilder::<AllIteTable<BddPtr>>::with_capacity(VarOrder::linear_order(16), 16);
+        let r_small = small.compile_cnf(&cnf);
+        let r_large = large.compile_cnf(&cnf);
+        assert_eq!(small.model_count(r_small), 3u128.pow(8));
+        assert_eq!(large.model_count(r_large), 3u128.pow(8));
+        assert!(small.num_nodes() > 16);
+        assert!(small.stats().num_table_resizes > 0);
+        assert_eq!(large.stats().num_table_resizes, 0);
+    }
 
-        let map = HashMap::from_iter([
-            (VarLabel::new(0), (RealSemiring(1.0), RealSemiring(1.0))),
-            (VarLabel::new(1), (RealSemiring(1.0), RealSemiring(1.0))),
-            (VarLabel::new(2), (RealSemiring(0.8), RealSemiring(0.2))),
-            (VarLabel::new(3), (RealSemiring(0.7), RealSemiring(0.3))),
-        ]);
+    #[test]
+    fn test_try_compile_cnf_timeout() {
+        // (x0 \/ x10) /\ (x1 \/ x11) /\ ... is exponential in the linear order
+        let clauses: Vec<Vec<Literal>> = (0..10)
+            .map(|i| {
+                vec![
+                    Literal::new(VarLabel::new(i), true),
+                    Literal::new(VarLabel::new(i + 10), true),
+                ]
+            })
+            .collect();
+        let cnf = Cnf::new(&clauses);
 
-        let wmc = WmcParams::new(map);
-        let iff1 = builder.iff(x, f1);
-        let iff2 = builder.iff(y, f2);
-        let obs = builder.or(x, y);
-        let and1 = builder.and(iff1, iff2);
-        let f = builder.and(and1, obs);
-        assert_eq!(f.unsmoothed_wmc(&wmc).0, 0.2 * 0.3 + 0.2 * 0.7 + 0.8 * 0.3);
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new(
+            VarOrder::linear_order(20),
+            Some((Instant::now(), Duration::ZERO)),
+        );
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(builder.try_compile_cnf(&cnf), Err(TimeoutError));
+
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(20);
+        let r = builder.try_compile_cnf(&cnf).unwrap();
+        assert_eq!(builder.model_count(r), 3u128.pow(10));
     }
 
     #[test]
-    fn test_ite_1() {
+    #[should_panic(expected = "model count overflowed u128")]
+    fn test_model_count_overflow_panics() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(128);
+        builder.model_count(BddPtr::PtrTrue);
+    }
+
+    #[test]
+    fn test_model_count_127_free_vars() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(128);
+        let x = builder.var(VarLabel::new(0), true);
+        assert_eq!(builder.model_count(x), 1u128 << 127);
+        assert_eq!(builder.model_count(x.neg()), 1u128 << 127);
+    }
+
+    #[test]
+    fn test_gc() {
         let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(16);
-        let c1 = Cnf::from_string("(1 || 2) && (0 || -2)");
-        let c2 = Cnf::from_string("(0 || 1) && (-4 || -7)");
-        let cnf1 = builder.compile_cnf(&c1);
-        let cnf2 = builder.compile_cnf(&c2);
-        let iff1 = builder.iff(cnf1, cnf2);
+        let kept = builder.compile_cnf(&Cnf::from_string("(0 || 1) && (-1 || 2)"));
+        // compile and then discard a larger formula
+        let discarded = builder.compile_cnf(&Cnf::from_string(
+            "(3 || 4 || -5) && (5 || 6) && (-7 || 8 || 9) && (10 || -11) && (12 || 13 || 14)",
+        ));
+        assert!(!discarded.is_false());
 
-        let clause1 = builder.and(cnf1, cnf2);
-        let clause2 = builder.and(cnf1.neg(), cnf2.neg());
-        let and = builder.or(clause1, clause2);
+        let fresh = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(16);
+        let roots = builder.gc(&[kept], &fresh);
+        assert_eq!(roots.len(), 1);
+        assert!(fresh.num_nodes() < builder.num_nodes());
+        assert_eq!(fresh.num_nodes(), roots[0].count_nodes());
 
-        if and != iff1 {
-            println!("cnf1: {}", c1);
-            println!("cnf2: {}", c2);
-            println!(
-                "not equal:\nBdd1: {}\nBdd2: {}",
-                and.to_string_debug(),
-                iff1.to_string_debug()
-            );
-        }
-        assert_eq!(and, iff1);
+        // rebuilding the kept formula after collection yields the same pointer
+        let rebuilt = fresh.compile_cnf(&Cnf::from_string("(0 || 1) && (-1 || 2)"));
+        assert!(fresh.eq(roots[0], rebuilt));
     }
 
     #[test]
@@ -1000,6 +6371,7 @@ mod tests {
 
         assert_eq!(unsmoothed_model_count.value(), 3);
         assert_eq!(smoothed_model_count.value(), 7);
+        assert_eq!(builder.model_count(bdd), smoothed_model_count.value());
     }
 
     #[test]
@@ -1028,6 +6400,59 @@ mod tests {
         assert_eq!(weighted_model_count.0, 0.54);
     }
 
+    #[test]
+    fn exact_wmc_rational_simple() {
+        // the CNF of `smoothed_weighted_model_count_with_finite_field_simple`
+        let cnf = Cnf::from_dimacs("p cnf 2 2\n-1 2 0\n1 -2 0\n");
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(cnf.num_vars());
+        let bdd = builder.compile_cnf(&cnf);
+        let wmc = WmcParams::new(HashMap::from_iter([
+            (
+                VarLabel::new(0),
+                (RationalSemiring::new(2, 5), RationalSemiring::new(3, 5)),
+            ),
+            (
+                VarLabel::new(1),
+                (RationalSemiring::new(3, 10), RationalSemiring::new(7, 10)),
+            ),
+        ]));
+        // 0.4 * 0.3 + 0.6 * 0.7 = 0.54, exactly
+        let r = builder.exact_wmc_rational(bdd, &wmc).unwrap();
+        assert_eq!((r.numerator(), r.denominator()), (27, 50));
+        // a variable missing from the diagram contributes its total weight
+        let a = builder.var(VarLabel::new(0), true);
+        assert_eq!(
+            builder.exact_wmc_rational(a, &wmc),
+            Some(RationalSemiring::new(3, 5))
+        );
+    }
+
+    #[test]
+    fn exact_wmc_rational_many_vars() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(30);
+        let thirds = WmcParams::from_slice(
+            &[(RationalSemiring::new(2, 3), RationalSemiring::new(1, 3)); 30],
+        );
+        // some of 30 variables is set with probability 1 - (2/3)^30
+        let disj = (0..30).fold(BddPtr::false_ptr(), |acc, v| {
+            builder.or(acc, builder.var(VarLabel::new(v), true))
+        });
+        let pow = 3i128.pow(30);
+        assert_eq!(
+            builder.exact_wmc_rational(disj, &thirds),
+            Some(RationalSemiring::new(pow - 2i128.pow(30), pow))
+        );
+
+        // (1/97)^24 does not fit in an i128
+        let conj = (0..24).fold(BddPtr::true_ptr(), |acc, v| {
+            builder.and(acc, builder.var(VarLabel::new(v), true))
+        });
+        let ninety_sevenths = WmcParams::from_slice(
+            &[(RationalSemiring::new(96, 97), RationalSemiring::new(1, 97)); 30],
+        );
+        assert_eq!(builder.exact_wmc_rational(conj, &ninety_sevenths), None);
+    }
+
     #[test]
     fn wmc_test_with_finite_field_complex() {
         static CNF: &str = "
@@ -1056,24 +6481,17 @@ mod tests {
             ]),
         ));
 
-        // TODO: this WMC test is broken. not sure why :(
-        // let weighted_model_count = smoothed.unsmoothed_wmc(
-        //     builder.order(),
-        //     &WmcParams::new(HashMap::from_iter([
-        //         // (VarLabel::new(0), (RealSemiring(0.10), RealSemiring(0.05))),
-        //         // (VarLabel::new(1), (RealSemiring(0.20), RealSemiring(0.15))),
-        //         // (VarLabel::new(2), (RealSemiring(0.30), RealSemiring(0.25))),
-        //         // (VarLabel::new(3), (RealSemiring(0.40), RealSemiring(0.35))),
-        //         // (VarLabel::new(4), (RealSemiring(0.50), RealSemiring(0.45))),
-        //         // (VarLabel::new(5), (RealSemiring(0.60), RealSemiring(0.55))),
-        //         (VarLabel::new(0), (RealSemiring(0.05), RealSemiring(0.10))),
-        //         (VarLabel::new(1), (RealSemiring(0.15), RealSemiring(0.20))),
-        //         (VarLabel::new(2), (RealSemiring(0.25), RealSemiring(0.30))),
-        //         (VarLabel::new(3), (RealSemiring(0.35), RealSemiring(0.40))),
-        //         (VarLabel::new(4), (RealSemiring(0.45), RealSemiring(0.50))),
-        //         (VarLabel::new(5), (RealSemiring(0.55), RealSemiring(0.60))),
-        //     ])),
-        // );
+        // `c weights` lists the weight of each variable's positive literal
+        // first, while `WmcParams` takes `(low, high)` pairs, i.e. the
+        // negative literal's weight first
+        let weighted_model_count = smoothed.unsmoothed_wmc(&WmcParams::new(HashMap::from_iter([
+            (VarLabel::new(0), (RealSemiring(0.10), RealSemiring(0.05))),
+            (VarLabel::new(1), (RealSemiring(0.20), RealSemiring(0.15))),
+            (VarLabel::new(2), (RealSemiring(0.30), RealSemiring(0.25))),
+            (VarLabel::new(3), (RealSemiring(0.40), RealSemiring(0.35))),
+            (VarLabel::new(4), (RealSemiring(0.50), RealSemiring(0.45))),
+            (VarLabel::new(5), (RealSemiring(0.60), RealSemiring(0.55))),
+        ])));
 
         // verified with pysdd
         //
@@ -1100,6 +6518,7 @@ mod tests {
         // done
 
         assert_eq!(model_count.value(), 48);
-        // assert_eq!(weighted_model_count.0, 0.017015015625000005);
+        assert_eq!(builder.model_count(bdd), model_count.value());
+        assert!((weighted_model_count.0 - 0.017015015625000005).abs() < 1e-15);
     }
 }
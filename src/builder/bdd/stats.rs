@@ -5,12 +5,24 @@ pub struct BddBuilderStats {
     /// this should probably be gated behind a debug build (since I suspect
     /// it may have non-trivial performance overhead and synchronization cost)
     pub num_recursive_calls: usize,
+    /// Number of apply-cache lookups that found a cached result
+    pub apply_cache_hits: usize,
+    /// Number of apply-cache lookups that missed
+    pub apply_cache_misses: usize,
+    /// Number of entries a bounded apply cache has evicted
+    pub apply_cache_evictions: usize,
+    /// Number of times the node table has been resized
+    pub num_table_resizes: usize,
 }
 
 impl BddBuilderStats {
     pub fn new() -> BddBuilderStats {
         BddBuilderStats {
             num_recursive_calls: 0,
+            apply_cache_hits: 0,
+            apply_cache_misses: 0,
+            apply_cache_evictions: 0,
+            num_table_resizes: 0,
         }
     }
 }
@@ -0,0 +1,146 @@
+//! Weighted model counting that is kept up to date as weights change
+
+use crate::{
+    repr::{BddPtr, VarLabel, VarOrder, WmcParams},
+    util::semirings::Semiring,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Computes the unsmoothed weighted model count of a fixed BDD under weights
+/// that change over time, e.g. across the steps of a gradient descent. The
+/// value of every node from the last count is cached. Changing the weight of
+/// a variable only invalidates the nodes at or above its level, since the
+/// nodes below it do not depend on it, and those are recomputed on the next
+/// call to `wmc`. Updating a variable near the bottom of the order is cheap.
+/// ```
+/// use rsdd::builder::bdd::{RobddBuilder, WmcSession};
+/// use rsdd::builder::BottomUpBuilder;
+/// use rsdd::builder::cache::AllIteTable;
+/// use rsdd::repr::{BddPtr, VarLabel, WmcParams};
+/// use rsdd::util::semirings::RealSemiring;
+///
+/// let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(2);
+/// let a = builder.var(VarLabel::new(0), true);
+/// let b = builder.var(VarLabel::new(1), true);
+/// let mut params = WmcParams::default();
+/// params.set_default(RealSemiring(0.5), RealSemiring(0.5));
+///
+/// let mut session = WmcSession::new(builder.and(a, b), builder.order(), params);
+/// assert_eq!(session.wmc(), RealSemiring(0.25));
+/// session.update_weight(VarLabel::new(1), RealSemiring(0.0), RealSemiring(1.0));
+/// assert_eq!(session.wmc(), RealSemiring(0.5));
+/// ```
+pub struct WmcSession<'a, T: Semiring> {
+    root: BddPtr<'a>,
+    params: WmcParams<T>,
+    /// the level of each variable, indexed by label
+    var_level: Vec<usize>,
+    /// the pointers reachable from `root` at each level
+    by_level: Vec<Vec<BddPtr<'a>>>,
+    /// the value of each pointer under the current weights, if known
+    cache: HashMap<BddPtr<'a>, T>,
+}
+
+impl<'a, T: Semiring> WmcSession<'a, T> {
+    /// Starts a session counting `root` under `params`, where `order` is the
+    /// variable order of the builder that produced `root`. Nothing is
+    /// computed until the first call to `wmc`.
+    #[allow(clippy::mutable_key_type)]
+    pub fn new(root: BddPtr<'a>, order: &VarOrder, params: WmcParams<T>) -> WmcSession<'a, T> {
+        let var_level: Vec<usize> = (0..order.num_vars())
+            .map(|v| order.get(VarLabel::new_usize(v)))
+            .collect();
+        let mut by_level = vec![Vec::new(); order.num_vars()];
+        let mut seen = HashSet::new();
+        let mut stack = vec![root];
+        while let Some(ptr) = stack.pop() {
+            let var = match ptr.var_safe() {
+                Some(var) => var,
+                None => continue,
+            };
+            if !seen.insert(ptr) {
+                continue;
+            }
+            by_level[var_level[var.value_usize()]].push(ptr);
+            stack.push(ptr.low());
+            stack.push(ptr.high());
+        }
+        WmcSession {
+            root,
+            params,
+            var_level,
+            by_level,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// The weighted model count of the root under the current weights,
+    /// recomputing only the nodes invalidated since the last call
+    pub fn wmc(&mut self) -> T {
+        self.root
+            .unsmoothed_wmc_cached(&self.params, &mut self.cache)
+    }
+
+    /// Sets the `(low, high)` weight of `var`, invalidating the cached value
+    /// of every node at or above its level
+    pub fn update_weight(&mut self, var: VarLabel, low: T, high: T) {
+        self.params.set_weight(var, low, high);
+        let level = self.var_level[var.value_usize()];
+        for ptr in self.by_level[..=level].iter().flatten() {
+            self.cache.remove(ptr);
+        }
+    }
+
+    /// The current weights
+    pub fn params(&self) -> &WmcParams<T> {
+        &self.params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WmcSession;
+    use crate::builder::bdd::RobddBuilder;
+    use crate::builder::cache::AllIteTable;
+    use crate::builder::BottomUpBuilder;
+    use crate::repr::{BddPtr, Cnf, DDNNFPtr, VarLabel, WmcParams};
+    use crate::util::semirings::RealSemiring;
+
+    #[test]
+    fn incremental_wmc_matches_recompute() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(6);
+        let f = builder.compile_cnf(&Cnf::from_string(
+            "(1 || -2 || 4) && (2 || 3 || -5) && (-1 || 5) && (3 || -4 || 5)",
+        ));
+        let mut params = WmcParams::default();
+        for v in 0..6 {
+            let p = 0.1 + 0.15 * v as f64;
+            params.set_weight(VarLabel::new(v), RealSemiring(1.0 - p), RealSemiring(p));
+        }
+        let mut session = WmcSession::new(f, builder.order(), params.clone());
+        assert_eq!(session.wmc(), f.unsmoothed_wmc(&params));
+        let cached = session.cache.len();
+
+        let updates = [
+            (5, 0.3, 0.9),
+            (1, 0.5, 0.5),
+            (4, 2.0, 0.25),
+            (5, 0.0, 1.0),
+            (3, 0.7, 0.1),
+        ];
+        for (v, lo, hi) in updates {
+            let (v, lo, hi) = (VarLabel::new(v), RealSemiring(lo), RealSemiring(hi));
+            session.update_weight(v, lo, hi);
+            params.set_weight(v, lo, hi);
+            let expected = f.unsmoothed_wmc(&params).0;
+            assert!((session.wmc().0 - expected).abs() < 1e-12);
+            assert_eq!(session.cache.len(), cached);
+        }
+
+        // the nodes below an updated variable keep their cached values
+        session.update_weight(VarLabel::new(4), RealSemiring(0.5), RealSemiring(0.5));
+        let kept = session.by_level[5].len() + 2;
+        assert!(session.cache.len() >= kept);
+        assert!(session.cache.len() < cached);
+    }
+}
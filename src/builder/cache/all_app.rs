@@ -1,16 +1,19 @@
 //! Apply cache for BDD operations that stores all ITEs
 
 use crate::{
-    builder::cache::{Ite, IteTable},
+    builder::cache::{Ite, IteTable, IteTableStats},
     repr::DDNNFPtr,
 };
 use rustc_hash::FxHashMap;
+use std::cell::Cell;
 
 /// An Ite structure, assumed to be in standard form.
 /// The top-level data structure that caches applications
 pub struct AllIteTable<T> {
     /// a vector of applications, indexed by the top label of the first pointer.
     table: FxHashMap<(T, T, T), T>,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
 }
 
 impl<'a, T: DDNNFPtr<'a>> IteTable<'a, T> for AllIteTable<T> {
@@ -36,6 +39,11 @@ impl<'a, T: DDNNFPtr<'a>> IteTable<'a, T> for AllIteTable<T> {
         match ite {
             Ite::IteChoice { f, g, h } | Ite::IteComplChoice { f, g, h } => {
                 let r = self.table.get(&(f, g, h));
+                if r.is_some() {
+                    self.hits.set(self.hits.get() + 1);
+                } else {
+                    self.misses.set(self.misses.get() + 1);
+                }
                 let compl = ite.is_compl_choice();
                 if compl {
                     r.map(|v| v.neg())
@@ -46,12 +54,22 @@ impl<'a, T: DDNNFPtr<'a>> IteTable<'a, T> for AllIteTable<T> {
             Ite::IteConst(f) => Some(f),
         }
     }
+
+    fn stats(&self) -> IteTableStats {
+        IteTableStats {
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+            evictions: 0,
+        }
+    }
 }
 
 impl<'a, T: DDNNFPtr<'a>> AllIteTable<T> {
     fn new() -> AllIteTable<T> {
         AllIteTable {
             table: FxHashMap::default(),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
         }
     }
 }
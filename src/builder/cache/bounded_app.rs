@@ -0,0 +1,255 @@
+//! Apply cache for ITEs that holds at most a fixed number of entries
+
+use crate::{
+    builder::cache::{Ite, IteTable, IteTableStats},
+    repr::DDNNFPtr,
+};
+use rustc_hash::FxHashMap;
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    hash::Hash,
+};
+
+/// number of entries kept by `BoundedIteTable::default()`
+const DEFAULT_CAPACITY: usize = 1 << 20;
+
+/// an ITE in standard form, as stored in the table
+type IteKey<T> = (T, T, T);
+
+/// An apply cache that stores at most `capacity` ITEs, evicting the least
+/// recently used entry (by insertion or successful lookup) to make room for
+/// a new one. Unlike `AllIteTable`, its memory use does not grow with the
+/// length of a compilation; an evicted result is simply recomputed if it is
+/// needed again, so eviction never affects the result of an operation.
+pub struct BoundedIteTable<T> {
+    /// each entry stores its result and the tick at which it was last used
+    table: RefCell<FxHashMap<IteKey<T>, (T, u64)>>,
+    /// keys of `table` by last-use tick; the first entry is evicted next
+    recency: RefCell<BTreeMap<u64, IteKey<T>>>,
+    clock: Cell<u64>,
+    capacity: usize,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
+    evictions: usize,
+}
+
+impl<'a, T: DDNNFPtr<'a>> IteTable<'a, T> for BoundedIteTable<T> {
+    fn hash(&self, _ite: &Ite<T>) -> u64 {
+        // unused; entries are located by the underlying hash map
+        0
+    }
+
+    /// Insert an ite (f, g, h) into the apply table
+    fn insert(&mut self, ite: Ite<T>, res: T, _hash: u64) {
+        match ite {
+            Ite::IteChoice { f, g, h } | Ite::IteComplChoice { f, g, h } => {
+                if self.capacity == 0 {
+                    return;
+                }
+                let compl = ite.is_compl_choice();
+                let res = if compl { res.neg() } else { res };
+                let key = (f, g, h);
+                let tick = self.tick();
+                let table = self.table.get_mut();
+                let recency = self.recency.get_mut();
+                if let Some((_, old_tick)) = table.insert(key, (res, tick)) {
+                    recency.remove(&old_tick);
+                } else if table.len() > self.capacity {
+                    let (_, lru) = recency.pop_first().unwrap();
+                    table.remove(&lru);
+                    self.evictions += 1;
+                }
+                recency.insert(tick, key);
+            }
+            Ite::IteConst(_) => (), // do not cache base-cases
+        }
+    }
+
+    fn get(&self, ite: Ite<T>, _hash: u64) -> Option<T> {
+        match ite {
+            Ite::IteChoice { f, g, h } | Ite::IteComplChoice { f, g, h } => {
+                let key = (f, g, h);
+                let r = match self.table.borrow_mut().get_mut(&key) {
+                    Some((v, last_used)) => {
+                        // mark the entry as most recently used
+                        let tick = self.tick();
+                        let mut recency = self.recency.borrow_mut();
+                        recency.remove(last_used);
+                        recency.insert(tick, key);
+                        *last_used = tick;
+                        Some(*v)
+                    }
+                    None => None,
+                };
+                if r.is_some() {
+                    self.hits.set(self.hits.get() + 1);
+                } else {
+                    self.misses.set(self.misses.get() + 1);
+                }
+                let compl = ite.is_compl_choice();
+                if compl {
+                    r.map(|v| v.neg())
+                } else {
+                    r
+                }
+            }
+            Ite::IteConst(f) => Some(f),
+        }
+    }
+
+    fn stats(&self) -> IteTableStats {
+        IteTableStats {
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+            evictions: self.evictions,
+        }
+    }
+}
+
+impl<T: Hash + Eq> BoundedIteTable<T> {
+    /// Creates a table holding at most `capacity` entries; a capacity of 0
+    /// disables caching entirely
+    pub fn with_capacity(capacity: usize) -> BoundedIteTable<T> {
+        BoundedIteTable {
+            table: RefCell::new(FxHashMap::default()),
+            recency: RefCell::new(BTreeMap::new()),
+            clock: Cell::new(0),
+            capacity,
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+            evictions: 0,
+        }
+    }
+
+    /// the maximum number of entries this table holds
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// the number of entries currently cached
+    pub fn len(&self) -> usize {
+        self.table.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.borrow().is_empty()
+    }
+
+    fn tick(&self) -> u64 {
+        let t = self.clock.get();
+        self.clock.set(t + 1);
+        t
+    }
+}
+
+impl<T: Hash + Eq> Default for BoundedIteTable<T> {
+    fn default() -> BoundedIteTable<T> {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        builder::{
+            bdd::RobddBuilder,
+            cache::{AllIteTable, Ite},
+            BottomUpBuilder,
+        },
+        repr::{BddPtr, Cnf, Literal, VarLabel, VarOrder, WmcParams},
+        util::semirings::RealSemiring,
+    };
+
+    #[test]
+    fn compiles_with_a_full_cache() {
+        let mut clauses: Vec<Vec<Literal>> = (0..8)
+            .map(|i| {
+                vec![
+                    Literal::new(VarLabel::new(i), true),
+                    Literal::new(VarLabel::new(i + 8), true),
+                ]
+            })
+            .collect();
+        clauses.push(vec![
+            Literal::new(VarLabel::new(0), false),
+            Literal::new(VarLabel::new(5), false),
+            Literal::new(VarLabel::new(11), true),
+        ]);
+        let cnf = Cnf::new(&clauses);
+        let all = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(16);
+        let bounded = RobddBuilder::with_apply_table(
+            VarOrder::linear_order(16),
+            BoundedIteTable::with_capacity(8),
+        );
+        let expected = all.compile_cnf(&cnf);
+        let r = bounded.compile_cnf(&cnf);
+        assert!(bounded.stats().apply_cache_evictions > 0);
+        assert_eq!(bounded.model_count(r), all.model_count(expected));
+        let weights = WmcParams::new(HashMap::from_iter((0..16).map(|v| {
+            let p = 0.1 + 0.05 * v as f64;
+            (
+                VarLabel::new_usize(v),
+                (RealSemiring(1.0 - p), RealSemiring(p)),
+            )
+        })));
+        let wmc = r.unsmoothed_wmc(&weights).0;
+        assert!((wmc - expected.unsmoothed_wmc(&weights).0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn compiles_with_a_tiny_cache() {
+        // (x0 \/ x10) /\ (x1 \/ x11) /\ ... /\ (!x0 \/ !x19)
+        let mut clauses: Vec<Vec<Literal>> = (0..10)
+            .map(|i| {
+                vec![
+                    Literal::new(VarLabel::new(i), true),
+                    Literal::new(VarLabel::new(i + 10), true),
+                ]
+            })
+            .collect();
+        clauses.push(vec![
+            Literal::new(VarLabel::new(0), false),
+            Literal::new(VarLabel::new(19), false),
+        ]);
+        let cnf = Cnf::new(&clauses);
+        let all = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(20);
+        let tiny = RobddBuilder::with_apply_table(
+            VarOrder::linear_order(20),
+            BoundedIteTable::with_capacity(4),
+        );
+        let expected = all.compile_cnf(&cnf);
+        let r = tiny.compile_cnf(&cnf);
+        assert_eq!(all.model_count(expected), 3u128.pow(8) * 5);
+        assert_eq!(tiny.model_count(r), all.model_count(expected));
+        assert!(tiny.stats().apply_cache_evictions > 0);
+        assert_eq!(all.stats().apply_cache_evictions, 0);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let builder = RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(4);
+        let v: Vec<BddPtr> = (0..4)
+            .map(|i| builder.var(VarLabel::new(i), true))
+            .collect();
+        let ite = |i: usize| Ite::IteChoice {
+            f: v[i],
+            g: v[(i + 1) % 4],
+            h: BddPtr::false_ptr(),
+        };
+        let mut table = BoundedIteTable::with_capacity(2);
+        table.insert(ite(0), v[0], 0);
+        table.insert(ite(1), v[1], 0);
+        // touching entry 0 makes entry 1 the least recently used
+        assert!(table.get(ite(0), 0).is_some());
+        table.insert(ite(2), v[2], 0);
+        assert!(table.get(ite(1), 0).is_none());
+        assert!(table.get(ite(0), 0) == Some(v[0]));
+        assert!(table.get(ite(2), 0) == Some(v[2]));
+        assert_eq!(table.stats().evictions, 1);
+        assert_eq!(table.len(), 2);
+    }
+}
@@ -1,11 +1,14 @@
 //! Apply cache for ITEs that uses a dynamically-expanding LRU cache
 use crate::{
-    builder::cache::{Ite, IteTable},
+    builder::cache::{Ite, IteTable, IteTableStats},
     repr::DDNNFPtr,
     util::lru::*,
 };
 use rustc_hash::FxHasher;
-use std::hash::{Hash, Hasher};
+use std::{
+    cell::Cell,
+    hash::{Hash, Hasher},
+};
 
 const INITIAL_CAPACITY: usize = 16; // given as a power of two
 
@@ -13,6 +16,8 @@ const INITIAL_CAPACITY: usize = 16; // given as a power of two
 pub struct LruIteTable<T: Eq + PartialEq + Clone + Hash + std::fmt::Debug> {
     /// a vector of applications, indexed by the top label of the first pointer.
     table: Lru<(T, T, T), T>,
+    hits: Cell<usize>,
+    misses: Cell<usize>,
 }
 
 impl<'a, T: DDNNFPtr<'a>> IteTable<'a, T> for LruIteTable<T> {
@@ -32,6 +37,11 @@ impl<'a, T: DDNNFPtr<'a>> IteTable<'a, T> for LruIteTable<T> {
         match ite {
             Ite::IteChoice { f, g, h } | Ite::IteComplChoice { f, g, h } => {
                 let r = self.table.get((f, g, h), hash);
+                if r.is_some() {
+                    self.hits.set(self.hits.get() + 1);
+                } else {
+                    self.misses.set(self.misses.get() + 1);
+                }
                 let compl = ite.is_compl_choice();
                 if compl {
                     r.map(|v| v.neg())
@@ -43,6 +53,14 @@ impl<'a, T: DDNNFPtr<'a>> IteTable<'a, T> for LruIteTable<T> {
         }
     }
 
+    fn stats(&self) -> IteTableStats {
+        IteTableStats {
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+            evictions: 0,
+        }
+    }
+
     fn hash(&self, ite: &Ite<T>) -> u64 {
         match ite {
             Ite::IteChoice { f, g, h } | Ite::IteComplChoice { f, g, h } => {
@@ -61,6 +79,8 @@ impl<'a, T: DDNNFPtr<'a>> LruIteTable<T> {
     fn new() -> LruIteTable<T> {
         LruIteTable {
             table: Lru::new(INITIAL_CAPACITY),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
         }
     }
 }
@@ -1,15 +1,37 @@
 use crate::repr::DDNNFPtr;
 
 mod all_app;
+mod bounded_app;
 mod ite;
 mod lru_app;
 
 pub use self::all_app::*;
+pub use self::bounded_app::*;
 pub use self::ite::*;
 pub use self::lru_app::*;
 
+/// Counts of lookups of non-constant ITEs in an apply cache, and of entries
+/// a bounded cache has evicted (always 0 for tables that keep every entry)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IteTableStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+}
+
+impl IteTableStats {
+    pub fn lookups(&self) -> usize {
+        self.hits + self.misses
+    }
+}
+
 pub trait IteTable<'a, T: DDNNFPtr<'a>> {
     fn hash(&self, ite: &Ite<T>) -> u64;
     fn insert(&mut self, ite: Ite<T>, res: T, hash: u64);
     fn get(&self, ite: Ite<T>, hash: u64) -> Option<T>;
+    /// hit/miss counts of `get` since this table was created; tables that do
+    /// not keep them report all zeros
+    fn stats(&self) -> IteTableStats {
+        IteTableStats::default()
+    }
 }
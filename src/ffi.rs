@@ -1,18 +1,79 @@
+use std::cell::RefCell;
 use std::os::raw::c_char;
-use std::{collections::HashMap, ffi::CStr};
+use std::sync::Mutex;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    ffi::{CStr, CString},
+};
 
 use crate::builder::bdd::BddBuilder;
 use crate::repr::DDNNFPtr;
-use crate::util::semirings::{RealSemiring, Semiring};
+use crate::util::semirings::{DualNumber, RationalSemiring, RealSemiring, Semiring};
 use crate::{
     builder::{bdd::RobddBuilder, cache::AllIteTable, BottomUpBuilder},
     constants::primes,
-    repr::{BddPtr, Cnf, VarLabel, VarOrder, WmcParams},
+    repr::{BddPtr, Cnf, Literal, VarLabel, VarOrder, WmcParams},
     util::semirings::FiniteField,
 };
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 
+// Errors are reported through a thread-local last-error slot: on bad input, an
+// FFI function records a message and returns a sentinel (NULL, false, 0, or NaN)
+// instead of aborting the host process.
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: &str) {
+    LAST_ERROR.with(|e| *e.borrow_mut() = CString::new(msg).ok());
+}
+
+/// Returns the message of the most recent error on this thread, or NULL if
+/// there has been none. The string is owned by rsdd and remains valid until the
+/// next error on this thread.
+#[no_mangle]
+pub extern "C" fn rsdd_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| match &*e.borrow() {
+        Some(msg) => msg.as_ptr(),
+        None => std::ptr::null(),
+    })
+}
+
+// The addresses of the BDD handles given out and not yet freed, so that
+// `bdd_set_order` can rebuild the diagrams they point to under the new order.
+static LIVE_BDDS: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+/// Boxes `bdd` into a handle for the caller, which releases it with `free_bdd`
+fn new_bdd_handle(bdd: BddPtr<'static>) -> *mut BddPtr<'static> {
+    let handle = Box::into_raw(Box::new(bdd));
+    LIVE_BDDS.lock().unwrap().insert(handle as usize);
+    handle
+}
+
+/// Clears the last error on this thread.
+#[no_mangle]
+pub extern "C" fn rsdd_clear_last_error() {
+    LAST_ERROR.with(|e| *e.borrow_mut() = None);
+}
+
+/// Records an error and returns early (with `$sentinel`, if given) when any of
+/// the named pointer arguments is NULL.
+macro_rules! check_null {
+    ($sentinel:expr; $($p:ident),+) => {
+        $(if $p.is_null() {
+            set_last_error(concat!("got NULL `", stringify!($p), "` pointer"));
+            return $sentinel;
+        })+
+    };
+    ($($p:ident),+) => {
+        $(if $p.is_null() {
+            set_last_error(concat!("got NULL `", stringify!($p), "` pointer"));
+            return;
+        })+
+    };
+}
+
 #[no_mangle]
 pub extern "C" fn var_order_linear(num_vars: usize) -> *const VarOrder {
     Box::into_raw(Box::new(VarOrder::linear_order(num_vars)))
@@ -21,6 +82,7 @@ pub extern "C" fn var_order_linear(num_vars: usize) -> *const VarOrder {
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn cnf_from_dimacs(dimacs_str: *const c_char) -> *const Cnf {
+    check_null!(std::ptr::null(); dimacs_str);
     let cstr = CStr::from_ptr(dimacs_str);
 
     Box::into_raw(Box::new(Cnf::from_dimacs(&String::from_utf8_lossy(
@@ -30,8 +92,17 @@ pub unsafe extern "C" fn cnf_from_dimacs(dimacs_str: *const c_char) -> *const Cn
 
 #[repr(C)]
 pub struct WeightedSampleResult {
-    sample: *mut BddPtr<'static>,
-    probability: f64,
+    pub(crate) sample: *mut BddPtr<'static>,
+    pub(crate) probability: f64,
+}
+
+impl WeightedSampleResult {
+    fn null() -> WeightedSampleResult {
+        WeightedSampleResult {
+            sample: std::ptr::null_mut(),
+            probability: f64::NAN,
+        }
+    }
 }
 
 #[no_mangle]
@@ -41,18 +112,14 @@ pub unsafe extern "C" fn robdd_weighted_sample(
     bdd: *mut BddPtr<'static>,
     wmc_params: *mut WmcParams<RealSemiring>,
 ) -> WeightedSampleResult {
-    if bdd.is_null() || wmc_params.is_null() {
-        eprintln!("Fatal error, got NULL pointer for `bdd` or `wmc_params`");
-        std::process::abort();
-    }
-
+    check_null!(WeightedSampleResult::null(); builder, bdd, wmc_params);
     let builder = robdd_builder_from_ptr(builder);
     let bdd = *bdd;
     let wmc_params = &*wmc_params;
 
     let (sample, sample_probability) = builder.weighted_sample(bdd, wmc_params);
     WeightedSampleResult {
-        sample: Box::into_raw(Box::new(sample)),
+        sample: new_bdd_handle(sample),
         probability: sample_probability,
     }
 }
@@ -65,11 +132,12 @@ pub unsafe extern "C" fn robdd_top_k_paths(
     k: usize,
     wmc: *mut WmcParams<RealSemiring>,
 ) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, bdd, wmc);
     let builder = robdd_builder_from_ptr(builder);
     let bdd = *bdd;
     let wmc = &*wmc;
     let sample = builder.top_k_paths(bdd, k, wmc);
-    Box::into_raw(Box::new(sample))
+    new_bdd_handle(sample)
 }
 
 // directly inspired by https://users.rust-lang.org/t/how-to-deal-with-lifetime-when-need-to-expose-through-ffi/39583
@@ -92,30 +160,91 @@ unsafe fn robdd_builder_from_ptr<'_0>(
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn robdd_builder_all_table(order: *mut VarOrder) -> *mut RsddBddBuilder {
-    if order.is_null() {
-        eprintln!("Fatal error, got NULL `order` pointer");
-        std::process::abort();
-    }
-
+    check_null!(std::ptr::null_mut(); order);
     let order = *Box::from_raw(order);
     Box::into_raw(Box::new(RobddBuilder::<AllIteTable<BddPtr>>::new(order, None))).cast()
 }
 
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn robdd_builder_all_table_with_capacity(
+    order: *mut VarOrder,
+    log2_capacity: usize,
+) -> *mut RsddBddBuilder {
+    check_null!(std::ptr::null_mut(); order);
+    let order = *Box::from_raw(order);
+    Box::into_raw(Box::new(
+        RobddBuilder::<AllIteTable<BddPtr>>::with_capacity(order, log2_capacity),
+    ))
+    .cast()
+}
+
+/// Returns NULL and records an error if the builder's time limit is exceeded.
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn robdd_builder_compile_cnf(
     builder: *mut RsddBddBuilder,
     cnf: *mut Cnf,
 ) -> *mut BddPtr<'static> {
-    if cnf.is_null() {
-        eprintln!("Fatal error, got NULL `cnf` pointer");
-        std::process::abort();
+    check_null!(std::ptr::null_mut(); builder, cnf);
+    let builder = robdd_builder_from_ptr(builder);
+    let cnf = *Box::from_raw(cnf);
+    match builder.try_compile_cnf(&cnf) {
+        Ok(ptr) => new_bdd_handle(ptr),
+        Err(e) => {
+            set_last_error(&e.to_string());
+            std::ptr::null_mut()
+        }
     }
+}
 
+/// Compiles a CNF given as a flat array of DIMACS-style signed literals (`i`
+/// for variable `i - 1`, `-i` for its negation), where `lens[c]` is the number
+/// of literals in clause `c`. Returns NULL and records an error if
+/// `num_vars` is not the builder's number of variables, if a literal is 0 or
+/// refers to a variable the builder does not have, or if the builder's time
+/// limit is exceeded.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn robdd_compile_clauses(
+    builder: *mut RsddBddBuilder,
+    num_vars: usize,
+    clauses: *const i64,
+    lens: *const usize,
+    num_clauses: usize,
+) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, clauses, lens);
     let builder = robdd_builder_from_ptr(builder);
-    let cnf = *Box::from_raw(cnf);
-    let ptr = builder.compile_cnf(&cnf);
-    Box::into_raw(Box::new(ptr))
+    if num_vars != builder.num_vars() {
+        set_last_error("num_vars does not match the builder");
+        return std::ptr::null_mut();
+    }
+    let lens = std::slice::from_raw_parts(lens, num_clauses);
+    let lits = std::slice::from_raw_parts(clauses, lens.iter().sum());
+
+    let mut clause_vec: Vec<Vec<Literal>> = Vec::with_capacity(num_clauses);
+    let mut offset = 0;
+    for len in lens {
+        let mut clause = Vec::with_capacity(*len);
+        for lit in &lits[offset..offset + len] {
+            let var = lit.unsigned_abs();
+            if var == 0 || var as usize > builder.num_vars() {
+                set_last_error("literal out of range");
+                return std::ptr::null_mut();
+            }
+            clause.push(Literal::new(VarLabel::new(var - 1), *lit > 0));
+        }
+        clause_vec.push(clause);
+        offset += len;
+    }
+
+    match builder.try_compile_cnf(&Cnf::new(&clause_vec)) {
+        Ok(ptr) => new_bdd_handle(ptr),
+        Err(e) => {
+            set_last_error(&e.to_string());
+            std::ptr::null_mut()
+        }
+    }
 }
 
 #[no_mangle]
@@ -124,6 +253,7 @@ pub unsafe extern "C" fn robdd_model_count(
     builder: *mut RsddBddBuilder,
     bdd: *mut BddPtr<'static>,
 ) -> u64 {
+    check_null!(0; builder, bdd);
     let builder = robdd_builder_from_ptr(builder);
     let num_vars = builder.num_vars();
     let smoothed = builder.smooth(*bdd, num_vars);
@@ -151,6 +281,7 @@ pub unsafe extern "C" fn mk_bdd_manager_default_order(num_vars: u64) -> *mut Rsd
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn start_bdd_manager_time_limit(builder: *mut RsddBddBuilder, time_limit: f64) {
+    check_null!(builder);
     let duration = std::time::Duration::from_secs_f64(time_limit);
     let builder = robdd_builder_from_ptr(builder);
     builder.start_time_limit(duration);
@@ -159,6 +290,7 @@ pub unsafe extern "C" fn start_bdd_manager_time_limit(builder: *mut RsddBddBuild
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn stop_bdd_manager_time_limit(builder: *mut RsddBddBuilder) {
+    check_null!(builder);
     let builder = robdd_builder_from_ptr(builder);
     builder.stop_time_limit();
 }
@@ -166,6 +298,7 @@ pub unsafe extern "C" fn stop_bdd_manager_time_limit(builder: *mut RsddBddBuilde
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_manager_time_limit_exceeded(builder: *mut RsddBddBuilder) -> bool {
+    check_null!(false; builder);
     let builder = robdd_builder_from_ptr(builder);
     builder.check_time_limit()
 }
@@ -174,6 +307,7 @@ pub unsafe extern "C" fn bdd_manager_time_limit_exceeded(builder: *mut RsddBddBu
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_new_label(builder: *mut RsddBddBuilder) -> u64 {
+    check_null!(0; builder);
     let builder = robdd_builder_from_ptr(builder);
     builder.new_label().value()
 }
@@ -185,9 +319,10 @@ pub unsafe extern "C" fn bdd_var(
     label: u64,
     polarity: bool,
 ) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder);
     let builder = robdd_builder_from_ptr(builder);
     let ptr = builder.var(VarLabel::new(label), polarity);
-    Box::into_raw(Box::new(ptr))
+    new_bdd_handle(ptr)
 }
 
 #[no_mangle]
@@ -196,9 +331,30 @@ pub unsafe extern "C" fn bdd_new_var(
     builder: *mut RsddBddBuilder,
     polarity: bool,
 ) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder);
     let builder = robdd_builder_from_ptr(builder);
     let (_, ptr) = builder.new_var(polarity);
-    Box::into_raw(Box::new(ptr))
+    new_bdd_handle(ptr)
+}
+
+/// Creates `count` new variables at the end of the order, whose labels are
+/// contiguous, and returns an array of `count` BDDs for them, in order.
+/// Release each BDD with `free_bdd` and the array with `free_bdd_array`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_new_vars(
+    builder: *mut RsddBddBuilder,
+    count: usize,
+    polarity: bool,
+) -> *mut *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder);
+    let builder = robdd_builder_from_ptr(builder);
+    let bdds: Vec<*mut BddPtr<'static>> = builder
+        .new_vars(count, polarity)
+        .into_iter()
+        .map(|(_, ptr)| new_bdd_handle(ptr))
+        .collect();
+    Box::into_raw(bdds.into_boxed_slice()).cast()
 }
 
 #[no_mangle]
@@ -208,9 +364,10 @@ pub unsafe extern "C" fn bdd_new_var_at_position(
     position: usize,
     polarity: bool,
 ) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder);
     let builder = robdd_builder_from_ptr(builder);
     let (_, ptr) = builder.new_var_at_position(position, polarity);
-    Box::into_raw(Box::new(ptr))
+    new_bdd_handle(ptr)
 }
 
 #[no_mangle]
@@ -221,9 +378,10 @@ pub unsafe extern "C" fn bdd_ite(
     g: *mut BddPtr<'static>,
     h: *mut BddPtr<'static>,
 ) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, f, g, h);
     let builder = robdd_builder_from_ptr(builder);
     let and = builder.ite(*f, *g, *h);
-    Box::into_raw(Box::new(and))
+    new_bdd_handle(and)
 }
 
 #[no_mangle]
@@ -234,9 +392,10 @@ pub unsafe extern "C" fn bdd_condition(
     label: u64,
     value: bool,
 ) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, bdd);
     let builder = robdd_builder_from_ptr(builder);
     let conditioned = builder.condition(*bdd, VarLabel::new(label), value);
-    Box::into_raw(Box::new(conditioned))
+    new_bdd_handle(conditioned)
 }
 
 #[no_mangle]
@@ -246,9 +405,10 @@ pub unsafe extern "C" fn bdd_and(
     left: *mut BddPtr<'static>,
     right: *mut BddPtr<'static>,
 ) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, left, right);
     let builder = robdd_builder_from_ptr(builder);
     let and = builder.and(*left, *right);
-    Box::into_raw(Box::new(and))
+    new_bdd_handle(and)
 }
 
 #[no_mangle]
@@ -258,9 +418,10 @@ pub unsafe extern "C" fn bdd_or(
     left: *mut BddPtr<'static>,
     right: *mut BddPtr<'static>,
 ) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, left, right);
     let builder = robdd_builder_from_ptr(builder);
     let or = builder.or(*left, *right);
-    Box::into_raw(Box::new(or))
+    new_bdd_handle(or)
 }
 
 #[no_mangle]
@@ -269,43 +430,65 @@ pub unsafe extern "C" fn bdd_negate(
     builder: *mut RsddBddBuilder,
     bdd: *mut BddPtr<'static>,
 ) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, bdd);
     let builder = robdd_builder_from_ptr(builder);
     let negate = builder.negate(*bdd);
-    Box::into_raw(Box::new(negate))
+    new_bdd_handle(negate)
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_is_true(bdd: *mut BddPtr<'static>) -> bool {
+    check_null!(false; bdd);
     (*bdd).is_true()
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_is_false(bdd: *mut BddPtr<'static>) -> bool {
+    check_null!(false; bdd);
     (*bdd).is_false()
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_is_const(bdd: *mut BddPtr<'static>) -> bool {
+    check_null!(false; bdd);
     (*bdd).is_const()
 }
 
+/// whether `bdd` is true under every assignment
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_is_tautology(bdd: *mut BddPtr<'static>) -> bool {
+    check_null!(false; bdd);
+    (*bdd).is_tautology()
+}
+
+/// whether `bdd` is false under every assignment
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_is_unsat(bdd: *mut BddPtr<'static>) -> bool {
+    check_null!(false; bdd);
+    (*bdd).is_unsat()
+}
+
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_true(builder: *mut RsddBddBuilder) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder);
     let builder = robdd_builder_from_ptr(builder);
     let bdd = builder.true_ptr();
-    Box::into_raw(Box::new(bdd))
+    new_bdd_handle(bdd)
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_false(builder: *mut RsddBddBuilder) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder);
     let builder = robdd_builder_from_ptr(builder);
     let bdd = builder.false_ptr();
-    Box::into_raw(Box::new(bdd))
+    new_bdd_handle(bdd)
 }
 
 #[no_mangle]
@@ -315,6 +498,7 @@ pub unsafe extern "C" fn bdd_eq(
     left: *mut BddPtr<'static>,
     right: *mut BddPtr<'static>,
 ) -> bool {
+    check_null!(false; builder, left, right);
     let builder = robdd_builder_from_ptr(builder);
     builder.eq(*left, *right)
 }
@@ -322,6 +506,7 @@ pub unsafe extern "C" fn bdd_eq(
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_topvar(bdd: *mut BddPtr<'static>) -> u64 {
+    check_null!(0; bdd);
     match (*bdd).var_safe() {
         Some(x) => x.value(),
         None => 0, // TODO: fix this
@@ -331,46 +516,70 @@ pub unsafe extern "C" fn bdd_topvar(bdd: *mut BddPtr<'static>) -> u64 {
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_low(bdd: *mut BddPtr<'static>) -> *mut BddPtr<'static> {
-    Box::into_raw(Box::new((*bdd).low()))
+    check_null!(std::ptr::null_mut(); bdd);
+    new_bdd_handle((*bdd).low())
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_high(bdd: *mut BddPtr<'static>) -> *mut BddPtr<'static> {
-    Box::into_raw(Box::new((*bdd).high()))
+    check_null!(std::ptr::null_mut(); bdd);
+    new_bdd_handle((*bdd).high())
 }
 
+/// Returns a string representation of `bdd`; the caller must release it
+/// with `free_rsdd_string`.
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
-pub unsafe extern "C" fn print_bdd(bdd: *mut BddPtr<'static>) -> *const c_char {
-    let s = std::ffi::CString::new((*bdd).print_bdd()).unwrap();
-    let p = s.as_ptr();
-    std::mem::forget(s);
-    p
+pub unsafe extern "C" fn print_bdd(bdd: *mut BddPtr<'static>) -> *mut c_char {
+    check_null!(std::ptr::null_mut(); bdd);
+    std::ffi::CString::new((*bdd).print_bdd())
+        .unwrap()
+        .into_raw()
 }
 
+/// Returns a JSON representation of `bdd`; the caller must release it with
+/// `free_rsdd_string`.
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
-pub unsafe extern "C" fn bdd_json(bdd: *mut BddPtr<'static>) -> *const c_char {
-    let s = std::ffi::CString::new((*bdd).bdd_json()).unwrap();
-    let p = s.as_ptr();
-    std::mem::forget(s);
-    p
+pub unsafe extern "C" fn bdd_json(bdd: *mut BddPtr<'static>) -> *mut c_char {
+    check_null!(std::ptr::null_mut(); bdd);
+    std::ffi::CString::new((*bdd).bdd_json())
+        .unwrap()
+        .into_raw()
 }
 
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_num_recursive_calls(builder: *mut RsddBddBuilder) -> usize {
+    check_null!(0; builder);
     let builder = robdd_builder_from_ptr(builder);
     builder.num_recursive_calls()
 }
 
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_apply_cache_hits(builder: *mut RsddBddBuilder) -> usize {
+    check_null!(0; builder);
+    let builder = robdd_builder_from_ptr(builder);
+    builder.stats().apply_cache_hits
+}
+
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_apply_cache_misses(builder: *mut RsddBddBuilder) -> usize {
+    check_null!(0; builder);
+    let builder = robdd_builder_from_ptr(builder);
+    builder.stats().apply_cache_misses
+}
+
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_wmc(
     bdd: *mut BddPtr<'static>,
     wmc: *mut WmcParams<RealSemiring>,
 ) -> f64 {
+    check_null!(f64::NAN; bdd, wmc);
     DDNNFPtr::unsmoothed_wmc(&(*bdd), &(*wmc)).0
 }
 
@@ -387,9 +596,10 @@ pub unsafe extern "C" fn bdd_compose(
     var: u64,
     g: *mut BddPtr<'static>,
 ) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, f, g);
     let builder = robdd_builder_from_ptr(builder);
     let result = builder.compose(*f, VarLabel::new(var), *g);
-    Box::into_raw(Box::new(result))
+    new_bdd_handle(result)
 }
 
 #[no_mangle]
@@ -399,9 +609,46 @@ pub unsafe extern "C" fn bdd_exists(
     f: *mut BddPtr<'static>,
     var: u64,
 ) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, f);
     let builder = robdd_builder_from_ptr(builder);
     let result = builder.exists(*f, VarLabel::new(var));
-    Box::into_raw(Box::new(result))
+    new_bdd_handle(result)
+}
+
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_exists_many(
+    builder: *mut RsddBddBuilder,
+    f: *mut BddPtr<'static>,
+    vars: *const u64,
+    n: usize,
+) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, f, vars);
+    let builder = robdd_builder_from_ptr(builder);
+    let vars: Vec<VarLabel> = std::slice::from_raw_parts(vars, n)
+        .iter()
+        .map(|v| VarLabel::new(*v))
+        .collect();
+    let result = builder.exists_many(*f, &vars);
+    new_bdd_handle(result)
+}
+
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_forall_many(
+    builder: *mut RsddBddBuilder,
+    f: *mut BddPtr<'static>,
+    vars: *const u64,
+    n: usize,
+) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, f, vars);
+    let builder = robdd_builder_from_ptr(builder);
+    let vars: Vec<VarLabel> = std::slice::from_raw_parts(vars, n)
+        .iter()
+        .map(|v| VarLabel::new(*v))
+        .collect();
+    let result = builder.forall_many(*f, &vars);
+    new_bdd_handle(result)
 }
 
 #[no_mangle]
@@ -412,9 +659,144 @@ pub unsafe extern "C" fn wmc_param_f64_set_weight(
     low: f64,
     high: f64,
 ) {
+    check_null!(weights);
     (*weights).set_weight(VarLabel::new(var), RealSemiring(low), RealSemiring(high))
 }
 
+/// Number of parameters tracked by gradient WMC parameters.
+pub const WMC_GRAD_NPARTIALS: usize = 16;
+
+type WmcGradParams = WmcParams<DualNumber<WMC_GRAD_NPARTIALS>>;
+
+#[no_mangle]
+pub extern "C" fn wmc_grad_num_partials() -> usize {
+    WMC_GRAD_NPARTIALS
+}
+
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn new_wmc_params_grad() -> *mut WmcGradParams {
+    Box::into_raw(Box::new(WmcParams::new(HashMap::from([]))))
+}
+
+/// Sets the weight of `var`, treating its high weight as the parameter at
+/// `partial_idx` (i.e., d high / d param = 1); the low weight is a constant.
+/// Records an error and leaves the weight unset if `partial_idx` is at least
+/// `WMC_GRAD_NPARTIALS`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn wmc_param_set_weight_with_grad(
+    weights: *mut WmcGradParams,
+    var: u64,
+    low: f64,
+    high: f64,
+    partial_idx: usize,
+) {
+    check_null!(weights);
+    if partial_idx >= WMC_GRAD_NPARTIALS {
+        set_last_error("partial index out of range");
+        return;
+    }
+    (*weights).set_weight(
+        VarLabel::new(var),
+        DualNumber::constant(low),
+        DualNumber::param(high, partial_idx),
+    )
+}
+
+/// Sets the weight of `var` with no derivative seed.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn wmc_param_grad_set_weight(
+    weights: *mut WmcGradParams,
+    var: u64,
+    low: f64,
+    high: f64,
+) {
+    check_null!(weights);
+    (*weights).set_weight(
+        VarLabel::new(var),
+        DualNumber::constant(low),
+        DualNumber::constant(high),
+    )
+}
+
+/// Returns the gradient of the WMC as an array of `WMC_GRAD_NPARTIALS`
+/// doubles, to be released with `free_wmc_grad`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_wmc_grad(
+    bdd: *mut BddPtr<'static>,
+    wmc: *mut WmcGradParams,
+) -> *const f64 {
+    check_null!(std::ptr::null(); bdd, wmc);
+    let r = DDNNFPtr::unsmoothed_wmc(&(*bdd), &(*wmc));
+    Box::into_raw(Box::new(r.deriv)) as *const f64
+}
+
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn new_wmc_params_rational() -> *mut WmcParams<RationalSemiring> {
+    Box::into_raw(Box::new(WmcParams::new(HashMap::from([]))))
+}
+
+/// Sets the weight of `var` to the exact fractions `num_lo / den_lo` and
+/// `num_hi / den_hi`. Records an error and leaves the weight unset if either
+/// denominator is 0.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn wmc_param_rational_set_weight(
+    weights: *mut WmcParams<RationalSemiring>,
+    var: u64,
+    num_lo: i64,
+    den_lo: i64,
+    num_hi: i64,
+    den_hi: i64,
+) {
+    check_null!(weights);
+    if den_lo == 0 || den_hi == 0 {
+        set_last_error("zero denominator");
+        return;
+    }
+    (*weights).set_weight(
+        VarLabel::new(var),
+        RationalSemiring::new(num_lo.into(), den_lo.into()),
+        RationalSemiring::new(num_hi.into(), den_hi.into()),
+    )
+}
+
+/// An exact fraction `num / den` in lowest terms.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RationalResult {
+    pub num: i64,
+    pub den: i64,
+}
+
+/// Computes the exact weighted model count of `bdd`. Returns `0 / 0` and
+/// records an error if the result does not fit in 64-bit integers, or an
+/// intermediate sum or product overflows 128-bit ones.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_wmc_rational(
+    bdd: *mut BddPtr<'static>,
+    wmc: *mut WmcParams<RationalSemiring>,
+) -> RationalResult {
+    const NULL_RESULT: RationalResult = RationalResult { num: 0, den: 0 };
+    check_null!(NULL_RESULT; bdd, wmc);
+    let Some(r) = (*bdd).checked_rational_wmc(&(*wmc)) else {
+        set_last_error("rational weighted model count overflowed i128");
+        return NULL_RESULT;
+    };
+    match (i64::try_from(r.numerator()), i64::try_from(r.denominator())) {
+        (Ok(num), Ok(den)) => RationalResult { num, den },
+        _ => {
+            set_last_error("rational weighted model count overflowed i64");
+            NULL_RESULT
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct WeightF64(pub f64, pub f64);
@@ -425,6 +807,7 @@ pub unsafe extern "C" fn wmc_param_f64_var_weight(
     weights: *mut WmcParams<RealSemiring>,
     var: u64,
 ) -> WeightF64 {
+    check_null!(WeightF64(f64::NAN, f64::NAN); weights);
     let (l, h) = (*weights).var_weight(VarLabel::new(var));
     WeightF64(l.0, h.0)
 }
@@ -444,9 +827,103 @@ pub unsafe extern "C" fn weight_f64_hi(w: WeightF64) -> f64 {
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_size(bdd: *mut BddPtr<'static>) -> usize {
+    check_null!(0; bdd);
     (*bdd).count_nodes()
 }
 
+/// Returns an array of `bdd_num_vars(builder)` counts, where entry `i` is the
+/// number of nodes of `bdd` labeled by variable `i`. Release it with
+/// `free_bdd_nodes_per_var`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_nodes_per_var(
+    builder: *mut RsddBddBuilder,
+    bdd: *mut BddPtr<'static>,
+) -> *mut usize {
+    check_null!(std::ptr::null_mut(); builder, bdd);
+    let builder = robdd_builder_from_ptr(builder);
+    let mut counts = vec![0; builder.num_vars()];
+    for (var, count) in (*bdd).nodes_per_var() {
+        counts[var.value_usize()] = count;
+    }
+    Box::into_raw(counts.into_boxed_slice()).cast()
+}
+
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_num_vars(builder: *mut RsddBddBuilder) -> usize {
+    check_null!(0; builder);
+    robdd_builder_from_ptr(builder).num_vars()
+}
+
+/// Returns the current variable order as an array of `bdd_num_vars(builder)`
+/// labels, where entry `i` is the label at level `i` (level 0 is the root).
+/// Release it with `free_bdd_order`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_get_order(builder: *mut RsddBddBuilder) -> *const u64 {
+    check_null!(std::ptr::null(); builder);
+    let builder = robdd_builder_from_ptr(builder);
+    let order: Vec<u64> = builder.order().in_order_iter().map(|v| v.value()).collect();
+    Box::into_raw(order.into_boxed_slice()).cast()
+}
+
+/// Installs the variable order given by the `n` labels in `order`, listed
+/// from the root level down, which must be a permutation of the builder's
+/// `n` variables. Every BDD of this builder that has not been freed is
+/// rebuilt under the new order in place, so existing handles stay valid; the
+/// builder's other settings (e.g. its time limit) are kept. Returns false and
+/// records an error, leaving the builder untouched, if `n` is not
+/// `bdd_num_vars(builder)` or `order` is not a permutation.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_set_order(
+    builder: *mut RsddBddBuilder,
+    order: *const u64,
+    n: usize,
+) -> bool {
+    check_null!(false; builder, order);
+    let builder = robdd_builder_from_ptr(builder);
+    if n != builder.num_vars() {
+        set_last_error("order does not have bdd_num_vars entries");
+        return false;
+    }
+    let labels = std::slice::from_raw_parts(order, n);
+    let mut seen = vec![false; n];
+    for &label in labels {
+        match seen.get_mut(label as usize) {
+            Some(s) if !*s => *s = true,
+            _ => {
+                set_last_error("order is not a permutation");
+                return false;
+            }
+        }
+    }
+    let order: Vec<VarLabel> = labels.iter().map(|&l| VarLabel::new(l)).collect();
+
+    // holding the lock keeps the handles from being freed meanwhile
+    let live = LIVE_BDDS.lock().unwrap();
+    let nodes: HashSet<usize> = builder
+        .collect_nodes()
+        .into_iter()
+        .map(|node| node as *const _ as usize)
+        .collect();
+    let handles: Vec<*mut BddPtr<'static>> = live
+        .iter()
+        .map(|&h| h as *mut BddPtr<'static>)
+        .filter(|&h| match *h {
+            BddPtr::Reg(node) | BddPtr::Compl(node) => nodes.contains(&(node as *const _ as usize)),
+            BddPtr::PtrTrue | BddPtr::PtrFalse => false,
+        })
+        .collect();
+    let roots: Vec<BddPtr<'static>> = handles.iter().map(|&h| *h).collect();
+    let rebuilt = builder.set_order(VarOrder::new(&order), &roots);
+    for (h, r) in handles.into_iter().zip(rebuilt) {
+        *h = r;
+    }
+    true
+}
+
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_iff(
@@ -454,9 +931,37 @@ pub unsafe extern "C" fn bdd_iff(
     left: *mut BddPtr<'static>,
     right: *mut BddPtr<'static>,
 ) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, left, right);
     let builder = robdd_builder_from_ptr(builder);
     let iff = builder.iff(*left, *right);
-    Box::into_raw(Box::new(iff))
+    new_bdd_handle(iff)
+}
+
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_xor(
+    builder: *mut RsddBddBuilder,
+    left: *mut BddPtr<'static>,
+    right: *mut BddPtr<'static>,
+) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, left, right);
+    let builder = robdd_builder_from_ptr(builder);
+    let xor = builder.xor(*left, *right);
+    new_bdd_handle(xor)
+}
+
+/// Returns the BDD for `left -> right`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_implies(
+    builder: *mut RsddBddBuilder,
+    left: *mut BddPtr<'static>,
+    right: *mut BddPtr<'static>,
+) -> *mut BddPtr<'static> {
+    check_null!(std::ptr::null_mut(); builder, left, right);
+    let builder = robdd_builder_from_ptr(builder);
+    let implies = builder.imp(*left, *right);
+    new_bdd_handle(implies)
 }
 
 #[no_mangle]
@@ -466,6 +971,7 @@ pub unsafe extern "C" fn bdd_has_variable(
     bdd: *mut BddPtr<'static>,
     var: u64,
 ) -> bool {
+    check_null!(false; builder, bdd);
     let builder = robdd_builder_from_ptr(builder);
     builder.has_variable(*bdd, VarLabel::new(var))
 }
@@ -473,6 +979,7 @@ pub unsafe extern "C" fn bdd_has_variable(
 #[no_mangle]
 #[allow(clippy::missing_safety_doc)]
 pub unsafe extern "C" fn bdd_hash(bdd: *mut BddPtr<'static>) -> u64 {
+    check_null!(0; bdd);
     let bdd = *bdd;
 
     let mut hasher = DefaultHasher::new();
@@ -480,9 +987,20 @@ pub unsafe extern "C" fn bdd_hash(bdd: *mut BddPtr<'static>) -> u64 {
     hasher.finish()
 }
 
+/// A hash of the BDD's structure that is stable across builders and runs;
+/// see `BddPtr::structural_hash`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn bdd_structural_hash(bdd: *mut BddPtr<'static>) -> u64 {
+    check_null!(0; bdd);
+    (*bdd).structural_hash()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn free_bdd(bdd: *mut BddPtr<'static>) {
     if !bdd.is_null() {
+        let mut live = LIVE_BDDS.lock().unwrap();
+        live.remove(&(bdd as usize));
         drop(Box::from_raw(bdd));
     }
 }
@@ -502,3 +1020,480 @@ pub unsafe extern "C" fn free_wmc_params(params: *mut WmcParams<RealSemiring>) {
         drop(Box::from_raw(params));
     }
 }
+
+/// Releases a string returned by `print_bdd` or `bdd_json`.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn free_rsdd_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(std::ffi::CString::from_raw(s));
+    }
+}
+
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn free_wmc_params_rational(params: *mut WmcParams<RationalSemiring>) {
+    if !params.is_null() {
+        drop(Box::from_raw(params));
+    }
+}
+
+/// Releases an array returned by `bdd_nodes_per_var`; `len` must be its length.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn free_bdd_nodes_per_var(counts: *mut usize, len: usize) {
+    if !counts.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            counts, len,
+        )));
+    }
+}
+
+/// Releases an array returned by `bdd_new_vars`; `len` must be its length.
+/// The BDDs it holds are not released.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn free_bdd_array(bdds: *mut *mut BddPtr<'static>, len: usize) {
+    if !bdds.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(bdds, len)));
+    }
+}
+
+/// Releases an array returned by `bdd_get_order`; `len` must be its length.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn free_bdd_order(order: *const u64, len: usize) {
+    if !order.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            order.cast_mut(),
+            len,
+        )));
+    }
+}
+
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn free_wmc_params_grad(params: *mut WmcGradParams) {
+    if !params.is_null() {
+        drop(Box::from_raw(params));
+    }
+}
+
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn free_wmc_grad(grad: *const f64) {
+    if !grad.is_null() {
+        drop(Box::from_raw(grad as *mut [f64; WMC_GRAD_NPARTIALS]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repr::VarSet;
+
+    #[test]
+    fn test_null_pointer_sets_last_error() {
+        unsafe {
+            rsdd_clear_last_error();
+            assert!(rsdd_last_error().is_null());
+
+            let builder = mk_bdd_manager_default_order(2);
+            let x = bdd_var(builder, 0, true);
+            let r = bdd_and(builder, x, std::ptr::null_mut());
+            assert!(r.is_null());
+            let msg = CStr::from_ptr(rsdd_last_error()).to_str().unwrap();
+            assert_eq!(msg, "got NULL `right` pointer");
+
+            assert!(bdd_wmc(x, std::ptr::null_mut()).is_nan());
+            let msg = CStr::from_ptr(rsdd_last_error()).to_str().unwrap();
+            assert_eq!(msg, "got NULL `wmc` pointer");
+
+            assert!(bdd_true(std::ptr::null_mut()).is_null());
+            assert!(!bdd_eq(std::ptr::null_mut(), x, x));
+            let msg = CStr::from_ptr(rsdd_last_error()).to_str().unwrap();
+            assert_eq!(msg, "got NULL `builder` pointer");
+
+            rsdd_clear_last_error();
+            assert!(rsdd_last_error().is_null());
+
+            free_bdd(x);
+            free_bdd_manager(builder);
+        }
+    }
+
+    #[test]
+    fn test_compile_clauses_eq_dimacs() {
+        unsafe {
+            let builder = mk_bdd_manager_default_order(4);
+            let dimacs = CString::new("p cnf 4 3\n1 -2 0\n2 3 -4 0\n-1 4 0\n").unwrap();
+            let cnf = cnf_from_dimacs(dimacs.as_ptr());
+            let from_dimacs = robdd_builder_compile_cnf(builder, cnf as *mut Cnf);
+
+            let lits: [i64; 7] = [1, -2, 2, 3, -4, -1, 4];
+            let lens: [usize; 3] = [2, 3, 2];
+            let from_clauses =
+                robdd_compile_clauses(builder, 4, lits.as_ptr(), lens.as_ptr(), lens.len());
+            assert!(bdd_eq(builder, from_dimacs, from_clauses));
+
+            let bad: [i64; 2] = [1, 5];
+            let r = robdd_compile_clauses(builder, 4, bad.as_ptr(), [2].as_ptr(), 1);
+            assert!(r.is_null());
+            let msg = CStr::from_ptr(rsdd_last_error()).to_str().unwrap();
+            assert_eq!(msg, "literal out of range");
+
+            // a larger num_vars cannot admit literals the builder lacks
+            let r = robdd_compile_clauses(builder, 5, bad.as_ptr(), [2].as_ptr(), 1);
+            assert!(r.is_null());
+            let msg = CStr::from_ptr(rsdd_last_error()).to_str().unwrap();
+            assert_eq!(msg, "num_vars does not match the builder");
+
+            free_bdd(from_clauses);
+            free_bdd(from_dimacs);
+            free_bdd_manager(builder);
+        }
+    }
+
+    #[test]
+    fn test_compile_cnf_timeout_sets_last_error() {
+        unsafe {
+            rsdd_clear_last_error();
+            let builder = mk_bdd_manager_default_order(4);
+            start_bdd_manager_time_limit(builder, 0.0);
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            let dimacs = CString::new("p cnf 4 3\n1 -2 0\n2 3 -4 0\n-1 4 0\n").unwrap();
+            let cnf = cnf_from_dimacs(dimacs.as_ptr());
+            let r = robdd_builder_compile_cnf(builder, cnf as *mut Cnf);
+            assert!(r.is_null());
+            let msg = CStr::from_ptr(rsdd_last_error()).to_str().unwrap();
+            assert_eq!(msg, "BDD builder time limit exceeded");
+
+            stop_bdd_manager_time_limit(builder);
+            let cnf = cnf_from_dimacs(dimacs.as_ptr());
+            let r = robdd_builder_compile_cnf(builder, cnf as *mut Cnf);
+            assert!(!r.is_null());
+
+            free_bdd(r);
+            free_bdd_manager(builder);
+        }
+    }
+
+    #[test]
+    fn test_quantify_many() {
+        unsafe {
+            let builder = mk_bdd_manager_default_order(4);
+            let x: Vec<_> = (0..4).map(|v| bdd_var(builder, v, true)).collect();
+            // f = (x0 /\ x1) \/ (x2 /\ x3)
+            let f = bdd_or(
+                builder,
+                bdd_and(builder, x[0], x[1]),
+                bdd_and(builder, x[2], x[3]),
+            );
+            let vars: [u64; 3] = [1, 2, 3];
+
+            // exists x1 x2 x3. f = true
+            let e = bdd_exists_many(builder, f, vars.as_ptr(), vars.len());
+            assert!(bdd_is_true(e));
+
+            // forall x1 x2 x3. f = false
+            let a = bdd_forall_many(builder, f, vars.as_ptr(), vars.len());
+            assert!(bdd_is_false(a));
+
+            // forall x2 x3. (x1 \/ f) = x1
+            let g = bdd_or(builder, x[1], f);
+            let a = bdd_forall_many(builder, g, vars[1..].as_ptr(), 2);
+            assert!(bdd_eq(builder, a, x[1]));
+
+            free_bdd_manager(builder);
+        }
+    }
+
+    #[test]
+    fn test_bdd_wmc_rational_tiny2() {
+        unsafe {
+            let builder = mk_bdd_manager_default_order(6);
+            let dimacs = CString::new("p cnf 6 3\n1 2 3 4 0\n-2 -3 4 5 0\n-4 -5 6 6 0\n").unwrap();
+            let cnf = cnf_from_dimacs(dimacs.as_ptr());
+            let bdd = robdd_builder_compile_cnf(builder, cnf as *mut Cnf);
+
+            // weights are in hundredths: (0.05, 0.10), (0.15, 0.20), ...,
+            // with the positive literal's weight first
+            let params = new_wmc_params_rational();
+            for v in 0..6 {
+                let hi = 10 * v as i64 + 5;
+                wmc_param_rational_set_weight(params, v, hi + 5, 100, hi, 100);
+            }
+
+            // smooth over every variable so the count is exact
+            let b = robdd_builder_from_ptr(builder);
+            let mut vars = VarSet::new();
+            for v in 0..6 {
+                vars.insert(VarLabel::new(v));
+            }
+            let mut smoothed = b.smooth_over(*bdd, &vars);
+            let r = bdd_wmc_rational(&mut smoothed, params);
+            // 0.017015015625, as computed by pysdd
+            assert_eq!(
+                r,
+                RationalResult {
+                    num: 1088961,
+                    den: 64000000
+                }
+            );
+
+            free_wmc_params_rational(params);
+            free_bdd(bdd);
+            free_bdd_manager(builder);
+        }
+    }
+
+    #[test]
+    fn test_bdd_wmc_rational_overflow_sets_last_error() {
+        unsafe {
+            rsdd_clear_last_error();
+            let builder = mk_bdd_manager_default_order(24);
+            // the conjunction of all 24 variables, weighing (1/97)^24
+            let clauses: String = (1..=24).map(|v| format!("{v} 0\n")).collect();
+            let dimacs = CString::new(format!("p cnf 24 24\n{clauses}")).unwrap();
+            let cnf = cnf_from_dimacs(dimacs.as_ptr());
+            let bdd = robdd_builder_compile_cnf(builder, cnf as *mut Cnf);
+            let params = new_wmc_params_rational();
+            for v in 0..24 {
+                wmc_param_rational_set_weight(params, v, 96, 97, 1, 97);
+            }
+
+            let r = bdd_wmc_rational(bdd, params);
+            assert_eq!(r, RationalResult { num: 0, den: 0 });
+            let msg = CStr::from_ptr(rsdd_last_error()).to_str().unwrap();
+            assert_eq!(msg, "rational weighted model count overflowed i128");
+
+            free_wmc_params_rational(params);
+            free_bdd(bdd);
+            free_bdd_manager(builder);
+        }
+    }
+
+    #[test]
+    fn test_bdd_nodes_per_var() {
+        unsafe {
+            let builder = mk_bdd_manager_default_order(4);
+            let x0 = bdd_var(builder, 0, true);
+            let x2 = bdd_var(builder, 2, true);
+            let f = bdd_and(builder, x0, x2);
+
+            let n = bdd_num_vars(builder);
+            let counts = bdd_nodes_per_var(builder, f);
+            assert_eq!(std::slice::from_raw_parts(counts, n), &[1, 0, 1, 0]);
+            free_bdd_nodes_per_var(counts, n);
+
+            free_bdd_manager(builder);
+        }
+    }
+
+    #[test]
+    fn test_binary_op_truth_tables() {
+        /// an FFI binary operation paired with its truth table
+        type BinOpCase = (
+            unsafe extern "C" fn(
+                *mut RsddBddBuilder,
+                *mut BddPtr<'static>,
+                *mut BddPtr<'static>,
+            ) -> *mut BddPtr<'static>,
+            fn(bool, bool) -> bool,
+        );
+        unsafe {
+            let builder = mk_bdd_manager_default_order(2);
+            let a = bdd_var(builder, 0, true);
+            let b = bdd_var(builder, 1, true);
+            let ops: [BinOpCase; 4] = [
+                (bdd_xor, |x, y| x != y),
+                (bdd_implies, |x, y| !x || y),
+                (bdd_iff, |x, y| x == y),
+                (bdd_and, |x, y| x && y),
+            ];
+            for (op, table) in ops {
+                let f = op(builder, a, b);
+                for va in [false, true] {
+                    for vb in [false, true] {
+                        let fa = bdd_condition(builder, f, 0, va);
+                        let fab = bdd_condition(builder, fa, 1, vb);
+                        assert!(bdd_is_const(fab));
+                        assert_eq!(bdd_is_true(fab), table(va, vb));
+                        free_bdd(fab);
+                        free_bdd(fa);
+                    }
+                }
+                free_bdd(f);
+            }
+
+            assert!(bdd_xor(builder, a, std::ptr::null_mut()).is_null());
+            assert!(bdd_implies(std::ptr::null_mut(), a, b).is_null());
+
+            free_bdd(a);
+            free_bdd(b);
+            free_bdd_manager(builder);
+        }
+    }
+
+    #[test]
+    fn test_set_get_order_round_trip() {
+        unsafe {
+            let builder = mk_bdd_manager_default_order(4);
+            let n = bdd_num_vars(builder);
+            let order = bdd_get_order(builder);
+            assert_eq!(std::slice::from_raw_parts(order, n), &[0, 1, 2, 3]);
+            free_bdd_order(order, n);
+
+            let new_order: [u64; 4] = [2, 0, 3, 1];
+            assert!(bdd_set_order(builder, new_order.as_ptr(), 4));
+            let order = bdd_get_order(builder);
+            assert_eq!(std::slice::from_raw_parts(order, n), &new_order);
+            free_bdd_order(order, n);
+
+            // diagrams built afterwards follow the new order
+            let x0 = bdd_var(builder, 0, true);
+            let x2 = bdd_var(builder, 2, true);
+            let f = bdd_and(builder, x0, x2);
+            assert_eq!(bdd_topvar(f), 2);
+
+            let bad: [u64; 4] = [2, 0, 2, 1];
+            assert!(!bdd_set_order(builder, bad.as_ptr(), 4));
+            let msg = CStr::from_ptr(rsdd_last_error()).to_str().unwrap();
+            assert_eq!(msg, "order is not a permutation");
+            assert!(!bdd_set_order(builder, [4, 0, 1, 2].as_ptr(), 4));
+            assert!(!bdd_set_order(builder, [0, 1].as_ptr(), 2));
+            let msg = CStr::from_ptr(rsdd_last_error()).to_str().unwrap();
+            assert_eq!(msg, "order does not have bdd_num_vars entries");
+            let order = bdd_get_order(builder);
+            assert_eq!(std::slice::from_raw_parts(order, n), &new_order);
+            free_bdd_order(order, n);
+
+            // existing diagrams are rebuilt under a later order
+            let not_x2 = bdd_negate(builder, x2);
+            let g = bdd_or(builder, f, not_x2);
+            assert!(bdd_set_order(builder, [0, 1, 2, 3].as_ptr(), 4));
+            assert_eq!(bdd_topvar(f), 0);
+            assert_eq!(bdd_topvar(x2), 2);
+            let expected = bdd_and(builder, x0, x2);
+            assert!(bdd_eq(builder, f, expected));
+            let expected_g = bdd_implies(builder, x2, x0);
+            assert!(bdd_eq(builder, g, expected_g));
+
+            free_bdd(expected_g);
+            free_bdd(expected);
+            free_bdd(g);
+            free_bdd(not_x2);
+            free_bdd(f);
+            free_bdd(x2);
+            free_bdd(x0);
+            free_bdd_manager(builder);
+        }
+    }
+
+    #[test]
+    fn test_tautology_and_unsat() {
+        unsafe {
+            let builder = mk_bdd_manager_default_order(2);
+            let a = bdd_var(builder, 0, true);
+            let not_a = bdd_negate(builder, a);
+            let valid = bdd_or(builder, a, not_a);
+            let unsat = bdd_and(builder, a, not_a);
+            assert!(bdd_is_tautology(valid));
+            assert!(!bdd_is_unsat(valid));
+            assert!(bdd_is_unsat(unsat));
+            assert!(!bdd_is_tautology(unsat));
+            assert!(!bdd_is_tautology(a) && !bdd_is_unsat(a));
+            assert!(!bdd_is_tautology(std::ptr::null_mut()));
+
+            free_bdd(unsat);
+            free_bdd(valid);
+            free_bdd(not_a);
+            free_bdd(a);
+            free_bdd_manager(builder);
+        }
+    }
+
+    #[test]
+    fn test_new_vars() {
+        unsafe {
+            let builder = mk_bdd_manager_default_order(2);
+            let bdds = bdd_new_vars(builder, 3, true);
+            assert_eq!(bdd_num_vars(builder), 5);
+            let vars = std::slice::from_raw_parts(bdds, 3);
+            for (i, &v) in vars.iter().enumerate() {
+                assert_eq!(bdd_topvar(v), 2 + i as u64);
+                free_bdd(v);
+            }
+            free_bdd_array(bdds, 3);
+            assert!(bdd_new_vars(std::ptr::null_mut(), 3, true).is_null());
+            free_bdd_manager(builder);
+        }
+    }
+
+    #[test]
+    fn test_rsdd_string_round_trip() {
+        unsafe {
+            let builder = mk_bdd_manager_default_order(2);
+            let x = bdd_var(builder, 0, true);
+            let y = bdd_var(builder, 1, true);
+            let f = bdd_and(builder, x, y);
+
+            let printed = print_bdd(f);
+            let expected = (*f).print_bdd();
+            assert_eq!(CStr::from_ptr(printed).to_str().unwrap(), expected);
+            free_rsdd_string(printed);
+
+            let json = bdd_json(f);
+            assert_eq!(CStr::from_ptr(json).to_str().unwrap(), (*f).bdd_json());
+            free_rsdd_string(json);
+
+            // freeing NULL is a no-op
+            free_rsdd_string(std::ptr::null_mut());
+
+            free_bdd(f);
+            free_bdd(y);
+            free_bdd(x);
+            free_bdd_manager(builder);
+        }
+    }
+
+    #[test]
+    fn test_bdd_wmc_grad_finite_differences() {
+        unsafe {
+            let builder = mk_bdd_manager_default_order(3);
+            let x = bdd_var(builder, 0, true);
+            let y = bdd_var(builder, 1, true);
+            let z = bdd_var(builder, 2, true);
+            let f = bdd_or(builder, bdd_and(builder, x, y), z);
+
+            let weights = [(0.4, 0.6), (0.7, 0.3), (0.9, 0.2)];
+            let wmc_at = |i: usize, delta: f64| {
+                let params = new_wmc_params_f64();
+                for (v, &(l, h)) in weights.iter().enumerate() {
+                    let h = if v == i { h + delta } else { h };
+                    wmc_param_f64_set_weight(params, v as u64, l, h);
+                }
+                let r = bdd_wmc(f, params);
+                free_wmc_params(params);
+                r
+            };
+
+            let params = new_wmc_params_grad();
+            for (v, &(l, h)) in weights.iter().enumerate() {
+                wmc_param_set_weight_with_grad(params, v as u64, l, h, v);
+            }
+            let grad = bdd_wmc_grad(f, params);
+            let grad_slice = std::slice::from_raw_parts(grad, wmc_grad_num_partials());
+
+            let eps = 1e-6;
+            for (i, g) in grad_slice.iter().enumerate().take(weights.len()) {
+                let fd = (wmc_at(i, eps) - wmc_at(i, -eps)) / (2.0 * eps);
+                assert!((g - fd).abs() < 1e-6, "partial {}: {} vs {}", i, g, fd);
+            }
+            assert!(grad_slice[weights.len()..].iter().all(|g| *g == 0.0));
+
+            free_wmc_grad(grad);
+            free_wmc_params_grad(params);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! A safe Rust façade over the C API's BDD builder
+//!
+//! The C API hands out the builder as an opaque `RsddBddBuilder` pointer and
+//! BDDs as boxed `BddPtr<'static>`s, leaving the caller to free both.
+//! `OwnedRobddBuilder` owns the builder, frees it on drop, and returns BDDs
+//! borrowed from it, so they cannot outlive it.
+
+use std::ffi::CStr;
+
+use crate::ffi::*;
+use crate::repr::{BddPtr, Cnf, VarOrder, WmcParams};
+use crate::util::semirings::RealSemiring;
+
+/// An owned BDD builder backed by the C API.
+/// ```
+/// use rsdd::ffi_safe::OwnedRobddBuilder;
+/// use rsdd::repr::{Cnf, WmcParams};
+/// use rsdd::util::semirings::RealSemiring;
+///
+/// let builder = OwnedRobddBuilder::new_with_linear_order(2);
+/// let bdd = builder.compile_cnf(&Cnf::from_dimacs("p cnf 2 1\n1 2 0\n")).unwrap();
+/// let mut params = WmcParams::default();
+/// params.set_default(RealSemiring(0.5), RealSemiring(0.5));
+/// assert_eq!(builder.wmc(bdd, &params), 0.75);
+/// ```
+pub struct OwnedRobddBuilder {
+    ptr: *mut RsddBddBuilder,
+}
+
+impl OwnedRobddBuilder {
+    /// Creates a builder with the given variable order.
+    pub fn new(order: VarOrder) -> OwnedRobddBuilder {
+        let ptr = unsafe { robdd_builder_all_table(Box::into_raw(Box::new(order))) };
+        OwnedRobddBuilder { ptr }
+    }
+
+    /// Creates a builder over `num_vars` variables in their natural order.
+    pub fn new_with_linear_order(num_vars: usize) -> OwnedRobddBuilder {
+        OwnedRobddBuilder::new(VarOrder::linear_order(num_vars))
+    }
+
+    /// Compiles `cnf` into a BDD, or returns the C API's error message (e.g.,
+    /// if the builder's time limit is exceeded).
+    pub fn compile_cnf(&self, cnf: &Cnf) -> Result<BddPtr<'_>, String> {
+        let cnf = Box::into_raw(Box::new(cnf.clone()));
+        let bdd = unsafe { robdd_builder_compile_cnf(self.ptr, cnf) };
+        unsafe { take_bdd(bdd) }.ok_or_else(last_error)
+    }
+
+    /// Computes the unsmoothed weighted model count of `bdd`.
+    pub fn wmc(&self, bdd: BddPtr<'_>, params: &WmcParams<RealSemiring>) -> f64 {
+        let mut bdd = bdd;
+        let params = (params as *const WmcParams<RealSemiring>).cast_mut();
+        unsafe { bdd_wmc((&mut bdd as *mut BddPtr).cast(), params) }
+    }
+
+    /// Draws a satisfying partial assignment of `bdd` with probability
+    /// proportional to its weight, returned as a conjunction of literals
+    /// along with that probability.
+    pub fn sample<'a>(
+        &'a self,
+        bdd: BddPtr<'a>,
+        params: &WmcParams<RealSemiring>,
+    ) -> (BddPtr<'a>, f64) {
+        let mut bdd = bdd;
+        let params = (params as *const WmcParams<RealSemiring>).cast_mut();
+        let result =
+            unsafe { robdd_weighted_sample(self.ptr, (&mut bdd as *mut BddPtr).cast(), params) };
+        let sample = unsafe { take_bdd(result.sample) }
+            .expect("robdd_weighted_sample is only NULL on NULL arguments");
+        (sample, result.probability)
+    }
+}
+
+impl Drop for OwnedRobddBuilder {
+    fn drop(&mut self) {
+        unsafe { free_bdd_manager(self.ptr) }
+    }
+}
+
+/// unboxes a BDD returned by the C API, or `None` if it is NULL
+unsafe fn take_bdd<'a>(bdd: *mut BddPtr<'static>) -> Option<BddPtr<'a>> {
+    if bdd.is_null() {
+        None
+    } else {
+        Some(*Box::from_raw(bdd))
+    }
+}
+
+fn last_error() -> String {
+    let msg = rsdd_last_error();
+    if msg.is_null() {
+        String::from("unknown error")
+    } else {
+        unsafe { CStr::from_ptr(msg) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repr::{DDNNFPtr, VarLabel};
+
+    fn params(probs: &[f64]) -> WmcParams<RealSemiring> {
+        let mut params = WmcParams::default();
+        for (v, p) in probs.iter().enumerate() {
+            params.set_weight(
+                VarLabel::new_usize(v),
+                RealSemiring(1.0 - p),
+                RealSemiring(*p),
+            );
+        }
+        params
+    }
+
+    #[test]
+    fn test_owned_builder_compile_and_wmc() {
+        let builder = OwnedRobddBuilder::new_with_linear_order(3);
+        let cnf = Cnf::from_dimacs("p cnf 3 2\n1 2 0\n-1 3 0\n");
+        let bdd = builder.compile_cnf(&cnf).unwrap();
+        // (a | b) & (!a | c)
+        let (pa, pb, pc) = (0.3, 0.6, 0.8);
+        let expected = pa * pc + (1.0 - pa) * pb;
+        let wmc = builder.wmc(bdd, &params(&[pa, pb, pc]));
+        assert!((wmc - expected).abs() < 1e-12);
+        // compiling again hits the same canonical diagram
+        assert_eq!(builder.compile_cnf(&cnf).unwrap(), bdd);
+    }
+
+    #[test]
+    fn test_owned_builder_sample() {
+        let builder = OwnedRobddBuilder::new_with_linear_order(3);
+        let bdd = builder
+            .compile_cnf(&Cnf::from_dimacs("p cnf 3 2\n1 2 0\n-1 3 0\n"))
+            .unwrap();
+        let params = params(&[0.3, 0.6, 0.8]);
+        let total = builder.wmc(bdd, &params);
+        for _ in 0..20 {
+            let (sample, prob) = builder.sample(bdd, &params);
+            assert!(prob > 0.0 && prob <= 1.0);
+            // every completion of the sample is a model of the BDD
+            for i in 0..8usize {
+                let assgn = [0, 1, 2].map(|v| i & (1 << v) != 0);
+                assert!(!sample.evaluate(&assgn) || bdd.evaluate(&assgn));
+            }
+            assert!(builder.wmc(sample, &params) <= total + 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_owned_builder_reports_timeout() {
+        let builder = OwnedRobddBuilder::new_with_linear_order(4);
+        unsafe { start_bdd_manager_time_limit(builder.ptr, 0.0) };
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        let r = builder.compile_cnf(&Cnf::from_dimacs("p cnf 4 2\n1 -2 0\n3 4 0\n"));
+        assert_eq!(r, Err(String::from("BDD builder time limit exceeded")));
+    }
+}
@@ -31,3 +31,5 @@ pub mod wasm;
 mod ffi;
 #[cfg(feature = "ffi")]
 pub use self::ffi::*;
+#[cfg(feature = "ffi")]
+pub mod ffi_safe;
@@ -0,0 +1,296 @@
+//! And-inverter graphs, read from the ASCII AIGER (`aag`) format
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, BufRead},
+};
+
+/// Describes why an ASCII AIGER file could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AigParseError {
+    /// the input does not start with a well-formed `aag M I L O A` header
+    MissingHeader,
+    /// a token that should be an unsigned integer is not one
+    InvalidToken { line: usize, token: String },
+    /// a line has the wrong number of fields for its section, or defines a
+    /// literal that is odd, constant or already defined
+    MalformedLine { line: usize },
+    /// the input ended before every declared input, output and gate
+    UnexpectedEof,
+    /// the file declares latches, which have no combinational meaning
+    LatchesUnsupported,
+    /// a literal's variable is neither an input nor the output of a gate, or
+    /// exceeds the maximum variable index declared in the header
+    UndefinedLiteral { lit: u64 },
+    /// the gate defining this literal depends on itself
+    CyclicGate { lit: u64 },
+}
+
+impl fmt::Display for AigParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AigParseError::MissingHeader => write!(f, "missing `aag` header"),
+            AigParseError::InvalidToken { line, token } => {
+                write!(f, "line {}: expected an integer, found `{}`", line, token)
+            }
+            AigParseError::MalformedLine { line } => write!(f, "line {}: malformed line", line),
+            AigParseError::UnexpectedEof => write!(f, "unexpected end of input"),
+            AigParseError::LatchesUnsupported => write!(f, "latches are not supported"),
+            AigParseError::UndefinedLiteral { lit } => write!(f, "literal {} is undefined", lit),
+            AigParseError::CyclicGate { lit } => {
+                write!(f, "the gate defining literal {} depends on itself", lit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AigParseError {}
+
+/// A combinational and-inverter graph. Following the AIGER convention, a
+/// literal `2v` refers to variable `v` and `2v + 1` to its negation; literal
+/// 0 is false and 1 is true.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aig {
+    /// the (even) literal of each input, in declaration order
+    inputs: Vec<u64>,
+    /// each AND gate as `(lhs, rhs0, rhs1)`, ordered so that every gate
+    /// comes after the gates it reads
+    ands: Vec<(u64, u64, u64)>,
+    /// each output's name and literal, in declaration order
+    outputs: Vec<(String, u64)>,
+}
+
+impl Aig {
+    /// Reads an ASCII AIGER file: the `aag M I L O A` header, then one line
+    /// per input, output and AND gate, then an optional symbol table and
+    /// comment section. Outputs are named by the symbol table where it names
+    /// them, and `o<k>` (for the `k`th output) otherwise. AND gates may be
+    /// listed in any order; they are sorted topologically.
+    ///
+    /// Malformed input produces an `InvalidData` error wrapping an
+    /// `AigParseError`.
+    /// ```
+    /// use std::io::Cursor;
+    /// use rsdd::repr::Aig;
+    ///
+    /// // the negated AND of two inputs
+    /// let aag = "aag 3 2 0 1 1\n2\n4\n7\n6 2 4\no0 nand\n";
+    /// let aig = Aig::from_aag_reader(Cursor::new(aag)).unwrap();
+    /// assert_eq!(aig.inputs(), &[2, 4]);
+    /// assert_eq!(aig.outputs(), &[(String::from("nand"), 7)]);
+    /// ```
+    pub fn from_aag_reader<R: BufRead>(reader: R) -> io::Result<Aig> {
+        let lines = reader.lines().collect::<io::Result<Vec<String>>>()?;
+        Aig::parse(&lines).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn parse(lines: &[String]) -> Result<Aig, AigParseError> {
+        // the unsigned integers on line `idx` (0-based), which must number `count`
+        let fields = |idx: usize, count: usize| -> Result<Vec<u64>, AigParseError> {
+            let text = lines.get(idx).ok_or(AigParseError::UnexpectedEof)?;
+            let fields = text
+                .split_whitespace()
+                .map(|tok| {
+                    tok.parse().map_err(|_| AigParseError::InvalidToken {
+                        line: idx + 1,
+                        token: tok.to_string(),
+                    })
+                })
+                .collect::<Result<Vec<u64>, _>>()?;
+            if fields.len() != count {
+                return Err(AigParseError::MalformedLine { line: idx + 1 });
+            }
+            Ok(fields)
+        };
+
+        let header: Vec<&str> = lines
+            .first()
+            .ok_or(AigParseError::MissingHeader)?
+            .split_whitespace()
+            .collect();
+        if header.len() != 6 || header[0] != "aag" {
+            return Err(AigParseError::MissingHeader);
+        }
+        let header = header[1..]
+            .iter()
+            .map(|tok| {
+                tok.parse().map_err(|_| AigParseError::InvalidToken {
+                    line: 1,
+                    token: tok.to_string(),
+                })
+            })
+            .collect::<Result<Vec<u64>, _>>()?;
+        let (max_var, num_inputs, num_latches, num_outputs, num_ands) = (
+            header[0],
+            header[1] as usize,
+            header[2],
+            header[3] as usize,
+            header[4] as usize,
+        );
+        if num_latches != 0 {
+            return Err(AigParseError::LatchesUnsupported);
+        }
+
+        // maps each defined variable to the index of its gate, if it has one
+        let mut defined: HashMap<u64, Option<usize>> = HashMap::new();
+        let mut define = |lit: u64, gate: Option<usize>, idx: usize| {
+            if lit < 2 || lit % 2 == 1 || defined.insert(lit / 2, gate).is_some() {
+                return Err(AigParseError::MalformedLine { line: idx + 1 });
+            }
+            Ok(())
+        };
+
+        let mut idx = 1;
+        let mut inputs = Vec::with_capacity(num_inputs);
+        for _ in 0..num_inputs {
+            let lit = fields(idx, 1)?[0];
+            define(lit, None, idx)?;
+            inputs.push(lit);
+            idx += 1;
+        }
+        let mut outputs = Vec::with_capacity(num_outputs);
+        for k in 0..num_outputs {
+            outputs.push((format!("o{}", k), fields(idx, 1)?[0]));
+            idx += 1;
+        }
+        let mut ands = Vec::with_capacity(num_ands);
+        for gate in 0..num_ands {
+            let f = fields(idx, 3)?;
+            define(f[0], Some(gate), idx)?;
+            ands.push((f[0], f[1], f[2]));
+            idx += 1;
+        }
+
+        // the symbol table, which ends at the comment section
+        for (i, line) in lines.iter().enumerate().skip(idx) {
+            if line.starts_with('c') {
+                break;
+            }
+            let malformed = AigParseError::MalformedLine { line: i + 1 };
+            let (pos, name) = line.split_once(' ').ok_or(malformed.clone())?;
+            if let Some(k) = pos.strip_prefix('o') {
+                let k: usize = k.parse().map_err(|_| malformed.clone())?;
+                outputs.get_mut(k).ok_or(malformed)?.0 = name.to_string();
+            }
+        }
+
+        let check = |lit: u64| {
+            let var = lit / 2;
+            if var == 0 || (var <= max_var && defined.contains_key(&var)) {
+                Ok(())
+            } else {
+                Err(AigParseError::UndefinedLiteral { lit })
+            }
+        };
+        for &(lhs, r0, r1) in ands.iter() {
+            check(lhs)?;
+            check(r0)?;
+            check(r1)?;
+        }
+        for (_, lit) in outputs.iter() {
+            check(*lit)?;
+        }
+        for lit in inputs.iter() {
+            check(*lit)?;
+        }
+
+        // order the gates by an iterative depth-first search, so that deep
+        // circuits cannot overflow the stack
+        let mut state = vec![0u8; ands.len()]; // 0: new, 1: open, 2: done
+        let mut sorted = Vec::with_capacity(ands.len());
+        for root in 0..ands.len() {
+            if state[root] != 0 {
+                continue;
+            }
+            state[root] = 1;
+            let mut stack = vec![(root, 0)];
+            while let Some((gate, child)) = stack.pop() {
+                let (_, r0, r1) = ands[gate];
+                if child == 2 {
+                    state[gate] = 2;
+                    sorted.push(ands[gate]);
+                    continue;
+                }
+                stack.push((gate, child + 1));
+                let lit = if child == 0 { r0 } else { r1 };
+                if let Some(&Some(dep)) = defined.get(&(lit / 2)) {
+                    match state[dep] {
+                        0 => {
+                            state[dep] = 1;
+                            stack.push((dep, 0));
+                        }
+                        1 => return Err(AigParseError::CyclicGate { lit: ands[dep].0 }),
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        Ok(Aig {
+            inputs,
+            ands: sorted,
+            outputs,
+        })
+    }
+
+    /// the literal of each input, in declaration order
+    pub fn inputs(&self) -> &[u64] {
+        &self.inputs
+    }
+
+    /// each AND gate as `(lhs, rhs0, rhs1)`, in topological order
+    pub fn ands(&self) -> &[(u64, u64, u64)] {
+        &self.ands
+    }
+
+    /// each output's name and literal, in declaration order
+    pub fn outputs(&self) -> &[(String, u64)] {
+        &self.outputs
+    }
+}
+
+#[test]
+fn from_aag_reader_sorts_gates_and_rejects_bad_input() {
+    use std::io::Cursor;
+
+    let read = |s: &str| Aig::from_aag_reader(Cursor::new(s));
+    let error = |s: &str| {
+        let e = read(s).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        e.into_inner()
+            .unwrap()
+            .downcast::<AigParseError>()
+            .map(|e| *e)
+            .unwrap()
+    };
+
+    // gate 8 reads gate 6, which is listed after it; unnamed outputs get
+    // positional names, and constant and inverted outputs are allowed
+    let aig = read("aag 4 2 0 3 2\n2\n4\n9\n0\n1\n8 6 3\n6 2 5\n").unwrap();
+    assert_eq!(aig.ands(), &[(6, 2, 5), (8, 6, 3)]);
+    let names: Vec<&str> = aig.outputs().iter().map(|(n, _)| n.as_str()).collect();
+    assert_eq!(names, ["o0", "o1", "o2"]);
+
+    assert_eq!(error("aig 1 1 0 1 0\n2\n2\n"), AigParseError::MissingHeader);
+    assert_eq!(
+        error("aag 1 1 1 0 0\n2\n2 3\n"),
+        AigParseError::LatchesUnsupported
+    );
+    assert_eq!(error("aag 2 1 0 1 1\n2\n4\n"), AigParseError::UnexpectedEof);
+    assert_eq!(
+        error("aag 2 1 0 1 0\n2\n6\n"),
+        AigParseError::UndefinedLiteral { lit: 6 }
+    );
+    assert_eq!(
+        error("aag 3 1 0 1 2\n2\n6\n4 6 2\n6 4 2\n"),
+        AigParseError::CyclicGate { lit: 4 }
+    );
+    assert_eq!(
+        error("aag 1 1 0 1 0\n2\nx\n"),
+        AigParseError::InvalidToken {
+            line: 3,
+            token: String::from("x")
+        }
+    );
+}
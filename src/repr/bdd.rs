@@ -7,14 +7,16 @@ use crate::{
     repr::{DDNNFPtr, DDNNF},
     repr::{Literal, VarLabel, VarSet},
     util::semirings::ExpectedUtility,
-    util::semirings::{BBSemiring, FiniteField, JoinSemilattice, RealSemiring},
+    util::semirings::{
+        BBSemiring, FiniteField, JoinSemilattice, RationalSemiring, RealSemiring, Semiring,
+    },
 };
 use bit_set::BitSet;
 use core::fmt::Debug;
 use std::{
     any::Any,
     cell::RefCell,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     hash::{Hash, Hasher},
     iter::FromIterator,
     ptr,
@@ -24,7 +26,14 @@ use BddPtr::*;
 use super::var_order::PartialVariableOrder;
 
 /// Core BDD pointer datatype
-#[derive(Debug, Clone, Eq, Copy, PartialOrd, Ord)]
+///
+/// Pointers are ordered by identity, consistently with `Eq` and `Hash`:
+/// `PtrFalse` comes first, then `PtrTrue`, then pointers to nodes by the
+/// address of the node, with `Reg` before `Compl` for the same node. Nodes do
+/// not move while their builder holds them, so the order is deterministic
+/// within one builder (e.g. for iterating a `BTreeMap` keyed by pointers),
+/// but not across builders, garbage collections or runs.
+#[derive(Debug, Clone, Eq, Copy)]
 pub enum BddPtr<'a> {
     Compl(&'a BddNode<'a>),
     Reg(&'a BddNode<'a>),
@@ -42,6 +51,30 @@ impl<'a> PartialEq for BddPtr<'a> {
     }
 }
 
+impl<'a> BddPtr<'a> {
+    /// the key by which pointers are ordered; node addresses are never 0
+    fn identity(&self) -> (usize, bool) {
+        match self {
+            PtrFalse => (0, false),
+            PtrTrue => (0, true),
+            Reg(n) => (*n as *const BddNode as usize, false),
+            Compl(n) => (*n as *const BddNode as usize, true),
+        }
+    }
+}
+
+impl<'a> PartialOrd for BddPtr<'a> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a> Ord for BddPtr<'a> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.identity().cmp(&other.identity())
+    }
+}
+
 impl<'a> Hash for BddPtr<'a> {
     fn hash<H: Hasher>(&self, state: &mut H) {
         core::mem::discriminant(self).hash(state);
@@ -299,6 +332,47 @@ impl<'a> BddPtr<'a> {
         }
     }
 
+    /// The cofactor of this BDD with its top variable set to true, i.e. the
+    /// function `f | var = true`. This is the high child with the pointer's
+    /// complement applied, so it is semantically correct for complemented
+    /// pointers, unlike `high_raw`, which returns the child as stored in the
+    /// shared node. A constant is its own cofactor.
+    /// ```
+    /// use rsdd::repr::{BddNode, BddPtr, VarLabel};
+    ///
+    /// // this node represents the positive literal 0
+    /// let node = BddNode::new(VarLabel::new(0), BddPtr::PtrFalse, BddPtr::PtrTrue);
+    ///
+    /// // the complemented pointer represents the negative literal 0
+    /// assert_eq!(BddPtr::Compl(&node).cofactor_true(), BddPtr::PtrFalse);
+    /// assert_eq!(BddPtr::Compl(&node).high_raw(), BddPtr::PtrTrue);
+    /// assert_eq!(BddPtr::PtrTrue.cofactor_true(), BddPtr::PtrTrue);
+    /// ```
+    pub fn cofactor_true(&self) -> BddPtr<'a> {
+        match &self {
+            Compl(_) | Reg(_) => self.high(),
+            PtrTrue | PtrFalse => *self,
+        }
+    }
+
+    /// The cofactor of this BDD with its top variable set to false, i.e. the
+    /// function `f | var = false`; see `cofactor_true`.
+    /// ```
+    /// use rsdd::repr::{BddNode, BddPtr, VarLabel};
+    ///
+    /// let node = BddNode::new(VarLabel::new(0), BddPtr::PtrFalse, BddPtr::PtrTrue);
+    ///
+    /// assert_eq!(BddPtr::Compl(&node).cofactor_false(), BddPtr::PtrTrue);
+    /// assert_eq!(BddPtr::Compl(&node).low_raw(), BddPtr::PtrFalse);
+    /// assert_eq!(BddPtr::PtrFalse.cofactor_false(), BddPtr::PtrFalse);
+    /// ```
+    pub fn cofactor_false(&self) -> BddPtr<'a> {
+        match &self {
+            Compl(_) | Reg(_) => self.low(),
+            PtrTrue | PtrFalse => *self,
+        }
+    }
+
     /// Traverses the BDD and clears all scratch memory (sets it equal to 0)
     pub fn clear_scratch(&self) {
         match &self {
@@ -335,9 +409,43 @@ impl<'a> BddPtr<'a> {
         }
     }
 
+    /// Whether this BDD is valid, i.e. true under every assignment. Since a
+    /// reduced BDD is canonical, this is the case exactly when it is the
+    /// true terminal; an unreduced diagram (e.g. a smoothed one) may be valid
+    /// without being the terminal.
+    /// ```
+    /// use rsdd::repr::{BddNode, BddPtr, VarLabel};
+    ///
+    /// assert!(BddPtr::PtrTrue.is_tautology());
+    /// assert!(!BddPtr::PtrFalse.is_tautology());
+    ///
+    /// let node = BddNode::new(VarLabel::new(0), BddPtr::PtrFalse, BddPtr::PtrTrue);
+    /// assert!(!BddPtr::Reg(&node).is_tautology());
+    /// ```
+    pub fn is_tautology(&self) -> bool {
+        matches!(self, PtrTrue)
+    }
+
+    /// Whether this BDD is unsatisfiable, i.e. false under every assignment;
+    /// as with `is_tautology`, this relies on the BDD being reduced.
+    /// ```
+    /// use rsdd::repr::{BddNode, BddPtr, VarLabel};
+    ///
+    /// assert!(BddPtr::PtrFalse.is_unsat());
+    /// assert!(!BddPtr::PtrTrue.is_unsat());
+    ///
+    /// let node = BddNode::new(VarLabel::new(0), BddPtr::PtrFalse, BddPtr::PtrTrue);
+    /// assert!(!BddPtr::Compl(&node).is_unsat());
+    /// ```
+    pub fn is_unsat(&self) -> bool {
+        matches!(self, PtrFalse)
+    }
+
     /// Gets the scratch value stored in `&self`
     ///
-    /// Panics if not node.
+    /// In debug builds, panics if the scratch holds a value of a type other
+    /// than `T` (e.g., left behind by a different traversal that did not
+    /// clear it); in release builds such a read returns `None`.
     pub fn scratch<T: ?Sized + Clone + 'static>(&self) -> Option<T> {
         match self {
             Compl(n) | Reg(n) => {
@@ -345,13 +453,16 @@ impl<'a> BddPtr<'a> {
                     return None;
                 }
                 // println!("dereferencing {:?}", n.data.as_ptr());
-                n.data
-                    .borrow()
-                    .as_ref()
-                    .unwrap()
-                    .as_ref()
-                    .downcast_ref::<T>()
-                    .cloned()
+                let data = n.data.borrow();
+                let (v, stored_type) = data.as_ref().unwrap();
+                let r = v.downcast_ref::<T>();
+                debug_assert!(
+                    r.is_some(),
+                    "scratch holds a `{}` but was read as a `{}`",
+                    stored_type,
+                    std::any::type_name::<T>()
+                );
+                r.cloned()
             }
             PtrTrue => None,
             PtrFalse => None,
@@ -368,7 +479,7 @@ impl<'a> BddPtr<'a> {
     pub fn set_scratch<T: 'static>(&self, v: T) {
         match self {
             Compl(n) | Reg(n) => {
-                *n.data.borrow_mut() = Some(Box::new(v));
+                *n.data.borrow_mut() = Some((Box::new(v), std::any::type_name::<T>()));
             }
             _ => panic!("attempting to store scratch on constant"),
         }
@@ -384,6 +495,256 @@ impl<'a> BddPtr<'a> {
         }
     }
 
+    /// Panics if any node reachable from this BDD holds a scratch value.
+    /// `is_scratch_cleared` only inspects the root, but a traversal that was
+    /// interrupted, or a caller that interleaves scratch-using passes, can
+    /// leave stale values deeper in the diagram, where the next pass would
+    /// silently read them as its own cache. Scratch-using algorithms call
+    /// this on entry in debug builds.
+    pub fn assert_scratch_cleared_recursive(&self) {
+        fn check_h<'a>(ptr: BddPtr<'a>, seen: &mut HashSet<*const BddNode<'a>>) {
+            if let Compl(n) | Reg(n) = ptr {
+                if !seen.insert(n as *const BddNode<'a>) {
+                    return;
+                }
+                if let Some((_, stored_type)) = n.data.borrow().as_ref() {
+                    panic!(
+                        "node on {:?} still holds scratch of type `{}`",
+                        n.var, stored_type
+                    );
+                }
+                check_h(n.low, seen);
+                check_h(n.high, seen);
+            }
+        }
+        check_h(*self, &mut HashSet::new());
+    }
+
+    /// Counts the nodes reachable from this BDD labeled by each variable.
+    /// A level with many more nodes than its neighbors often indicates a poor
+    /// variable order.
+    ///
+    /// Pre-condition: scratch cleared
+    pub fn nodes_per_var(&self) -> HashMap<VarLabel, usize> {
+        debug_assert!(self.is_scratch_cleared());
+        fn count_h(ptr: BddPtr, counts: &mut HashMap<VarLabel, usize>) {
+            if ptr.is_const() || ptr.scratch::<usize>().is_some() {
+                return;
+            }
+            *counts.entry(ptr.var_safe().unwrap()).or_insert(0) += 1;
+            ptr.set_scratch::<usize>(0);
+            count_h(ptr.low_raw(), counts);
+            count_h(ptr.high_raw(), counts);
+        }
+        let mut counts = HashMap::new();
+        count_h(*self, &mut counts);
+        self.clear_scratch();
+        counts
+    }
+
+    /// Counts the nodes reachable from this BDD. `count_nodes` excludes the
+    /// terminals, as is conventional when reporting BDD sizes; with
+    /// `count_terminals` set, the terminals reached are counted too. `PtrTrue`
+    /// and `PtrFalse` are distinct terminals (unlike CUDD, where false is a
+    /// complemented edge to the one constant node, so `Cudd_DagSize` counts at
+    /// most one), so a reduced non-constant BDD reaches both of them.
+    ///
+    /// Pre-condition: scratch cleared
+    pub fn count_nodes_with_opts(&self, count_terminals: bool) -> usize {
+        debug_assert!(self.is_scratch_cleared());
+        if !count_terminals {
+            return self.count_nodes();
+        }
+        // whether `PtrFalse` and `PtrTrue` were reached, in that order
+        fn terminals_h(ptr: BddPtr, reached: &mut [bool; 2]) {
+            match ptr {
+                PtrFalse => reached[0] = true,
+                PtrTrue => reached[1] = true,
+                _ if ptr.scratch::<usize>().is_some() => (),
+                _ => {
+                    ptr.set_scratch::<usize>(0);
+                    terminals_h(ptr.low_raw(), reached);
+                    terminals_h(ptr.high_raw(), reached);
+                }
+            }
+        }
+        let mut reached = [false; 2];
+        terminals_h(*self, &mut reached);
+        self.clear_scratch();
+        self.count_nodes() + reached.iter().filter(|&&r| r).count()
+    }
+
+    /// Counts the paths from the root to the true terminal. Unlike the model
+    /// count, a path that skips a variable counts once rather than once per
+    /// value of that variable, so this measures the diagram rather than the
+    /// function it represents (the count depends on the variable order).
+    ///
+    /// # Panics
+    ///
+    /// Panics with "path count overflowed u128" if the count does not fit in
+    /// a `u128`, which a parity function over 130 variables already exceeds.
+    ///
+    /// Pre-condition: scratch cleared
+    pub fn path_count(&self) -> u128 {
+        debug_assert!(self.is_scratch_cleared());
+        self.bdd_fold(
+            &|_, low: u128, high: u128| low.checked_add(high).expect("path count overflowed u128"),
+            0,
+            1,
+        )
+    }
+
+    /// Computes the same weighted model count as `unsmoothed_wmc` with exact
+    /// rational weights, or `None` if a numerator or denominator along the
+    /// way overflows `i128`. Unlike the `RationalSemiring` operators, this
+    /// never panics on overflow, which can happen after a few dozen variables
+    /// whose weights have coprime denominators.
+    ///
+    /// Pre-condition: scratch cleared
+    /// ```
+    /// use rsdd::repr::{BddNode, BddPtr, VarLabel, WmcParams};
+    /// use rsdd::util::semirings::RationalSemiring;
+    ///
+    /// let node = BddNode::new(VarLabel::new(0), BddPtr::PtrFalse, BddPtr::PtrTrue);
+    /// let (lo, hi) = (RationalSemiring::new(1, 3), RationalSemiring::new(2, 3));
+    /// let params = WmcParams::from_slice(&[(lo, hi)]);
+    /// assert_eq!(
+    ///     BddPtr::Compl(&node).checked_rational_wmc(&params),
+    ///     Some(RationalSemiring::new(1, 3))
+    /// );
+    /// ```
+    pub fn checked_rational_wmc(
+        &self,
+        params: &WmcParams<RationalSemiring>,
+    ) -> Option<RationalSemiring> {
+        debug_assert!(self.is_scratch_cleared());
+        self.bdd_fold(
+            &|v, low: Option<RationalSemiring>, high: Option<RationalSemiring>| {
+                let (low_w, high_w) = params.var_weight(v);
+                low_w
+                    .checked_mul(low?)?
+                    .checked_add(high_w.checked_mul(high?)?)
+            },
+            Some(params.zero),
+            Some(params.one),
+        )
+    }
+
+    /// Counts the distinct non-constant Boolean functions reachable from this
+    /// BDD by taking (complement-respecting) cofactors. A node reached both
+    /// through a regular and through a complemented edge stands for two
+    /// functions, `f` and `!f`, so it is counted twice here but once by
+    /// `count_nodes`, which counts the nodes stored. The two measures agree on
+    /// diagrams without complemented edges, and this one is at most twice the
+    /// other.
+    /// ```
+    /// use rsdd::repr::{BddNode, BddPtr, DDNNFPtr, VarLabel};
+    ///
+    /// // a xor b, whose high edge is the complement of its low edge
+    /// let b = BddNode::new(VarLabel::new(1), BddPtr::PtrFalse, BddPtr::PtrTrue);
+    /// let a = BddNode::new(VarLabel::new(0), BddPtr::Reg(&b), BddPtr::Compl(&b));
+    ///
+    /// assert_eq!(BddPtr::Reg(&a).count_nodes(), 2);
+    /// assert_eq!(BddPtr::Reg(&a).distinct_subfunctions(), 3);
+    /// ```
+    #[allow(clippy::mutable_key_type)]
+    pub fn distinct_subfunctions(&self) -> usize {
+        let mut seen = HashSet::new();
+        let mut stack = vec![*self];
+        while let Some(ptr) = stack.pop() {
+            if ptr.is_const() || !seen.insert(ptr) {
+                continue;
+            }
+            stack.push(ptr.low());
+            stack.push(ptr.high());
+        }
+        seen.len()
+    }
+
+    /// Computes the same weighted model count as `unsmoothed_wmc`, memoizing
+    /// the count of every pointer reached in `cache`. Passing the same cache
+    /// to several calls (with the same `params`) reuses the counts of the
+    /// subdiagrams they share, so overlapping roots are only counted once.
+    /// Complemented pointers are cached separately from their regular
+    /// counterparts.
+    /// ```
+    /// use std::collections::HashMap;
+    /// use rsdd::repr::{BddNode, BddPtr, VarLabel, WmcParams};
+    /// use rsdd::util::semirings::RealSemiring;
+    ///
+    /// let node = BddNode::new(VarLabel::new(0), BddPtr::PtrFalse, BddPtr::PtrTrue);
+    /// let params = WmcParams::from_slice(&[(RealSemiring(0.3), RealSemiring(0.7))]);
+    ///
+    /// let mut cache = HashMap::new();
+    /// assert_eq!(BddPtr::Reg(&node).unsmoothed_wmc_cached(&params, &mut cache).0, 0.7);
+    /// assert_eq!(BddPtr::Compl(&node).unsmoothed_wmc_cached(&params, &mut cache).0, 0.3);
+    /// ```
+    #[allow(clippy::mutable_key_type)]
+    pub fn unsmoothed_wmc_cached<T: Semiring>(
+        &self,
+        params: &WmcParams<T>,
+        cache: &mut HashMap<BddPtr<'a>, T>,
+    ) -> T {
+        if let Some(v) = cache.get(self) {
+            return *v;
+        }
+        let v = match self {
+            PtrTrue => params.one,
+            PtrFalse => params.zero,
+            Reg(_) | Compl(_) => {
+                let (low_w, high_w) = params.var_weight(self.var_safe().unwrap());
+                let low_v = self.low().unsmoothed_wmc_cached(params, cache);
+                let high_v = self.high().unsmoothed_wmc_cached(params, cache);
+                (*low_w * low_v) + (*high_w * high_v)
+            }
+        };
+        cache.insert(*self, v);
+        v
+    }
+
+    /// A fingerprint of the structure of this BDD: nodes are combined bottom-up
+    /// from their variable label and their children's fingerprints. Unlike
+    /// hashing the pointer, the result is stable across builders and runs, so
+    /// identical diagrams hash equally wherever they were built. (The same
+    /// function compiled under a different variable order generally has a
+    /// different structure, and so a different fingerprint.)
+    ///
+    /// Pre-condition: scratch cleared
+    pub fn structural_hash(&self) -> u64 {
+        debug_assert!(self.is_scratch_cleared());
+        // splitmix64 finalizer
+        fn mix(mut z: u64) -> u64 {
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        }
+        const COMPL_SALT: u64 = 0x9e3779b97f4a7c15;
+        fn hash_h(ptr: BddPtr) -> u64 {
+            let node_hash = match ptr {
+                PtrTrue => mix(1),
+                PtrFalse => return mix(mix(1) ^ COMPL_SALT),
+                Compl(n) | Reg(n) => match ptr.scratch::<u64>() {
+                    Some(h) => h,
+                    None => {
+                        let l = hash_h(n.low);
+                        let h = hash_h(n.high);
+                        let r = mix(mix(n.var.value() ^ mix(l)) ^ h.rotate_left(17));
+                        ptr.set_scratch::<u64>(r);
+                        r
+                    }
+                },
+            };
+            if ptr.is_neg() {
+                mix(node_hash ^ COMPL_SALT)
+            } else {
+                node_hash
+            }
+        }
+        let r = hash_h(*self);
+        self.clear_scratch();
+        r
+    }
+
     pub fn to_string_debug(&self) -> String {
         fn print_bdd_helper(ptr: BddPtr) -> String {
             match ptr {
@@ -1094,9 +1455,9 @@ pub struct BddNode<'a> {
     pub var: VarLabel,
     pub low: BddPtr<'a>,
     pub high: BddPtr<'a>,
-    /// scratch space used for caching data during traversals; ignored during
-    /// equality checking and hashing
-    data: RefCell<Option<Box<dyn Any>>>,
+    /// scratch space used for caching data during traversals, tagged with the
+    /// name of the stored type; ignored during equality checking and hashing
+    data: RefCell<Option<(Box<dyn Any>, &'static str)>>,
     semantic_hash: RefCell<Option<u128>>,
 }
 
@@ -16,11 +16,121 @@ use std::{
     cmp::{max, min},
     collections::HashSet,
     fmt,
+    io::{self, BufRead},
 };
 
 // number of primes to consider during CNF hashing
 const NUM_PRIMES: usize = 2;
 
+/// An error encountered while parsing DIMACS input. Each variant carries the
+/// (1-indexed) line on which parsing failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CnfParseError {
+    /// a clause was reached (or the input ended) without a well-formed
+    /// `p cnf <vars> <clauses>` header
+    MissingHeader { line: usize },
+    /// a literal mentions a variable above the count declared in the header
+    VarOutOfRange {
+        line: usize,
+        var: u64,
+        num_vars: usize,
+    },
+    /// a token that should be an integer is not one
+    InvalidToken { line: usize, token: String },
+}
+
+impl fmt::Display for CnfParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CnfParseError::MissingHeader { line } => {
+                write!(f, "line {}: missing `p cnf` header", line)
+            }
+            CnfParseError::VarOutOfRange {
+                line,
+                var,
+                num_vars,
+            } => write!(
+                f,
+                "line {}: variable {} exceeds declared count {}",
+                line, var, num_vars
+            ),
+            CnfParseError::InvalidToken { line, token } => {
+                write!(f, "line {}: expected an integer, found `{}`", line, token)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CnfParseError {}
+
+/// Line-at-a-time DIMACS parser shared by `Cnf::try_from_dimacs` and
+/// `Cnf::from_dimacs_reader`
+#[derive(Default)]
+struct DimacsParser {
+    num_vars: Option<usize>,
+    clauses: Vec<Vec<Literal>>,
+    cur: Vec<Literal>,
+    last_line: usize,
+}
+
+impl DimacsParser {
+    fn feed(&mut self, line_num: usize, line: &str) -> Result<(), CnfParseError> {
+        self.last_line = line_num;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('c') {
+            return Ok(());
+        }
+        let invalid = |token: &str| CnfParseError::InvalidToken {
+            line: line_num,
+            token: token.to_string(),
+        };
+        if trimmed.starts_with('p') && self.num_vars.is_none() {
+            let fields: Vec<&str> = trimmed.split_whitespace().collect();
+            if fields.len() != 4 || fields[0] != "p" || fields[1] != "cnf" {
+                return Err(CnfParseError::MissingHeader { line: line_num });
+            }
+            let num_vars = fields[2].parse().map_err(|_| invalid(fields[2]))?;
+            fields[3].parse::<usize>().map_err(|_| invalid(fields[3]))?;
+            self.num_vars = Some(num_vars);
+            return Ok(());
+        }
+        let num_vars = self
+            .num_vars
+            .ok_or(CnfParseError::MissingHeader { line: line_num })?;
+        for tok in trimmed.split_whitespace() {
+            let lit: i64 = tok.parse().map_err(|_| invalid(tok))?;
+            if lit == 0 {
+                self.clauses.push(std::mem::take(&mut self.cur));
+                continue;
+            }
+            let var = lit.unsigned_abs();
+            if var > num_vars as u64 {
+                return Err(CnfParseError::VarOutOfRange {
+                    line: line_num,
+                    var,
+                    num_vars,
+                });
+            }
+            // subtract 1, we are 0-indexed
+            self.cur.push(Literal::new(VarLabel::new(var - 1), lit > 0));
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Cnf, CnfParseError> {
+        if self.num_vars.is_none() {
+            return Err(CnfParseError::MissingHeader {
+                line: self.last_line,
+            });
+        }
+        // a final clause may omit its terminating 0
+        if !self.cur.is_empty() {
+            self.clauses.push(self.cur);
+        }
+        Ok(Cnf::new(&self.clauses))
+    }
+}
+
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
 pub struct HashedCNF {
     v: [u128; NUM_PRIMES],
@@ -322,6 +432,49 @@ impl Cnf {
         Cnf::new(&clause_vec)
     }
 
+    /// Parses a DIMACS CNF string, reporting malformed input rather than
+    /// panicking. Unlike `from_dimacs`, every literal is checked against the
+    /// variable count declared in the `p cnf` header.
+    /// ```
+    /// use rsdd::repr::{Cnf, CnfParseError};
+    ///
+    /// assert!(Cnf::try_from_dimacs("p cnf 2 1\n1 -2 0\n").is_ok());
+    /// assert_eq!(
+    ///     Cnf::try_from_dimacs("p cnf 2 1\n1 -3 0\n"),
+    ///     Err(CnfParseError::VarOutOfRange { line: 2, var: 3, num_vars: 2 })
+    /// );
+    /// ```
+    pub fn try_from_dimacs(input: &str) -> Result<Cnf, CnfParseError> {
+        let mut parser = DimacsParser::default();
+        for (idx, line) in input.lines().enumerate() {
+            parser.feed(idx + 1, line)?;
+        }
+        parser.finish()
+    }
+
+    /// Parses a DIMACS CNF incrementally from `reader`, without loading the
+    /// whole input into memory. Comment lines (starting with `c`) are
+    /// skipped, the `p cnf` header is required before the first clause, and
+    /// clauses may span several lines. Malformed input (see
+    /// `try_from_dimacs`) produces an `InvalidData` error wrapping a
+    /// `CnfParseError`, which names the offending line.
+    /// ```
+    /// use std::io::Cursor;
+    /// use rsdd::repr::Cnf;
+    ///
+    /// let input = "c a comment\np cnf 3 2\n1 -2\n 3 0 -1 0\n";
+    /// let cnf = Cnf::from_dimacs_reader(Cursor::new(input)).unwrap();
+    /// assert_eq!(cnf, Cnf::from_dimacs(input));
+    /// ```
+    pub fn from_dimacs_reader<R: BufRead>(reader: R) -> io::Result<Cnf> {
+        let invalid = |e: CnfParseError| io::Error::new(io::ErrorKind::InvalidData, e);
+        let mut parser = DimacsParser::default();
+        for (idx, line) in reader.lines().enumerate() {
+            parser.feed(idx + 1, &line?).map_err(invalid)?;
+        }
+        parser.finish().map_err(invalid)
+    }
+
     /// Parses a CNF string into a CNF
     ///
     /// Format: (-1 || 0 || 2) && (1)
@@ -495,18 +648,17 @@ impl Cnf {
     }
 
     /// heuristically generate a variable ordering which minimizes the average
-    /// clause span
+    /// clause span, using the FORCE heuristic of Aloul et al.: each round
+    /// moves every variable to the average center of gravity of the clauses
+    /// that mention it and re-sorts, for up to `FORCE_ROUNDS` rounds or until
+    /// the span stops improving. Returns the best order seen.
     pub fn force_order(&self) -> VarOrder {
-        // map from position -> label (i.e., first element is the first in the
-        // order)
+        const FORCE_ROUNDS: usize = 20;
+        // map from label -> position in the order
         let mut lbl_to_pos: Vec<usize> = (0..(self.num_vars)).collect();
-        // let mut rng = rand::thread_rng();
-        // lbl_to_pos.shuffle(&mut rng);
-        // perform 100 iterations of force-update
-        let mut cur_span: f64 = self.average_span(&lbl_to_pos);
-        let mut prev_span;
-        loop {
-            prev_span = cur_span;
+        let mut best_span: f64 = self.average_span(&lbl_to_pos);
+        let mut best = lbl_to_pos.clone();
+        for _ in 0..FORCE_ROUNDS {
             let mut cog: Vec<f64> = Vec::with_capacity(self.clauses.len());
             for clause in self.clauses.iter() {
                 cog.push(self.center_of_gravity(clause, &lbl_to_pos));
@@ -514,40 +666,49 @@ impl Cnf {
             // compute average centers of gravity for each variable
             // a vector which holds (1) the running CoG, (2) the number of clauses
             // which contain a given variable
-            let mut update: Vec<(f64, usize)> = Vec::with_capacity(self.num_vars);
-            for _ in 0..self.num_vars {
-                update.push((0.0, 0));
-            }
+            let mut update: Vec<(f64, usize)> = vec![(0.0, 0); self.num_vars];
             for (idx, clause) in self.clauses.iter().enumerate() {
                 for &lit in clause.iter() {
                     let (cur_total, num_edges) = update[lit.label().value() as usize];
                     update[lit.label().value() as usize] = (cur_total + cog[idx], num_edges + 1);
                 }
             }
-            let avg_cog: Vec<f64> = update
+            // variables in no clause stay where they are
+            let mut avg_cog: Vec<(f64, usize)> = update
                 .into_iter()
-                .map(|(total, cnt)| if cnt == 0 { 0.0 } else { total / (cnt as f64) })
+                .enumerate()
+                .map(|(lbl, (total, cnt))| {
+                    let c = if cnt == 0 {
+                        lbl_to_pos[lbl] as f64
+                    } else {
+                        total / (cnt as f64)
+                    };
+                    (c, lbl)
+                })
                 .collect();
-
-            let l = avg_cog.len();
-            let mut avg_cog: Vec<(f64, usize)> = avg_cog.into_iter().zip(0..l).collect();
-            // now sort avg_cog on the centers of gravity
-            avg_cog.sort_by(|(c1, _), (c2, _)| c1.partial_cmp(c2).unwrap());
+            // now sort on the centers of gravity, breaking ties by the
+            // current position
+            avg_cog.sort_by(|(c1, l1), (c2, l2)| {
+                c1.partial_cmp(c2)
+                    .unwrap()
+                    .then(lbl_to_pos[*l1].cmp(&lbl_to_pos[*l2]))
+            });
             // update positions
-            let pos_to_lbl: Vec<usize> = avg_cog.into_iter().map(|(_, p)| p).collect();
-            // now convert to lbl_to_pos
-            for (idx, lbl) in pos_to_lbl.into_iter().enumerate() {
+            for (idx, (_, lbl)) in avg_cog.into_iter().enumerate() {
                 lbl_to_pos[lbl] = idx;
             }
-            cur_span = self.average_span(&lbl_to_pos);
-            if prev_span - cur_span < 1.0 {
+            let cur_span = self.average_span(&lbl_to_pos);
+            if cur_span >= best_span {
                 break;
             }
+            best_span = cur_span;
+            best.clone_from(&lbl_to_pos);
+        }
+        // invert into the list of labels from first to last
+        let mut final_order = vec![VarLabel::new(0); self.num_vars];
+        for (lbl, pos) in best.into_iter().enumerate() {
+            final_order[pos] = VarLabel::new_usize(lbl);
         }
-        let final_order: Vec<VarLabel> = lbl_to_pos
-            .into_iter()
-            .map(|v| VarLabel::new(v as u64))
-            .collect();
         VarOrder::new(&final_order)
     }
 
@@ -730,3 +891,75 @@ fn test_cnf_wmc() {
     ]);
     assert_eq!(cnf.wmc(&WmcParams::new(weights)), FiniteField::new(3));
 }
+
+#[test]
+fn test_from_dimacs_reader_eq_from_dimacs() {
+    use std::io::Cursor;
+
+    let input = "c header comment
+p cnf 6 5
+1 -2 3 0
+c a comment between clauses
+-1 4
+0 2 -3
+-4 0 5 6 -1 0
+-6 0
+";
+    let streamed = Cnf::from_dimacs_reader(Cursor::new(input)).unwrap();
+    assert_eq!(streamed, Cnf::from_dimacs(input));
+    assert_eq!(streamed.clauses().len(), 5);
+
+    let err = Cnf::from_dimacs_reader(Cursor::new("p cnf 2 1\n1 x 0\n")).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(err.to_string().starts_with("line 2:"), "{}", err);
+    assert!(Cnf::from_dimacs_reader(Cursor::new("1 2 0\n")).is_err());
+}
+
+#[test]
+fn test_try_from_dimacs_ok() {
+    let input = "c comment\np cnf 4 3\n1 -2 0\n-3\n4 0 2 0\n";
+    let cnf = Cnf::try_from_dimacs(input).unwrap();
+    assert_eq!(cnf, Cnf::from_dimacs(input));
+}
+
+#[test]
+fn test_try_from_dimacs_missing_header() {
+    assert_eq!(
+        Cnf::try_from_dimacs("c no header\n1 2 0\n"),
+        Err(CnfParseError::MissingHeader { line: 2 })
+    );
+    assert_eq!(
+        Cnf::try_from_dimacs("c only comments\n"),
+        Err(CnfParseError::MissingHeader { line: 1 })
+    );
+}
+
+#[test]
+fn test_try_from_dimacs_var_out_of_range() {
+    assert_eq!(
+        Cnf::try_from_dimacs("p cnf 3 2\n1 2 0\n-3 -4 0\n"),
+        Err(CnfParseError::VarOutOfRange {
+            line: 3,
+            var: 4,
+            num_vars: 3
+        })
+    );
+}
+
+#[test]
+fn test_try_from_dimacs_invalid_token() {
+    assert_eq!(
+        Cnf::try_from_dimacs("p cnf 2 1\n\n1 two 0\n"),
+        Err(CnfParseError::InvalidToken {
+            line: 3,
+            token: String::from("two")
+        })
+    );
+    assert_eq!(
+        Cnf::try_from_dimacs("p cnf x 1\n1 0\n"),
+        Err(CnfParseError::InvalidToken {
+            line: 1,
+            token: String::from("x")
+        })
+    );
+}
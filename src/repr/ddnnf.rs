@@ -45,6 +45,7 @@ pub fn create_semantic_hash_map<const P: u128>(num_vars: usize) -> WmcParams<Fin
 }
 
 /// A base d-DNNF type
+#[derive(Debug, Clone, PartialEq)]
 pub enum DDNNF<T> {
     /// contains the cached values for the children, and the VarSet that
     /// contains the set of decision variables that this Or node was made with
@@ -55,6 +56,32 @@ pub enum DDNNF<T> {
     False,
 }
 
+/// Negates a d-DNNF node by De Morgan's laws, using `negate_child` to negate
+/// its children; a recursive `negate_child` that calls back into
+/// `negate_ddnnf` negates a whole tree structurally.
+///
+/// The result is in negation normal form, but is generally neither
+/// decomposable nor deterministic: an `And` becomes an `Or` whose children may
+/// share models, so the `Or` nodes created here carry an empty decision set.
+/// Negating twice therefore restores every node except the decision sets.
+/// ```
+/// use rsdd::repr::{negate_ddnnf, VarLabel, VarSet, DDNNF};
+///
+/// let x = VarLabel::new(0);
+/// let or: DDNNF<DDNNF<()>> = DDNNF::Or(DDNNF::Lit(x, true), DDNNF::False, VarSet::new());
+/// let negated = negate_ddnnf(or, &|c: DDNNF<()>| negate_ddnnf(c, &|()| ()));
+/// assert_eq!(negated, DDNNF::And(DDNNF::Lit(x, false), DDNNF::True));
+/// ```
+pub fn negate_ddnnf<T>(node: DDNNF<T>, negate_child: &impl Fn(T) -> T) -> DDNNF<T> {
+    match node {
+        DDNNF::Or(l, r, _) => DDNNF::And(negate_child(l), negate_child(r)),
+        DDNNF::And(l, r) => DDNNF::Or(negate_child(l), negate_child(r), VarSet::new()),
+        DDNNF::Lit(v, polarity) => DDNNF::Lit(v, !polarity),
+        DDNNF::True => DDNNF::False,
+        DDNNF::False => DDNNF::True,
+    }
+}
+
 pub trait DDNNFPtr<'a>: Clone + Debug + PartialEq + Eq + Hash + Copy {
     /// performs a memoized bottom-up pass with aggregating function `f` calls
     fn fold<T: Semiring, F: Fn(DDNNF<T>) -> T>(&self, f: F) -> T
@@ -118,6 +145,51 @@ pub trait DDNNFPtr<'a>: Clone + Debug + PartialEq + Eq + Hash + Copy {
     /// True if `self` is a negated pointer, false otherwise
     fn is_neg(&self) -> bool;
 
-    /// count the number of nodes in this representation
+    /// count the number of (non-terminal) nodes in this representation
     fn count_nodes(&self) -> usize;
 }
+
+#[test]
+fn negate_ddnnf_de_morgan() {
+    #[derive(Debug, Clone, PartialEq)]
+    struct Tree(DDNNF<Box<Tree>>);
+
+    fn lit(v: u64, polarity: bool) -> Box<Tree> {
+        Box::new(Tree(DDNNF::Lit(VarLabel::new(v), polarity)))
+    }
+
+    fn neg(t: Tree) -> Tree {
+        Tree(negate_ddnnf(t.0, &|c: Box<Tree>| Box::new(neg(*c))))
+    }
+
+    fn eval(t: &Tree, assgn: &[bool]) -> bool {
+        match &t.0 {
+            DDNNF::Or(l, r, _) => eval(l, assgn) || eval(r, assgn),
+            DDNNF::And(l, r) => eval(l, assgn) && eval(r, assgn),
+            DDNNF::Lit(v, polarity) => assgn[v.value_usize()] == *polarity,
+            DDNNF::True => true,
+            DDNNF::False => false,
+        }
+    }
+
+    // (x0 & (x1 | false)) | (!x0 & !x2)
+    let t = Tree(DDNNF::Or(
+        Box::new(Tree(DDNNF::And(
+            lit(0, true),
+            Box::new(Tree(DDNNF::Or(
+                lit(1, true),
+                Box::new(Tree(DDNNF::False)),
+                VarSet::new(),
+            ))),
+        ))),
+        Box::new(Tree(DDNNF::And(lit(0, false), lit(2, false)))),
+        VarSet::new(),
+    ));
+    let negated = neg(t.clone());
+    for i in 0..8usize {
+        let assgn = [0, 1, 2].map(|v| i & (1 << v) != 0);
+        assert_eq!(eval(&negated, &assgn), !eval(&t, &assgn));
+    }
+    let twice = neg(negated);
+    assert_eq!(twice, t);
+}
@@ -0,0 +1,407 @@
+//! A Boolean expression DAG whose subexpressions may be shared
+
+use crate::repr::{Cnf, Literal, VarLabel};
+use std::{collections::HashMap, fmt, rc::Rc};
+
+/// Describes why `Expr::parse` rejected its input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprParseError {
+    /// the input ended where an operand or a closing parenthesis was expected
+    UnexpectedEnd,
+    /// the token starting at byte offset `pos` does not fit the grammar
+    UnexpectedToken { pos: usize, token: String },
+}
+
+impl fmt::Display for ExprParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprParseError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            ExprParseError::UnexpectedToken { pos, token } => {
+                write!(f, "unexpected `{}` at offset {}", token, pos)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExprParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    Not,
+    Minus,
+    And,
+    Or,
+    Xor,
+    True,
+    False,
+    Var(u64),
+}
+
+/// splits `s` into tokens, each paired with its byte offset
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, ExprParseError> {
+    let mut tokens = Vec::new();
+    let mut rest = s.char_indices().peekable();
+    while let Some((pos, c)) = rest.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '!' => Token::Not,
+            '-' => Token::Minus,
+            '^' => Token::Xor,
+            '&' | '|' if rest.peek().map(|&(_, n)| n) == Some(c) => {
+                rest.next();
+                if c == '&' {
+                    Token::And
+                } else {
+                    Token::Or
+                }
+            }
+            c if c.is_ascii_alphanumeric() => {
+                let mut end = pos + c.len_utf8();
+                while let Some(&(i, n)) = rest.peek() {
+                    if !n.is_ascii_alphanumeric() {
+                        break;
+                    }
+                    end = i + n.len_utf8();
+                    rest.next();
+                }
+                match &s[pos..end] {
+                    "true" => Token::True,
+                    "false" => Token::False,
+                    word => {
+                        Token::Var(word.parse().map_err(|_| ExprParseError::UnexpectedToken {
+                            pos,
+                            token: word.to_string(),
+                        })?)
+                    }
+                }
+            }
+            c => {
+                return Err(ExprParseError::UnexpectedToken {
+                    pos,
+                    token: c.to_string(),
+                })
+            }
+        };
+        tokens.push((pos, token));
+    }
+    Ok(tokens)
+}
+
+/// a recursive-descent parser over the output of `tokenize`, with one
+/// method per precedence level
+struct Parser<'s> {
+    src: &'s str,
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, t)| t)
+    }
+
+    fn unexpected(&self) -> ExprParseError {
+        match self.tokens.get(self.next) {
+            None => ExprParseError::UnexpectedEnd,
+            Some((pos, _)) => {
+                let end = self
+                    .tokens
+                    .get(self.next + 1)
+                    .map_or(self.src.len(), |(p, _)| *p);
+                ExprParseError::UnexpectedToken {
+                    pos: *pos,
+                    token: self.src[*pos..end].trim().to_string(),
+                }
+            }
+        }
+    }
+
+    /// parses operands of the next-tighter level separated by `op`, folding
+    /// them to the left with `combine`
+    fn binary(
+        &mut self,
+        op: Token,
+        operand: fn(&mut Self) -> Result<Expr, ExprParseError>,
+        combine: fn(Rc<Expr>, Rc<Expr>) -> Expr,
+    ) -> Result<Expr, ExprParseError> {
+        let mut e = operand(self)?;
+        while self.peek() == Some(&op) {
+            self.next += 1;
+            e = combine(Rc::new(e), Rc::new(operand(self)?));
+        }
+        Ok(e)
+    }
+
+    fn or(&mut self) -> Result<Expr, ExprParseError> {
+        self.binary(Token::Or, Parser::xor, Expr::Or)
+    }
+
+    fn xor(&mut self) -> Result<Expr, ExprParseError> {
+        self.binary(Token::Xor, Parser::and, Expr::Xor)
+    }
+
+    fn and(&mut self) -> Result<Expr, ExprParseError> {
+        self.binary(Token::And, Parser::unary, Expr::And)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExprParseError> {
+        let token = self.peek().cloned().ok_or(ExprParseError::UnexpectedEnd)?;
+        let e = match token {
+            Token::Not => {
+                self.next += 1;
+                return Ok(Expr::Not(Rc::new(self.unary()?)));
+            }
+            Token::Minus => {
+                self.next += 1;
+                match self.peek() {
+                    Some(Token::Var(v)) => Expr::Not(Rc::new(Expr::Var(VarLabel::new(*v)))),
+                    _ => return Err(self.unexpected()),
+                }
+            }
+            Token::LParen => {
+                self.next += 1;
+                let e = self.or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(self.unexpected());
+                }
+                e
+            }
+            Token::True => Expr::True,
+            Token::False => Expr::False,
+            Token::Var(v) => Expr::Var(VarLabel::new(v)),
+            _ => return Err(self.unexpected()),
+        };
+        self.next += 1;
+        Ok(e)
+    }
+}
+
+/// A nested Boolean expression. Unlike `LogicalExpr`, children are
+/// reference-counted so that a subexpression can be shared between several
+/// parents; compilers memoize on the identity of each shared node, so a
+/// subexpression referenced many times is compiled once.
+/// ```
+/// use std::rc::Rc;
+/// use rsdd::repr::{Expr, VarLabel};
+///
+/// let a = Rc::new(Expr::Var(VarLabel::new(0)));
+/// let b = Rc::new(Expr::Var(VarLabel::new(1)));
+/// // a xor b, shared by both sides of the or
+/// let x = Rc::new(Expr::Xor(a.clone(), b));
+/// let e = Expr::Or(x.clone(), Rc::new(Expr::Not(x)));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    True,
+    False,
+    Var(VarLabel),
+    Not(Rc<Expr>),
+    And(Rc<Expr>, Rc<Expr>),
+    Or(Rc<Expr>, Rc<Expr>),
+    Xor(Rc<Expr>, Rc<Expr>),
+}
+
+impl Expr {
+    /// Parses a Boolean formula, such as `(1 ^ 2) && !(3 || -0)`. The
+    /// grammar, from loosest- to tightest-binding, is
+    ///
+    /// ```text
+    /// or    := xor ("||" xor)*
+    /// xor   := and ("^" and)*
+    /// and   := unary ("&&" unary)*
+    /// unary := "!" unary | "-" var | "(" or ")" | "true" | "false" | var
+    /// var   := [0-9]+
+    /// ```
+    ///
+    /// where each binary operator associates to the left and whitespace is
+    /// ignored. An integer `n` is the variable with label `n`, and `-n` its
+    /// negation; unlike `Cnf::from_string`, `0` is an ordinary variable, so
+    /// `-0` is its negation.
+    /// ```
+    /// use std::rc::Rc;
+    /// use rsdd::repr::{Expr, VarLabel};
+    ///
+    /// let e = Expr::parse("1 ^ !true").unwrap();
+    /// let one = Rc::new(Expr::Var(VarLabel::new(1)));
+    /// let not_true = Rc::new(Expr::Not(Rc::new(Expr::True)));
+    /// assert_eq!(e, Expr::Xor(one, not_true));
+    /// assert!(Expr::parse("(1 && 2").is_err());
+    /// ```
+    pub fn parse(s: &str) -> Result<Expr, ExprParseError> {
+        let mut parser = Parser {
+            src: s,
+            tokens: tokenize(s)?,
+            next: 0,
+        };
+        let e = parser.or()?;
+        if parser.next < parser.tokens.len() {
+            return Err(parser.unexpected());
+        }
+        Ok(e)
+    }
+
+    /// the largest variable label mentioned in the expression, if any
+    fn max_var(&self) -> Option<u64> {
+        fn max_var_h(e: &Expr, seen: &mut HashMap<*const Expr, Option<u64>>) -> Option<u64> {
+            let key = e as *const Expr;
+            if let Some(r) = seen.get(&key) {
+                return *r;
+            }
+            let r = match e {
+                Expr::True | Expr::False => None,
+                Expr::Var(v) => Some(v.value()),
+                Expr::Not(e) => max_var_h(e, seen),
+                Expr::And(l, r) | Expr::Or(l, r) | Expr::Xor(l, r) => {
+                    max_var_h(l, seen).max(max_var_h(r, seen))
+                }
+            };
+            seen.insert(key, r);
+            r
+        }
+        max_var_h(self, &mut HashMap::new())
+    }
+
+    /// Converts the expression into an equisatisfiable CNF via the Tseitin
+    /// transformation, returning the CNF and the variable standing for the
+    /// whole expression (which the CNF asserts true).
+    ///
+    /// Each And, Or, Xor and constant node gets a fresh auxiliary variable,
+    /// numbered consecutively from one above the expression's largest
+    /// variable; negation is folded into literals and needs no variable.
+    /// Subexpressions shared through an `Rc` are encoded once. Projecting
+    /// the auxiliary variables out of the CNF yields the original expression.
+    /// ```
+    /// use std::rc::Rc;
+    /// use rsdd::repr::{Expr, VarLabel};
+    ///
+    /// let a = Rc::new(Expr::Var(VarLabel::new(0)));
+    /// let b = Rc::new(Expr::Var(VarLabel::new(1)));
+    /// let (cnf, out) = Expr::And(a, b).to_cnf_tseitin();
+    /// assert_eq!(out, VarLabel::new(2));
+    /// // three clauses defining `out`, plus the unit clause asserting it
+    /// assert_eq!(cnf.clauses().len(), 4);
+    /// ```
+    pub fn to_cnf_tseitin(&self) -> (Cnf, VarLabel) {
+        struct Tseitin {
+            next_var: u64,
+            clauses: Vec<Vec<Literal>>,
+            cache: HashMap<*const Expr, Literal>,
+        }
+
+        impl Tseitin {
+            fn fresh(&mut self) -> Literal {
+                let lit = Literal::new(VarLabel::new(self.next_var), true);
+                self.next_var += 1;
+                lit
+            }
+
+            fn encode(&mut self, e: &Expr) -> Literal {
+                let key = e as *const Expr;
+                if let Some(r) = self.cache.get(&key) {
+                    return *r;
+                }
+                let r = match e {
+                    Expr::True | Expr::False => {
+                        let z = self.fresh();
+                        let unit = if matches!(e, Expr::True) {
+                            z
+                        } else {
+                            z.negated()
+                        };
+                        self.clauses.push(vec![unit]);
+                        z
+                    }
+                    Expr::Var(v) => Literal::new(*v, true),
+                    Expr::Not(e) => self.encode(e).negated(),
+                    Expr::And(l, r) => {
+                        let (l, r) = (self.encode(l), self.encode(r));
+                        let z = self.fresh();
+                        self.clauses.push(vec![z.negated(), l]);
+                        self.clauses.push(vec![z.negated(), r]);
+                        self.clauses.push(vec![z, l.negated(), r.negated()]);
+                        z
+                    }
+                    Expr::Or(l, r) => {
+                        let (l, r) = (self.encode(l), self.encode(r));
+                        let z = self.fresh();
+                        self.clauses.push(vec![z, l.negated()]);
+                        self.clauses.push(vec![z, r.negated()]);
+                        self.clauses.push(vec![z.negated(), l, r]);
+                        z
+                    }
+                    Expr::Xor(l, r) => {
+                        let (l, r) = (self.encode(l), self.encode(r));
+                        let z = self.fresh();
+                        self.clauses.push(vec![z.negated(), l, r]);
+                        self.clauses
+                            .push(vec![z.negated(), l.negated(), r.negated()]);
+                        self.clauses.push(vec![z, l.negated(), r]);
+                        self.clauses.push(vec![z, l, r.negated()]);
+                        z
+                    }
+                };
+                self.cache.insert(key, r);
+                r
+            }
+        }
+
+        let mut t = Tseitin {
+            next_var: self.max_var().map_or(0, |v| v + 1),
+            clauses: Vec::new(),
+            cache: HashMap::new(),
+        };
+        let mut out = t.encode(self);
+        if !out.polarity() {
+            // the root is a negation; name it with a variable of its own
+            let z = t.fresh();
+            t.clauses.push(vec![z.negated(), out]);
+            t.clauses.push(vec![z, out.negated()]);
+            out = z;
+        }
+        t.clauses.push(vec![out]);
+        (Cnf::new(&t.clauses), out.label())
+    }
+}
+
+#[test]
+fn parse_respects_precedence_and_reports_errors() {
+    let var = |v: u64| Rc::new(Expr::Var(VarLabel::new(v)));
+    let not = |e: Rc<Expr>| Rc::new(Expr::Not(e));
+
+    // `&&` binds tighter than `^`, which binds tighter than `||`
+    assert_eq!(
+        Expr::parse("0 || 1 ^ 2 && -3").unwrap(),
+        Expr::Or(
+            var(0),
+            Rc::new(Expr::Xor(var(1), Rc::new(Expr::And(var(2), not(var(3)))))),
+        )
+    );
+    // operators associate to the left, and `-0` negates variable 0
+    assert_eq!(
+        Expr::parse("(-0 ^ 1 ^ false)").unwrap(),
+        Expr::Xor(
+            Rc::new(Expr::Xor(not(var(0)), var(1))),
+            Rc::new(Expr::False)
+        )
+    );
+    assert_eq!(
+        Expr::parse("!!(true)").unwrap(),
+        Expr::Not(not(Rc::new(Expr::True)))
+    );
+
+    let unexpected = |pos, token: &str| ExprParseError::UnexpectedToken {
+        pos,
+        token: token.to_string(),
+    };
+    assert_eq!(Expr::parse(""), Err(ExprParseError::UnexpectedEnd));
+    assert_eq!(Expr::parse("1 &&"), Err(ExprParseError::UnexpectedEnd));
+    assert_eq!(Expr::parse("(1 || 2"), Err(ExprParseError::UnexpectedEnd));
+    assert_eq!(Expr::parse("1 2"), Err(unexpected(2, "2")));
+    assert_eq!(Expr::parse("1 & 2"), Err(unexpected(2, "&")));
+    assert_eq!(Expr::parse("-true"), Err(unexpected(1, "true")));
+    assert_eq!(Expr::parse("1 || maybe"), Err(unexpected(5, "maybe")));
+}
@@ -4,10 +4,12 @@
 //!
 //! (i.e., conjunctive normal forms, arbitrary logical formulae, etc.)
 
+mod aig;
 mod bdd;
 mod cnf;
 mod ddnnf;
 mod dtree;
+mod expr;
 mod logical_expr;
 mod model;
 mod sdd;
@@ -17,10 +19,12 @@ mod var_order;
 mod vtree;
 mod wmc;
 
+pub use self::aig::*;
 pub use self::bdd::*;
 pub use self::cnf::*;
 pub use self::ddnnf::*;
 pub use self::dtree::*;
+pub use self::expr::*;
 pub use self::logical_expr::*;
 pub use self::model::*;
 pub use self::sdd::*;
@@ -2,8 +2,12 @@
 //! in the order occur first in the BDD, starting from the root.
 //! Lower numbers occur first in the order (i.e., closer to the root)
 
-use crate::repr::VarLabel;
-use std::fmt::{Debug, Display};
+use crate::repr::{Cnf, VarLabel};
+use petgraph::visit::Dfs;
+use std::{
+    cmp::{max, min},
+    fmt::{Debug, Display},
+};
 
 #[derive(Debug, Clone)]
 pub struct VarOrder {
@@ -42,6 +46,46 @@ impl VarOrder {
         VarOrder::new(&v)
     }
 
+    /// Generate a static variable ordering for `cnf` with the FORCE
+    /// heuristic, which places variables that share clauses close together;
+    /// see `Cnf::force_order`.
+    /// ```
+    /// # use rsdd::repr::{Cnf, VarLabel, VarOrder};
+    /// // a chain 0 - 2 - 1 - 3 whose labels are out of order
+    /// let cnf = Cnf::from_dimacs("p cnf 4 3\n1 3 0\n3 2 0\n2 4 0\n");
+    /// let o = VarOrder::force_order(&cnf);
+    /// // neighbors in the chain end up adjacent in the order
+    /// for (a, b) in [(0, 2), (2, 1), (1, 3)] {
+    ///     let (pa, pb) = (o.get(VarLabel::new(a)), o.get(VarLabel::new(b)));
+    ///     assert_eq!(pa.abs_diff(pb), 1);
+    /// }
+    /// ```
+    pub fn force_order(cnf: &Cnf) -> VarOrder {
+        cnf.force_order()
+    }
+
+    /// Generate a static variable ordering for `cnf` from a depth-first
+    /// traversal of its interaction graph (where two variables are adjacent if
+    /// they occur in a common clause), so that interacting variables tend to
+    /// be close together. Each connected component is traversed in full before
+    /// the next, starting from its lowest label, so independent parts of the
+    /// CNF occupy contiguous blocks of the order.
+    pub fn dfs_order(cnf: &Cnf) -> VarOrder {
+        let g = cnf.interaction_graph();
+        let mut order = Vec::with_capacity(g.node_count());
+        let mut dfs = Dfs::empty(&g);
+        for start in g.node_indices() {
+            if dfs.discovered.contains(start.index()) {
+                continue;
+            }
+            dfs.move_to(start);
+            while let Some(n) = dfs.next(&g) {
+                order.push(g[n]);
+            }
+        }
+        VarOrder::new(&order)
+    }
+
     /// Gives the number of variables in the order
     /// ```
     /// # use rsdd::repr::VarOrder;
@@ -264,6 +308,34 @@ impl VarOrder {
         VarLabel::new(new_var as u64)
     }
 
+    /// Moves `var` to `target_level`, shifting the variables in between up or
+    /// down by one level. This does not touch any BDD built under the old
+    /// order, so it should only be used before nodes are created.
+    /// ```
+    /// # use rsdd::repr::{VarLabel, VarOrder};
+    /// let mut o = VarOrder::linear_order(4);
+    /// o.move_var(VarLabel::new(3), 1);
+    /// assert_eq!(o.get(VarLabel::new(3)), 1);
+    /// assert_eq!(o.get(VarLabel::new(1)), 2);
+    /// ```
+    ///
+    /// Panics if `target_level` is out of bounds for the order.
+    pub fn move_var(&mut self, var: VarLabel, target_level: usize) {
+        assert!(
+            target_level < self.pos_to_var.len(),
+            "target level {} out of bounds for {} variables",
+            target_level,
+            self.pos_to_var.len()
+        );
+        let cur = self.get(var);
+        let v = self.pos_to_var.remove(cur);
+        self.pos_to_var.insert(target_level, v);
+        // only the levels between the old and new position changed
+        for i in min(cur, target_level)..=max(cur, target_level) {
+            self.var_to_pos[self.pos_to_var[i]] = i;
+        }
+    }
+
     /// Returns an iterator of all variables between [low_level..high_level)
     pub fn between_iter(
         &self,
@@ -304,3 +376,35 @@ fn var_order_basics() {
     assert!(!order.lt(lbl2, lbl1));
     assert_eq!(order.above(lbl2).unwrap(), lbl1);
 }
+
+#[test]
+fn dfs_order_keeps_components_contiguous() {
+    // two independent blocks with interleaved labels: {0, 2, 4} and {1, 3, 5}
+    let cnf = Cnf::from_dimacs("p cnf 6 4\n1 3 0\n3 -5 0\n2 -4 0\n4 6 0\n");
+    let order = VarOrder::dfs_order(&cnf);
+    assert_eq!(order.num_vars(), 6);
+    for block in [[0, 2, 4], [1, 3, 5]] {
+        let mut pos = block.map(|v| order.get(VarLabel::new(v)));
+        pos.sort();
+        assert_eq!(pos[2] - pos[0], 2);
+    }
+    // the block holding variable 0 comes first
+    assert_eq!(order.var_at_level(0), VarLabel::new(0));
+}
+
+#[test]
+fn move_var_keeps_order_consistent() {
+    let mut order = VarOrder::linear_order(6);
+    order.move_var(VarLabel::new(1), 4);
+    order.move_var(VarLabel::new(5), 0);
+    order.move_var(VarLabel::new(2), 2);
+    let levels: Vec<u64> = order.in_order_iter().map(|v| v.value()).collect();
+    assert_eq!(levels, vec![5, 0, 2, 3, 4, 1]);
+    for level in 0..order.num_vars() {
+        assert_eq!(order.get(order.var_at_level(level)), level);
+    }
+    for v in 0..6 {
+        let v = VarLabel::new(v);
+        assert_eq!(order.var_at_level(order.get(v)), v);
+    }
+}
@@ -1,9 +1,9 @@
 use crate::{
     repr::{Literal, VarLabel},
-    util::semirings::Semiring,
+    util::semirings::{Semiring, StableHash},
 };
 use core::fmt::Debug;
-use std::collections::HashMap;
+use std::{collections::HashMap, hash::Hasher};
 /// Weighted model counting parameters for a BDD. It primarily is a storage for
 /// the weight on each variable.
 #[repr(C)]
@@ -14,6 +14,8 @@ pub struct WmcParams<T: Semiring> {
     /// a vector which maps variable labels to `(low, high)`
     /// valuations.
     var_to_val: Vec<Option<(T, T)>>,
+    /// the `(low, high)` valuation of any variable without its own weight
+    default: Option<(T, T)>,
 }
 
 impl<T: Semiring> WmcParams<T> {
@@ -40,14 +42,67 @@ impl<T: Semiring> WmcParams<T> {
     /// assert_eq!(params.assignment_weight(&all_true).0, 0.7)
     /// ```
     pub fn new(var_to_val: HashMap<VarLabel, (T, T)>) -> WmcParams<T> {
-        let mut var_to_val_vec: Vec<Option<(T, T)>> = vec![None; var_to_val.len()];
+        WmcParams::with_zero_one(T::zero(), T::one(), var_to_val)
+    }
+
+    /// Like `new`, but with explicit values for the false and true terminals,
+    /// for semirings whose `Semiring::zero()` and `Semiring::one()` lack
+    /// information the count should carry (e.g., a `DynFiniteField` modulus).
+    /// ```
+    /// use rsdd::repr::{VarLabel, WmcParams};
+    /// use rsdd::util::semirings::DynFiniteField;
+    /// use std::collections::HashMap;
+    ///
+    /// let weights = HashMap::from([
+    ///     (VarLabel::new(0), (DynFiniteField::new(1, 7), DynFiniteField::new(1, 7))),
+    /// ]);
+    /// let params = WmcParams::with_zero_one(
+    ///     DynFiniteField::new(0, 7),
+    ///     DynFiniteField::new(1, 7),
+    ///     weights,
+    /// );
+    ///
+    /// assert_eq!(params.one.modulus, 7);
+    /// ```
+    pub fn with_zero_one(zero: T, one: T, var_to_val: HashMap<VarLabel, (T, T)>) -> WmcParams<T> {
+        // labels need not be dense, so size the vector by the largest one
+        let len = var_to_val
+            .keys()
+            .map(|v| v.value_usize() + 1)
+            .max()
+            .unwrap_or(0);
+        let mut var_to_val_vec: Vec<Option<(T, T)>> = vec![None; len];
         for (key, value) in var_to_val.iter() {
             var_to_val_vec[key.value_usize()] = Some(*value);
         }
+        WmcParams {
+            zero,
+            one,
+            var_to_val: var_to_val_vec,
+            default: None,
+        }
+    }
+
+    /// Parametrize a weighted model count with dense weights: `weights[i]`
+    /// is the `(negative, positive)` weight of `VarLabel(i)`, matching the
+    /// order in which DIMACS weight lines list variables.
+    /// ```
+    /// use rsdd::repr::{VarLabel, WmcParams};
+    /// use rsdd::util::semirings::RealSemiring;
+    ///
+    /// let params = WmcParams::from_slice(&[
+    ///     (RealSemiring(0.0), RealSemiring(1.0)),
+    ///     (RealSemiring(0.3), RealSemiring(0.7)),
+    /// ]);
+    ///
+    /// assert_eq!(*params.var_weight(VarLabel::new(1)), (RealSemiring(0.3), RealSemiring(0.7)))
+    /// ```
+    pub fn from_slice(weights: &[(T, T)]) -> WmcParams<T> {
         WmcParams {
             zero: T::zero(),
             one: T::one(),
-            var_to_val: var_to_val_vec,
+            var_to_val: weights.iter().map(|w| Some(*w)).collect(),
+            default: None,
         }
     }
 
@@ -76,9 +131,9 @@ impl<T: Semiring> WmcParams<T> {
         let mut prod = self.one;
         for lit in assgn.iter() {
             if lit.polarity() {
-                prod = prod * self.var_to_val[lit.label().value_usize()].unwrap().1
+                prod = prod * self.var_weight(lit.label()).1
             } else {
-                prod = prod * self.var_to_val[lit.label().value_usize()].unwrap().0
+                prod = prod * self.var_weight(lit.label()).0
             }
         }
         prod
@@ -130,9 +185,114 @@ impl<T: Semiring> WmcParams<T> {
     ///
     /// assert_eq!(*params.var_weight(VarLabel::new(1)), (RealSemiring(0.3), RealSemiring(0.7)))
     /// ```
-    // gives you the weight of `(low, high)` literals for a given VarLabel
+    // gives you the weight of `(low, high)` literals for a given VarLabel,
+    // falling back to the default weight if it has none
     pub fn var_weight(&self, label: VarLabel) -> &(T, T) {
-        return (self.var_to_val[label.value_usize()]).as_ref().unwrap();
+        self.var_to_val
+            .get(label.value_usize())
+            .and_then(|w| w.as_ref())
+            .or(self.default.as_ref())
+            .unwrap()
+    }
+
+    /// Sets the `(low, high)` weight used for every variable that has no
+    /// weight of its own.
+    /// ```
+    /// use rsdd::repr::{VarLabel, WmcParams};
+    /// use rsdd::util::semirings::RealSemiring;
+    ///
+    /// let mut params = WmcParams::<RealSemiring>::default();
+    /// params.set_weight(VarLabel::new(0), RealSemiring(0.3), RealSemiring(0.7));
+    /// params.set_default(RealSemiring(1.0), RealSemiring(1.0));
+    ///
+    /// assert_eq!(*params.var_weight(VarLabel::new(0)), (RealSemiring(0.3), RealSemiring(0.7)));
+    /// assert_eq!(*params.var_weight(VarLabel::new(5)), (RealSemiring(1.0), RealSemiring(1.0)));
+    /// ```
+    pub fn set_default(&mut self, low: T, high: T) {
+        self.default = Some((low, high));
+    }
+}
+
+impl<T: Semiring + StableHash> WmcParams<T> {
+    /// A hash of every weight (including the default and the terminal
+    /// values), visited in label order, that is the same across runs and
+    /// platforms; useful for memoizing results keyed by `(bdd, weights)`.
+    ///
+    /// Float weights are hashed by their bit patterns (see `StableHash`):
+    /// weights computed in different ways may differ in their last bits and
+    /// hash differently even though they print the same.
+    /// ```
+    /// use rsdd::repr::{VarLabel, WmcParams};
+    /// use rsdd::util::semirings::RealSemiring;
+    ///
+    /// let a = WmcParams::from_slice(&[(RealSemiring(0.3), RealSemiring(0.7))]);
+    /// let mut b = WmcParams::default();
+    /// b.set_weight(VarLabel::new(0), RealSemiring(0.3), RealSemiring(0.7));
+    /// assert_eq!(a.stable_hash(), b.stable_hash());
+    /// ```
+    pub fn stable_hash(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        self.zero.stable_hash(&mut hasher);
+        self.one.stable_hash(&mut hasher);
+        for (label, weight) in self.var_to_val.iter().enumerate() {
+            if let Some((low, high)) = weight {
+                hasher.write_u64(label as u64);
+                low.stable_hash(&mut hasher);
+                high.stable_hash(&mut hasher);
+            }
+        }
+        if let Some((low, high)) = &self.default {
+            hasher.write_u8(1);
+            low.stable_hash(&mut hasher);
+            high.stable_hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+/// 64-bit FNV-1a, with integers fed as little-endian bytes (and `usize` widened
+/// to 64 bits), so that its output does not depend on the platform
+struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.write(&[i]);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
     }
 }
 
@@ -156,6 +316,7 @@ impl<T: Semiring> Debug for WmcParams<T> {
                     })
                     .collect::<Vec<String>>(),
             )
+            .field("default", &self.default)
             .finish()
     }
 }
@@ -176,6 +337,97 @@ impl<T: Semiring> Default for WmcParams<T> {
             zero: T::zero(),
             one: T::one(),
             var_to_val: Vec::new(),
+            default: None,
         }
     }
 }
+
+#[test]
+fn from_slice_matches_hashmap_constructor() {
+    use crate::util::semirings::RealSemiring;
+
+    let weights: Vec<(RealSemiring, RealSemiring)> = (0..5)
+        .map(|i| {
+            let p = (i as f64 + 1.0) / 10.0;
+            (RealSemiring(1.0 - p), RealSemiring(p))
+        })
+        .collect();
+    let from_map = WmcParams::new(HashMap::from_iter(
+        weights
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (VarLabel::new_usize(i), *w)),
+    ));
+    let from_slice = WmcParams::from_slice(&weights);
+    assert_eq!(from_slice.var_to_val, from_map.var_to_val);
+    assert_eq!(from_slice.default, from_map.default);
+}
+
+#[test]
+fn new_accepts_sparse_labels() {
+    use crate::util::semirings::RealSemiring;
+
+    let params = WmcParams::new(HashMap::from([
+        (VarLabel::new(2), (RealSemiring(0.4), RealSemiring(0.6))),
+        (VarLabel::new(7), (RealSemiring(0.9), RealSemiring(0.1))),
+    ]));
+    assert_eq!(params.zero, RealSemiring(0.0));
+    assert_eq!(params.one, RealSemiring(1.0));
+    assert_eq!(
+        *params.var_weight(VarLabel::new(7)),
+        (RealSemiring(0.9), RealSemiring(0.1))
+    );
+    assert_eq!(params.var_to_val.len(), 8);
+    assert!(params.var_to_val[0].is_none());
+}
+
+#[test]
+fn with_zero_one_keeps_terminal_values() {
+    use crate::util::semirings::DynFiniteField;
+
+    let w = (DynFiniteField::new(3, 5), DynFiniteField::new(4, 5));
+    let params = WmcParams::with_zero_one(
+        DynFiniteField::new(0, 5),
+        DynFiniteField::new(1, 5),
+        HashMap::from([(VarLabel::new(1), w)]),
+    );
+    assert_eq!(params.zero, DynFiniteField::new(0, 5));
+    assert_eq!(params.one, DynFiniteField::new(1, 5));
+    assert_eq!(*params.var_weight(VarLabel::new(1)), w);
+    // the empty assignment weighs `one`, including its modulus
+    assert_eq!(params.assignment_weight(&[]), DynFiniteField::new(1, 5));
+}
+
+#[test]
+fn stable_hash_follows_weights() {
+    use crate::util::semirings::{FiniteField, RealSemiring};
+
+    let weights = [
+        (RealSemiring(0.2), RealSemiring(0.8)),
+        (RealSemiring(0.5), RealSemiring(0.5)),
+    ];
+    let a = WmcParams::from_slice(&weights);
+    let b = WmcParams::new(HashMap::from([
+        (VarLabel::new(1), weights[1]),
+        (VarLabel::new(0), weights[0]),
+    ]));
+    assert_eq!(a.stable_hash(), b.stable_hash());
+
+    let mut c = WmcParams::from_slice(&weights);
+    c.set_weight(VarLabel::new(1), RealSemiring(0.5), RealSemiring(0.6));
+    assert_ne!(a.stable_hash(), c.stable_hash());
+    // the same weights on different labels
+    let swapped = WmcParams::from_slice(&[weights[1], weights[0]]);
+    assert_ne!(a.stable_hash(), swapped.stable_hash());
+    let mut with_default = WmcParams::from_slice(&weights);
+    with_default.set_default(RealSemiring(1.0), RealSemiring(1.0));
+    assert_ne!(a.stable_hash(), with_default.stable_hash());
+
+    let ff = |v| WmcParams::from_slice(&[(FiniteField::<7>::new(v), FiniteField::<7>::new(1))]);
+    assert_eq!(ff(3).stable_hash(), ff(10).stable_hash());
+    assert_ne!(ff(3).stable_hash(), ff(4).stable_hash());
+
+    // FNV-1a over the little-endian bytes of the zero, the one, and each
+    // label and weight, so it cannot vary with the platform or toolchain
+    assert_eq!(a.stable_hash(), 10754841866817106597);
+}
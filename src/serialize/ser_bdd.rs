@@ -13,18 +13,18 @@ pub enum SerBDDPtr {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SerBDD {
-    topvar: usize,
-    low: SerBDDPtr,
-    high: SerBDDPtr,
+    pub(crate) topvar: usize,
+    pub(crate) low: SerBDDPtr,
+    pub(crate) high: SerBDDPtr,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BDDSerializer {
     /// list of nodes allocated in the BDD
     /// `nodes[0]` is true, `nodes[1]` is false
-    nodes: Vec<SerBDD>,
+    pub(crate) nodes: Vec<SerBDD>,
     /// list of all roots (to support multi-rooted BDD serialization with shared structure)
-    roots: Vec<SerBDDPtr>,
+    pub(crate) roots: Vec<SerBDDPtr>,
 }
 
 impl BDDSerializer {
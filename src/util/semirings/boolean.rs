@@ -1,6 +1,6 @@
-use std::{fmt::Display, ops};
+use std::{fmt::Display, hash::Hasher, ops};
 
-use super::semiring_traits::Semiring;
+use super::semiring_traits::{Semiring, StableHash};
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct BooleanSemiring(pub bool);
@@ -36,3 +36,9 @@ impl ops::Mul<BooleanSemiring> for BooleanSemiring {
         BooleanSemiring(self.0 && rhs.0)
     }
 }
+
+impl StableHash for BooleanSemiring {
+    fn stable_hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u8(self.0 as u8);
+    }
+}
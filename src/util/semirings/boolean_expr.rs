@@ -0,0 +1,188 @@
+//! A symbolic semiring of Boolean expressions, used for provenance.
+
+use super::semiring_traits::*;
+use std::{cell::RefCell, collections::HashMap, fmt::Display, ops};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ExprNode {
+    False,
+    True,
+    Lit(usize, bool),
+    And(usize, usize),
+    Or(usize, usize),
+}
+
+/// hash-consed storage for expression nodes; index 0 is False and 1 is True.
+/// `generation` counts the calls to `reset_arena`, to catch stale handles
+struct ExprArena {
+    nodes: Vec<ExprNode>,
+    table: HashMap<ExprNode, usize>,
+    generation: u64,
+}
+
+impl ExprArena {
+    fn new(generation: u64) -> ExprArena {
+        ExprArena {
+            nodes: vec![ExprNode::False, ExprNode::True],
+            table: HashMap::from([(ExprNode::False, 0), (ExprNode::True, 1)]),
+            generation,
+        }
+    }
+
+    fn intern(&mut self, node: ExprNode) -> usize {
+        if let Some(idx) = self.table.get(&node) {
+            return *idx;
+        }
+        let idx = self.nodes.len();
+        self.nodes.push(node);
+        self.table.insert(node, idx);
+        idx
+    }
+}
+
+thread_local! {
+    static ARENA: RefCell<ExprArena> = RefCell::new(ExprArena::new(0));
+}
+
+/// A semiring whose elements are Boolean expression trees over literals.
+/// `+` builds an Or node and `*` builds an And node, so a weighted model
+/// count where each literal is weighted by itself (see
+/// `BooleanExprSemiring::lit`) yields the provenance of satisfaction: an
+/// expression recording which variable choices lead to a model.
+///
+/// `Semiring` requires `Copy`, so rather than owning a boxed tree each value
+/// is a handle into a thread-local, hash-consed node arena. Handles are only
+/// meaningful on the thread that created them, and the arena keeps every
+/// expression built on the thread until `reset_arena` frees them all.
+/// Constants are simplified on construction (`0 + x = x`, `1 * x = x`,
+/// `0 * x = 0`, `1 + x = 1`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BooleanExprSemiring {
+    index: usize,
+    generation: u64,
+}
+
+impl BooleanExprSemiring {
+    /// interns `node`, whose children are `operands`
+    fn mk(node: ExprNode, operands: &[BooleanExprSemiring]) -> BooleanExprSemiring {
+        ARENA.with(|a| {
+            let mut a = a.borrow_mut();
+            assert!(
+                operands.iter().all(|o| o.generation == a.generation),
+                "BooleanExprSemiring used after reset_arena"
+            );
+            BooleanExprSemiring {
+                index: a.intern(node),
+                generation: a.generation,
+            }
+        })
+    }
+
+    fn constant(index: usize) -> BooleanExprSemiring {
+        BooleanExprSemiring {
+            index,
+            generation: ARENA.with(|a| a.borrow().generation),
+        }
+    }
+
+    /// the handle of a node stored in the same arena as `self`
+    fn child(&self, index: usize) -> BooleanExprSemiring {
+        BooleanExprSemiring {
+            index,
+            generation: self.generation,
+        }
+    }
+
+    fn node(&self) -> ExprNode {
+        ARENA.with(|a| {
+            let a = a.borrow();
+            assert_eq!(
+                self.generation, a.generation,
+                "BooleanExprSemiring used after reset_arena"
+            );
+            a.nodes[self.index]
+        })
+    }
+
+    /// Frees every expression built on this thread. Expressions created
+    /// before the call must not be used afterwards; doing so panics.
+    pub fn reset_arena() {
+        ARENA.with(|a| {
+            let generation = a.borrow().generation + 1;
+            *a.borrow_mut() = ExprArena::new(generation);
+        })
+    }
+
+    /// the number of expression nodes currently stored on this thread,
+    /// including the two constants
+    pub fn arena_size() -> usize {
+        ARENA.with(|a| a.borrow().nodes.len())
+    }
+
+    /// the expression consisting of a single literal
+    pub fn lit(var: usize, polarity: bool) -> BooleanExprSemiring {
+        BooleanExprSemiring::mk(ExprNode::Lit(var, polarity), &[])
+    }
+
+    /// evaluates the expression under a total assignment, indexed by variable
+    pub fn eval(&self, assignment: &[bool]) -> bool {
+        match self.node() {
+            ExprNode::False => false,
+            ExprNode::True => true,
+            ExprNode::Lit(v, polarity) => assignment[v] == polarity,
+            ExprNode::And(l, r) => self.child(l).eval(assignment) && self.child(r).eval(assignment),
+            ExprNode::Or(l, r) => self.child(l).eval(assignment) || self.child(r).eval(assignment),
+        }
+    }
+}
+
+impl Semiring for BooleanExprSemiring {
+    fn one() -> Self {
+        BooleanExprSemiring::constant(1)
+    }
+
+    fn zero() -> Self {
+        BooleanExprSemiring::constant(0)
+    }
+}
+
+impl Display for BooleanExprSemiring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.node() {
+            ExprNode::False => write!(f, "F"),
+            ExprNode::True => write!(f, "T"),
+            ExprNode::Lit(v, true) => write!(f, "{}", v),
+            ExprNode::Lit(v, false) => write!(f, "!{}", v),
+            ExprNode::And(l, r) => write!(f, "({} & {})", self.child(l), self.child(r)),
+            ExprNode::Or(l, r) => write!(f, "({} | {})", self.child(l), self.child(r)),
+        }
+    }
+}
+
+impl ops::Add<BooleanExprSemiring> for BooleanExprSemiring {
+    type Output = BooleanExprSemiring;
+
+    fn add(self, rhs: BooleanExprSemiring) -> Self::Output {
+        if self == Self::zero() || rhs == Self::one() {
+            rhs
+        } else if rhs == Self::zero() || self == Self::one() || self == rhs {
+            self
+        } else {
+            BooleanExprSemiring::mk(ExprNode::Or(self.index, rhs.index), &[self, rhs])
+        }
+    }
+}
+
+impl ops::Mul<BooleanExprSemiring> for BooleanExprSemiring {
+    type Output = BooleanExprSemiring;
+
+    fn mul(self, rhs: BooleanExprSemiring) -> Self::Output {
+        if self == Self::one() || rhs == Self::zero() {
+            rhs
+        } else if rhs == Self::one() || self == Self::zero() || self == rhs {
+            self
+        } else {
+            BooleanExprSemiring::mk(ExprNode::And(self.index, rhs.index), &[self, rhs])
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Complex numbers, for weighted model counting with complex amplitudes.
+
+use super::semiring_traits::*;
+use std::{fmt::Display, hash::Hasher, ops};
+
+/// A complex number `re + im i` under complex addition and multiplication.
+/// With complex literal weights, a weighted model count sums the amplitude
+/// (the product of literal weights) of each satisfying assignment, as in
+/// quantum-inspired counting where amplitudes can interfere.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComplexSemiring {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl ComplexSemiring {
+    pub fn new(re: f64, im: f64) -> ComplexSemiring {
+        ComplexSemiring { re, im }
+    }
+
+    /// the complex conjugate `re - im i`
+    pub fn conj(&self) -> ComplexSemiring {
+        ComplexSemiring::new(self.re, -self.im)
+    }
+
+    /// the squared magnitude `re^2 + im^2`
+    pub fn norm_sqr(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+}
+
+impl Display for ComplexSemiring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.im < 0.0 {
+            write!(f, "{} - {}i", self.re, -self.im)
+        } else {
+            write!(f, "{} + {}i", self.re, self.im)
+        }
+    }
+}
+
+impl ops::Add<ComplexSemiring> for ComplexSemiring {
+    type Output = ComplexSemiring;
+
+    fn add(self, rhs: ComplexSemiring) -> Self::Output {
+        ComplexSemiring::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl ops::Mul<ComplexSemiring> for ComplexSemiring {
+    type Output = ComplexSemiring;
+
+    fn mul(self, rhs: ComplexSemiring) -> Self::Output {
+        ComplexSemiring::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl ops::Sub<ComplexSemiring> for ComplexSemiring {
+    type Output = ComplexSemiring;
+
+    fn sub(self, rhs: ComplexSemiring) -> Self::Output {
+        ComplexSemiring::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Semiring for ComplexSemiring {
+    fn one() -> Self {
+        ComplexSemiring::new(1.0, 0.0)
+    }
+
+    fn zero() -> Self {
+        ComplexSemiring::new(0.0, 0.0)
+    }
+}
+
+impl Ring for ComplexSemiring {}
+
+impl StableHash for ComplexSemiring {
+    fn stable_hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.re.to_bits());
+        state.write_u64(self.im.to_bits());
+    }
+}
@@ -0,0 +1,95 @@
+//! Dual numbers for forward-mode automatic differentiation through weighted
+//! model counting. A `DualNumber<N>` carries a value together with its partial
+//! derivatives with respect to `N` parameters.
+
+use super::semiring_traits::*;
+use std::{fmt::Display, ops};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DualNumber<const N: usize> {
+    pub real: f64,
+    pub deriv: [f64; N],
+}
+
+impl<const N: usize> DualNumber<N> {
+    /// a constant: a value whose partial derivatives are all zero
+    pub fn constant(real: f64) -> DualNumber<N> {
+        DualNumber {
+            real,
+            deriv: [0.0; N],
+        }
+    }
+
+    /// a value whose derivative with respect to parameter `idx` is 1
+    /// and all other partial derivatives are zero
+    pub fn param(real: f64, idx: usize) -> DualNumber<N> {
+        assert!(idx < N, "partial index {} out of range (N = {})", idx, N);
+        let mut deriv = [0.0; N];
+        deriv[idx] = 1.0;
+        DualNumber { real, deriv }
+    }
+}
+
+impl<const N: usize> Display for DualNumber<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {:?})", self.real, self.deriv)
+    }
+}
+
+impl<const N: usize> ops::Add<DualNumber<N>> for DualNumber<N> {
+    type Output = DualNumber<N>;
+
+    fn add(self, rhs: DualNumber<N>) -> Self::Output {
+        let mut deriv = self.deriv;
+        for (d, r) in deriv.iter_mut().zip(rhs.deriv.iter()) {
+            *d += r;
+        }
+        DualNumber {
+            real: self.real + rhs.real,
+            deriv,
+        }
+    }
+}
+
+impl<const N: usize> ops::Sub<DualNumber<N>> for DualNumber<N> {
+    type Output = DualNumber<N>;
+
+    fn sub(self, rhs: DualNumber<N>) -> Self::Output {
+        let mut deriv = self.deriv;
+        for (d, r) in deriv.iter_mut().zip(rhs.deriv.iter()) {
+            *d -= r;
+        }
+        DualNumber {
+            real: self.real - rhs.real,
+            deriv,
+        }
+    }
+}
+
+impl<const N: usize> ops::Mul<DualNumber<N>> for DualNumber<N> {
+    type Output = DualNumber<N>;
+
+    /// product rule: (a, a')(b, b') = (ab, a'b + ab')
+    fn mul(self, rhs: DualNumber<N>) -> Self::Output {
+        let mut deriv = [0.0; N];
+        for (i, d) in deriv.iter_mut().enumerate() {
+            *d = self.deriv[i] * rhs.real + self.real * rhs.deriv[i];
+        }
+        DualNumber {
+            real: self.real * rhs.real,
+            deriv,
+        }
+    }
+}
+
+impl<const N: usize> Semiring for DualNumber<N> {
+    fn one() -> Self {
+        DualNumber::constant(1.0)
+    }
+
+    fn zero() -> Self {
+        DualNumber::constant(0.0)
+    }
+}
+
+impl<const N: usize> Ring for DualNumber<N> {}
@@ -0,0 +1,89 @@
+//! A finite-field semiring whose modulus is chosen at runtime.
+
+use super::semiring_traits::*;
+use std::{fmt::Display, hash::Hasher, ops};
+
+/// Integers modulo `modulus`, where the modulus is carried by each value
+/// rather than fixed at compile time as in `FiniteField`. This allows
+/// counting under several moduli picked at runtime, e.g. to reconstruct a
+/// large count via the Chinese remainder theorem.
+///
+/// `Semiring::zero()` and `Semiring::one()` cannot know the modulus, so they
+/// carry modulus 0, which stands for "not yet known": combining such a value
+/// with one that has a modulus adopts that modulus. Combining two values with
+/// different nonzero moduli panics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DynFiniteField {
+    pub val: u64,
+    pub modulus: u64,
+}
+
+impl DynFiniteField {
+    pub fn new(val: u64, modulus: u64) -> DynFiniteField {
+        assert!(modulus > 0, "modulus must be positive");
+        DynFiniteField {
+            val: val % modulus,
+            modulus,
+        }
+    }
+
+    fn common_modulus(&self, other: &DynFiniteField) -> u64 {
+        match (self.modulus, other.modulus) {
+            (0, m) | (m, 0) => m,
+            (m1, m2) => {
+                assert_eq!(m1, m2, "combining values with different moduli");
+                m1
+            }
+        }
+    }
+
+    fn reduce(v: u128, modulus: u64) -> DynFiniteField {
+        let val = if modulus == 0 {
+            v as u64
+        } else {
+            (v % modulus as u128) as u64
+        };
+        DynFiniteField { val, modulus }
+    }
+}
+
+impl Semiring for DynFiniteField {
+    fn one() -> Self {
+        DynFiniteField { val: 1, modulus: 0 }
+    }
+
+    fn zero() -> Self {
+        DynFiniteField { val: 0, modulus: 0 }
+    }
+}
+
+impl Display for DynFiniteField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (mod {})", self.val, self.modulus)
+    }
+}
+
+impl ops::Add<DynFiniteField> for DynFiniteField {
+    type Output = DynFiniteField;
+
+    fn add(self, rhs: DynFiniteField) -> Self::Output {
+        let m = self.common_modulus(&rhs);
+        DynFiniteField::reduce(self.val as u128 + rhs.val as u128, m)
+    }
+}
+
+impl ops::Mul<DynFiniteField> for DynFiniteField {
+    type Output = DynFiniteField;
+
+    fn mul(self, rhs: DynFiniteField) -> Self::Output {
+        let m = self.common_modulus(&rhs);
+        DynFiniteField::reduce(self.val as u128 * rhs.val as u128, m)
+    }
+}
+
+impl StableHash for DynFiniteField {
+    fn stable_hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.val);
+        state.write_u64(self.modulus);
+    }
+}
@@ -1,7 +1,7 @@
 // Expected Utility Semiring.
 
 use super::semiring_traits::*;
-use std::{cmp::Ordering, fmt::Display, ops};
+use std::{cmp::Ordering, fmt::Display, hash::Hasher, ops};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ExpectedUtility(pub f64, pub f64);
@@ -97,3 +97,10 @@ impl MeetSemilattice for ExpectedUtility {
 impl Lattice for ExpectedUtility {}
 
 impl EdgeboundingRing for ExpectedUtility {}
+
+impl StableHash for ExpectedUtility {
+    fn stable_hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.0.to_bits());
+        state.write_u64(self.1.to_bits());
+    }
+}
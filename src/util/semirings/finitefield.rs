@@ -2,7 +2,7 @@ use super::semiring_traits::*;
 use core::fmt::Debug;
 /// Simple real-number semiring abstraction (all operations standard for reals, abstracted as f64)
 /// a finite-field abstraction. The parameter `p` is the size of the field.
-use std::{fmt::Display, ops};
+use std::{fmt::Display, hash::Hasher, ops};
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct FiniteField<const P: u128> {
@@ -74,3 +74,9 @@ impl<const P: u128> Debug for FiniteField<P> {
         write!(f, "FiniteField({})", self.v)
     }
 }
+
+impl<const P: u128> StableHash for FiniteField<P> {
+    fn stable_hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u128(self.v);
+    }
+}
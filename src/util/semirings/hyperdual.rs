@@ -0,0 +1,102 @@
+//! Hyper-dual numbers for second-order forward-mode automatic differentiation
+//! through weighted model counting. A `HyperDual<N>` carries a value, its
+//! gradient with respect to `N` parameters, and its `N x N` Hessian.
+
+use super::semiring_traits::*;
+use std::{fmt::Display, ops};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HyperDual<const N: usize> {
+    pub real: f64,
+    pub grad: [f64; N],
+    pub hessian: [[f64; N]; N],
+}
+
+impl<const N: usize> HyperDual<N> {
+    /// a constant: a value whose derivatives are all zero
+    pub fn constant(real: f64) -> HyperDual<N> {
+        HyperDual {
+            real,
+            grad: [0.0; N],
+            hessian: [[0.0; N]; N],
+        }
+    }
+
+    /// the parameter at index `idx`, taking value `real`
+    pub fn param(real: f64, idx: usize) -> HyperDual<N> {
+        assert!(idx < N, "partial index {} out of range (N = {})", idx, N);
+        let mut r = HyperDual::constant(real);
+        r.grad[idx] = 1.0;
+        r
+    }
+}
+
+impl<const N: usize> Display for HyperDual<N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {:?}, {:?})", self.real, self.grad, self.hessian)
+    }
+}
+
+impl<const N: usize> ops::Add<HyperDual<N>> for HyperDual<N> {
+    type Output = HyperDual<N>;
+
+    fn add(self, rhs: HyperDual<N>) -> Self::Output {
+        let mut r = self;
+        r.real += rhs.real;
+        for i in 0..N {
+            r.grad[i] += rhs.grad[i];
+            for j in 0..N {
+                r.hessian[i][j] += rhs.hessian[i][j];
+            }
+        }
+        r
+    }
+}
+
+impl<const N: usize> ops::Sub<HyperDual<N>> for HyperDual<N> {
+    type Output = HyperDual<N>;
+
+    fn sub(self, rhs: HyperDual<N>) -> Self::Output {
+        let mut r = self;
+        r.real -= rhs.real;
+        for i in 0..N {
+            r.grad[i] -= rhs.grad[i];
+            for j in 0..N {
+                r.hessian[i][j] -= rhs.hessian[i][j];
+            }
+        }
+        r
+    }
+}
+
+impl<const N: usize> ops::Mul<HyperDual<N>> for HyperDual<N> {
+    type Output = HyperDual<N>;
+
+    /// second-order product rule:
+    /// (ab)_ij = a_ij b + a_i b_j + a_j b_i + a b_ij
+    fn mul(self, rhs: HyperDual<N>) -> Self::Output {
+        let mut r = HyperDual::constant(self.real * rhs.real);
+        for i in 0..N {
+            r.grad[i] = self.grad[i] * rhs.real + self.real * rhs.grad[i];
+            for j in 0..N {
+                r.hessian[i][j] = self.hessian[i][j] * rhs.real
+                    + self.grad[i] * rhs.grad[j]
+                    + self.grad[j] * rhs.grad[i]
+                    + self.real * rhs.hessian[i][j];
+            }
+        }
+        r
+    }
+}
+
+impl<const N: usize> Semiring for HyperDual<N> {
+    fn one() -> Self {
+        HyperDual::constant(1.0)
+    }
+
+    fn zero() -> Self {
+        HyperDual::constant(0.0)
+    }
+}
+
+impl<const N: usize> Ring for HyperDual<N> {}
@@ -0,0 +1,65 @@
+//! Interval semiring over non-negative reals, used to propagate lower and
+//! upper bounds on weights through weighted model counting.
+
+use super::semiring_traits::*;
+use std::{fmt::Display, hash::Hasher, ops};
+
+/// A closed interval `[lo, hi]` of non-negative reals. Multiplication assumes
+/// both bounds are non-negative, so the product of two intervals is simply the
+/// product of their endpoints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntervalSemiring(pub f64, pub f64);
+
+impl IntervalSemiring {
+    pub fn lo(&self) -> f64 {
+        self.0
+    }
+
+    pub fn hi(&self) -> f64 {
+        self.1
+    }
+
+    /// true if `v` lies within this interval
+    pub fn contains(&self, v: f64) -> bool {
+        self.0 <= v && v <= self.1
+    }
+}
+
+impl Display for IntervalSemiring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}, {}]", self.0, self.1)
+    }
+}
+
+impl ops::Add<IntervalSemiring> for IntervalSemiring {
+    type Output = IntervalSemiring;
+
+    fn add(self, rhs: IntervalSemiring) -> Self::Output {
+        IntervalSemiring(self.0 + rhs.0, self.1 + rhs.1)
+    }
+}
+
+impl ops::Mul<IntervalSemiring> for IntervalSemiring {
+    type Output = IntervalSemiring;
+
+    fn mul(self, rhs: IntervalSemiring) -> Self::Output {
+        IntervalSemiring(self.0 * rhs.0, self.1 * rhs.1)
+    }
+}
+
+impl Semiring for IntervalSemiring {
+    fn one() -> Self {
+        IntervalSemiring(1.0, 1.0)
+    }
+
+    fn zero() -> Self {
+        IntervalSemiring(0.0, 0.0)
+    }
+}
+
+impl StableHash for IntervalSemiring {
+    fn stable_hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.0.to_bits());
+        state.write_u64(self.1.to_bits());
+    }
+}
@@ -1,15 +1,29 @@
 mod boolean;
+mod boolean_expr;
+mod complex;
+mod dual_number;
+mod dyn_finitefield;
 mod expectation;
 mod finitefield;
+mod hyperdual;
+mod interval;
 mod rational;
 mod realsemiring;
 mod semiring_traits;
 mod tropical;
+mod viterbi;
 
 pub use self::boolean::*;
+pub use self::boolean_expr::*;
+pub use self::complex::*;
+pub use self::dual_number::*;
+pub use self::dyn_finitefield::*;
 pub use self::expectation::*;
 pub use self::finitefield::*;
+pub use self::hyperdual::*;
+pub use self::interval::*;
 pub use self::rational::*;
 pub use self::realsemiring::*;
 pub use self::semiring_traits::*;
 pub use self::tropical::*;
+pub use self::viterbi::*;
@@ -1,11 +1,38 @@
 use rational::Rational;
-use std::{fmt::Display, ops};
+use std::{fmt::Display, hash::Hasher, ops};
 
-use super::semiring_traits::Semiring;
+use super::semiring_traits::{Semiring, StableHash};
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct RationalSemiring(Rational);
 
+impl RationalSemiring {
+    /// the fraction `numerator / denominator`, in lowest terms
+    pub fn new(numerator: i128, denominator: i128) -> RationalSemiring {
+        RationalSemiring(Rational::new(numerator, denominator))
+    }
+
+    pub fn numerator(&self) -> i128 {
+        self.0.numerator()
+    }
+
+    pub fn denominator(&self) -> i128 {
+        self.0.denominator()
+    }
+
+    /// `self + rhs`, or `None` if the result (in lowest terms) does not fit
+    /// in `i128`s
+    pub fn checked_add(self, rhs: RationalSemiring) -> Option<RationalSemiring> {
+        self.0.checked_add(rhs.0).map(RationalSemiring)
+    }
+
+    /// `self * rhs`, or `None` if the result (in lowest terms) does not fit
+    /// in `i128`s
+    pub fn checked_mul(self, rhs: RationalSemiring) -> Option<RationalSemiring> {
+        self.0.checked_mul(rhs.0).map(RationalSemiring)
+    }
+}
+
 impl Semiring for RationalSemiring {
     fn one() -> Self {
         RationalSemiring(Rational::new(1, 1))
@@ -37,3 +64,11 @@ impl ops::Mul<RationalSemiring> for RationalSemiring {
         RationalSemiring(self.0 * rhs.0)
     }
 }
+
+impl StableHash for RationalSemiring {
+    fn stable_hash<H: Hasher>(&self, state: &mut H) {
+        // always in lowest terms, so equal values have equal parts
+        state.write_i128(self.numerator());
+        state.write_i128(self.denominator());
+    }
+}
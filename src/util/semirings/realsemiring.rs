@@ -1,5 +1,5 @@
 use super::semiring_traits::*;
-use std::{fmt::Display, ops};
+use std::{fmt::Display, hash::Hasher, ops};
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
 pub struct RealSemiring(pub f64);
@@ -73,3 +73,9 @@ impl MeetSemilattice for RealSemiring {
 impl Lattice for RealSemiring {}
 
 impl EdgeboundingRing for RealSemiring {}
+
+impl StableHash for RealSemiring {
+    fn stable_hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.0.to_bits());
+    }
+}
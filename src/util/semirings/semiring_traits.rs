@@ -6,6 +6,7 @@
 //! Compared with a ring, a semiring omits an inverse for addition
 //!
 use std::fmt::{Debug, Display};
+use std::hash::Hasher;
 use std::ops;
 
 pub trait Semiring:
@@ -15,6 +16,17 @@ pub trait Semiring:
     fn zero() -> Self;
 }
 
+/// Feeds a semiring value into a hasher in a form that is the same across
+/// runs and platforms, for keying caches on weights; the hash is only as
+/// portable as the hasher, e.g. `FxHasher` depends on the word size, while
+/// `WmcParams::stable_hash` uses one that does not. Float-valued semirings
+/// feed the bit pattern of each float, so values that compare equal but have
+/// different bits (`0.0` and `-0.0`) hash differently, and NaNs with equal
+/// bits hash equally.
+pub trait StableHash {
+    fn stable_hash<H: Hasher>(&self, state: &mut H);
+}
+
 // A ring is a semiring with additive inverses, which is equivalent to a notion of subtraction.
 pub trait Ring: Semiring + ops::Sub<Self, Output = Self> {}
 
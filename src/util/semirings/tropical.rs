@@ -1,4 +1,9 @@
-use std::{fmt::Debug, ops};
+use super::semiring_traits::{Semiring, StableHash};
+use std::{
+    fmt::{Debug, Display},
+    hash::Hasher,
+    ops,
+};
 
 pub trait TropicalSemiring: Debug + Clone + Copy + ops::Add + ops::Mul {
     fn one() -> Self;
@@ -6,3 +11,68 @@ pub trait TropicalSemiring: Debug + Clone + Copy + ops::Add + ops::Mul {
     fn max(&self, other: &Self) -> Self;
     fn min(&self, other: &Self) -> Self;
 }
+
+/// The max-plus (tropical) semiring over the reals extended with negative
+/// infinity: addition is `max` and multiplication is ordinary addition.
+/// Weighted model counting in this semiring computes the largest total
+/// additive weight of a single model (given a smoothed circuit).
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Tropical(pub f64);
+
+impl Display for Tropical {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl ops::Add<Tropical> for Tropical {
+    type Output = Tropical;
+
+    fn add(self, rhs: Tropical) -> Self::Output {
+        Tropical(f64::max(self.0, rhs.0))
+    }
+}
+
+impl ops::Mul<Tropical> for Tropical {
+    type Output = Tropical;
+
+    // tropical multiplication is real addition
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn mul(self, rhs: Tropical) -> Self::Output {
+        Tropical(self.0 + rhs.0)
+    }
+}
+
+impl Semiring for Tropical {
+    fn one() -> Self {
+        Tropical(0.0)
+    }
+
+    fn zero() -> Self {
+        Tropical(f64::NEG_INFINITY)
+    }
+}
+
+impl TropicalSemiring for Tropical {
+    fn one() -> Self {
+        Tropical(0.0)
+    }
+
+    fn zero() -> Self {
+        Tropical(f64::NEG_INFINITY)
+    }
+
+    fn max(&self, other: &Self) -> Self {
+        Tropical(f64::max(self.0, other.0))
+    }
+
+    fn min(&self, other: &Self) -> Self {
+        Tropical(f64::min(self.0, other.0))
+    }
+}
+
+impl StableHash for Tropical {
+    fn stable_hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.0.to_bits());
+    }
+}
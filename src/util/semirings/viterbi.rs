@@ -0,0 +1,48 @@
+//! The Viterbi (max-product) semiring over non-negative reals.
+
+use super::semiring_traits::*;
+use std::{fmt::Display, hash::Hasher, ops};
+
+/// A semiring whose addition is `max` and whose multiplication is ordinary
+/// multiplication. Weighted model counting in this semiring computes the
+/// weight of the single highest-weight model (given a smoothed circuit).
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct ViterbiSemiring(pub f64);
+
+impl Display for ViterbiSemiring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl ops::Add<ViterbiSemiring> for ViterbiSemiring {
+    type Output = ViterbiSemiring;
+
+    fn add(self, rhs: ViterbiSemiring) -> Self::Output {
+        ViterbiSemiring(f64::max(self.0, rhs.0))
+    }
+}
+
+impl ops::Mul<ViterbiSemiring> for ViterbiSemiring {
+    type Output = ViterbiSemiring;
+
+    fn mul(self, rhs: ViterbiSemiring) -> Self::Output {
+        ViterbiSemiring(self.0 * rhs.0)
+    }
+}
+
+impl Semiring for ViterbiSemiring {
+    fn one() -> Self {
+        ViterbiSemiring(1.0)
+    }
+
+    fn zero() -> Self {
+        ViterbiSemiring(0.0)
+    }
+}
+
+impl StableHash for ViterbiSemiring {
+    fn stable_hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.0.to_bits());
+    }
+}
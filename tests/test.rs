@@ -283,6 +283,7 @@ mod test_bdd_builder {
     use rsdd::repr::BddPtr;
     use rsdd::repr::Cnf;
     use rsdd::repr::DTree;
+    use rsdd::repr::Literal;
     use rsdd::repr::PartialModel;
     use rsdd::repr::VTree;
     use rsdd::repr::VarLabel;
@@ -396,6 +397,97 @@ mod test_bdd_builder {
         }
     }
 
+    quickcheck! {
+        /// test that restricting by a cube agrees with conditioning one variable at a time
+        fn restrict_eq_condition_model(c1: Cnf, assgn: Vec<Option<bool>>) -> TestResult {
+            if c1.num_vars() == 0 || c1.num_vars() > 8 { return TestResult::discard() }
+            let builder = super::RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(c1.num_vars());
+            let assgn: Vec<Option<bool>> = assgn.into_iter().take(c1.num_vars()).collect();
+            let pm = PartialModel::from_assignments(&assgn);
+            let bdd = builder.compile_cnf(&c1);
+            let restricted = builder.restrict(bdd, &pm);
+            let conditioned = builder.condition_model(bdd, &pm);
+            TestResult::from_bool(restricted == conditioned)
+        }
+    }
+
+    quickcheck! {
+        /// test that find_model returns a model of the CNF, whatever values the
+        /// don't-care variables take, and None exactly when it is unsatisfiable
+        fn find_model_satisfies(c: Cnf) -> TestResult {
+            if c.num_vars() == 0 || c.num_vars() > 16 { return TestResult::discard() }
+            let builder = super::RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(c.num_vars());
+            let bdd = builder.compile_cnf(&c);
+            match builder.find_model(bdd) {
+                None => TestResult::from_bool(bdd.is_false()),
+                Some(m) => TestResult::from_bool([false, true].iter().all(|fill| {
+                    let assgn: Vec<bool> = (0..c.num_vars())
+                        .map(|v| m.get(VarLabel::new_usize(v)).unwrap_or(*fill))
+                        .collect();
+                    bdd.evaluate(&assgn)
+                })),
+            }
+        }
+    }
+
+    quickcheck! {
+        /// test that the parallel balanced conjunction of each clause agrees with
+        /// a sequential fold, under a non-linear variable order
+        fn and_many_parallel_eq_fold(c: Cnf) -> TestResult {
+            if c.num_vars() == 0 || c.num_vars() > 12 { return TestResult::discard() }
+            let order: Vec<VarLabel> = (0..c.num_vars()).rev().map(VarLabel::new_usize).collect();
+            let builder = super::RobddBuilder::<AllIteTable<BddPtr>>::new(VarOrder::new(&order), None);
+            let clauses: Vec<BddPtr> = c.clauses().iter().map(|clause| builder.conjoin_clause(BddPtr::PtrTrue, clause)).collect();
+            let seq = clauses.iter().fold(BddPtr::PtrTrue, |acc, f| builder.and(acc, *f));
+            TestResult::from_bool(builder.and_many_parallel(clauses) == seq)
+        }
+    }
+
+    quickcheck! {
+        /// test that conjoining a clause onto a compiled CNF agrees with compiling
+        /// the CNF extended by that clause
+        fn conjoin_clause_eq_compile(c1: Cnf, c2: Cnf) -> TestResult {
+            if c1.num_vars() == 0 || c1.num_vars() > 8 { return TestResult::discard() }
+            let clause: Vec<Literal> = match c2.clauses().first() {
+                Some(clause) => clause.iter().filter(|l| l.label().value_usize() < c1.num_vars()).copied().collect(),
+                None => return TestResult::discard(),
+            };
+            if clause.is_empty() { return TestResult::discard() }
+            let builder = super::RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(c1.num_vars());
+            let mut clauses = c1.clauses().to_vec();
+            clauses.push(clause.clone());
+            let both = builder.compile_cnf(&Cnf::new(&clauses));
+            let conjoined = builder.conjoin_clause(builder.compile_cnf(&c1), &clause);
+            TestResult::from_bool(both == conjoined)
+        }
+    }
+
+    quickcheck! {
+        /// test that random-assignment equivalence agrees with exact equality on
+        /// small inputs, where enough trials cover every assignment
+        fn prob_equiv_eq(c1: Cnf, c2: Cnf) -> TestResult {
+            let n = std::cmp::max(c1.num_vars(), c2.num_vars());
+            if n == 0 || n > 4 { return TestResult::discard() }
+            let builder = super::RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(n);
+            let a = builder.compile_cnf(&c1);
+            let b = builder.compile_cnf(&c2);
+            TestResult::from_bool(builder.prob_equiv(a, b, 500) == (a == b))
+        }
+    }
+
+    quickcheck! {
+        /// test that the exact model count agrees with brute-force enumeration
+        fn model_count_eq(c1: Cnf) -> TestResult {
+            if c1.num_vars() == 0 || c1.num_vars() > 8 { return TestResult::discard() }
+            let builder = super::RobddBuilder::<AllIteTable<BddPtr>>::new_with_linear_order(c1.num_vars());
+            let bdd = builder.compile_cnf(&c1);
+            let weights = WmcParams::new(HashMap::from_iter((0..c1.num_vars()).map(|v| {
+                (VarLabel::new_usize(v), (FiniteField::<{primes::U32_SMALL}>::one(), FiniteField::one()))
+            })));
+            TestResult::from_bool(builder.model_count(bdd) == c1.wmc(&weights).value())
+        }
+    }
+
     quickcheck! {
         /// test that an BDD and CNF compute identical WMC
         fn wmc_eq(c1: Cnf) -> TestResult {